@@ -3,10 +3,11 @@ use crate::{
     audio_player::AudioOutput,
     player_state::PlayerState,
     preference::Preference,
+    simulation::Simulation,
     state::{FileRegistory, State},
     ui::{
-        UiAudioFiles, UiMenuBar, UiPerformanceWindow, UiPitchVolumeEdit, UiPitchVolumePlots,
-        UiPlayer, UiSettingWindow,
+        UiAudioFiles, UiCarrierScheduleWindow, UiMenuBar, UiPerformanceWindow, UiPitchVolumeEdit,
+        UiPitchVolumePlots, UiPlayer, UiSettingWindow,
     },
 };
 use egui::{
@@ -14,6 +15,11 @@ use egui::{
 };
 use std::path::PathBuf;
 
+/// 選んだ出力デバイスが使えるか確かめる最初の間隔 (秒)
+const DEVICE_PROBE_INTERVAL: f64 = 2.0;
+/// 見つからないたびに倍にする間隔の上限 (秒)
+const DEVICE_PROBE_MAX_INTERVAL: f64 = 120.0;
+
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct MainApp {
     #[serde(skip)]
@@ -27,6 +33,8 @@ pub struct MainApp {
     preference: Preference,
     #[serde(skip)]
     player_state: PlayerState,
+    #[serde(skip)]
+    simulation: Simulation,
     ui_menu_bar: UiMenuBar,
     #[serde(skip)]
     ui_audio_files: UiAudioFiles,
@@ -39,9 +47,15 @@ pub struct MainApp {
     #[serde(skip)]
     ui_performance_window: UiPerformanceWindow,
     #[serde(skip)]
+    ui_carrier_schedule_window: UiCarrierScheduleWindow,
+    #[serde(skip)]
     ui_setting_window: UiSettingWindow,
     #[serde(skip)]
     action: AppAction,
+    #[serde(skip)]
+    next_device_probe: f64,
+    #[serde(skip)]
+    device_probe_interval: f64,
 }
 
 impl Default for MainApp {
@@ -57,14 +71,18 @@ impl Default for MainApp {
             state_file_path: None,
             preference: Preference::default(),
             player_state: PlayerState::default(),
+            simulation: Simulation::default(),
             ui_menu_bar: UiMenuBar::default(),
             ui_audio_files: UiAudioFiles,
-            ui_point_edit: UiPitchVolumeEdit,
+            ui_point_edit: UiPitchVolumeEdit::default(),
             ui_pitch_volume_plots: UiPitchVolumePlots::default(),
             ui_player: UiPlayer,
             ui_performance_window: UiPerformanceWindow::default(),
-            ui_setting_window: UiSettingWindow,
+            ui_carrier_schedule_window: UiCarrierScheduleWindow::default(),
+            ui_setting_window: UiSettingWindow::default(),
             action: AppAction::default(),
+            next_device_probe: DEVICE_PROBE_INTERVAL,
+            device_probe_interval: DEVICE_PROBE_INTERVAL,
         }
     }
 }
@@ -107,6 +125,7 @@ impl MainApp {
 
     fn init(&mut self) {
         self.player_state.check(&self.state.train_performance);
+        self.init_audio_output();
         if let Some(path) = self.state_file_path.as_ref().and_then(|p| p.saved_path()) {
             if let Err(err) =
                 crate::save_load::load_file(path, &mut self.registory, &mut self.state)
@@ -114,9 +133,41 @@ impl MainApp {
                 self.action.add_error_modal(err);
             }
         }
-        self.registory
-            .play_audio(&mut self.audio_output)
-            .expect("Failed to play audio.");
+    }
+
+    fn init_audio_output(&mut self) {
+        self.action.recover_audio_output(
+            &mut self.registory,
+            &mut self.audio_output,
+            &self.preference,
+        );
+        if let Some(params) = self.registory.take_param_sink() {
+            self.simulation.set_param_sink(params);
+        }
+    }
+
+    fn check_audio_output(&mut self, ctx: &egui::Context) {
+        // デバイスが外れたら作り直し、選んだデバイスが戻れば切り替える
+        if self.audio_output.has_failed() {
+            self.action.recover_audio_device();
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        if self.action.is_device_probe_stopped() || now < self.next_device_probe {
+            return;
+        }
+        // デバイスの列挙や開くのは遅いことがあるので、見つからない間は間隔を延ばす
+        if self
+            .audio_output
+            .is_preferred(&self.preference.audio_device)
+        {
+            self.device_probe_interval = DEVICE_PROBE_INTERVAL;
+        } else {
+            self.action.probe_audio_device();
+            self.device_probe_interval =
+                (self.device_probe_interval * 2.0).min(DEVICE_PROBE_MAX_INTERVAL);
+        }
+        self.next_device_probe = now + self.device_probe_interval;
     }
 
     fn ui(&mut self, ctx: &egui::Context, frame: &eframe::Frame) {
@@ -162,11 +213,15 @@ impl MainApp {
                 .show(ctx, |ui| {
                     ScrollArea::vertical().show(ui, |ui| {
                         ui.style_mut().spacing.item_spacing = vec2(8.0, 8.0);
+                        let context = self.state.expr_context(self.player_state.sound_type);
                         self.ui_point_edit.ui(
                             ui,
                             &mut self.action,
+                            &self.registory,
                             &mut self.state.audio_entries,
                             &mut self.state.selection,
+                            self.simulation.pitch_warnings(),
+                            &context,
                         );
                     });
                 });
@@ -175,8 +230,12 @@ impl MainApp {
         TopBottomPanel::bottom("train_speed_control")
             .exact_height(80.0)
             .show(ctx, |ui| {
-                self.ui_player
-                    .ui(ui, &mut self.preference, &mut self.player_state);
+                self.ui_player.ui(
+                    ui,
+                    &mut self.preference,
+                    &mut self.player_state,
+                    &self.state.drive_train,
+                );
             });
 
         CentralPanel::default()
@@ -196,12 +255,21 @@ impl MainApp {
             ctx,
             &mut self.ui_menu_bar.show_performance_window,
             &mut self.action,
-            &mut self.state.train_performance,
+            &mut self.state,
+        );
+        self.ui_carrier_schedule_window.show(
+            ctx,
+            &mut self.ui_menu_bar.show_carrier_schedule_window,
+            &mut self.action,
+            &mut self.state,
         );
         self.ui_setting_window.show(
             ctx,
             &mut self.ui_menu_bar.show_setting_window,
+            &mut self.action,
             &mut self.state,
+            &mut self.preference,
+            &self.audio_output,
         );
     }
 }
@@ -217,25 +285,42 @@ impl eframe::App for MainApp {
         self.ui(ctx, frame);
 
         // 毎フレームの更新処理
-        self.state.train_performance.update();
+        self.state.update_expressions();
         self.player_state.update(ctx, &self.state, &self.preference);
-        self.registory.update(
-            &mut self.state,
-            &self.player_state,
-            &self.preference,
-            &mut self.action,
-        );
+        self.registory.update(&mut self.state, &mut self.action);
+        if let Err(err) = self
+            .registory
+            .set_resampler_quality(self.preference.resampler_quality)
+        {
+            self.action.add_error_modal(err);
+        }
+        self.simulation
+            .update_model(&self.state, self.preference.global_volume);
+        self.simulation.sync(&mut self.player_state);
+        #[cfg(target_arch = "wasm32")]
+        self.simulation.poll();
+
+        self.check_audio_output(ctx);
 
         // モーダル表示
         self.action.show_modal(ctx);
 
         // action を実行
         self.action.shortcut(ctx);
+        self.action.exec_audio_output(
+            &mut self.registory,
+            &mut self.audio_output,
+            &mut self.preference,
+        );
+        if let Some(params) = self.registory.take_param_sink() {
+            self.simulation.set_param_sink(params);
+        }
         self.action.exec(
             ctx,
             Some(frame),
             &mut self.registory,
             &mut self.state,
+            &self.preference,
             &mut self.undoer,
             &mut self.state_file_path,
         );
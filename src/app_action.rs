@@ -1,5 +1,7 @@
 use crate::{
     app::StateFilePath,
+    audio_player::AudioOutput,
+    preference::{DeviceSettings, Preference},
     state::{FileRegistory, State},
 };
 use egui::{Id, Key, KeyboardShortcut, Modal, Modifiers, Sides, util::undoer::Undoer};
@@ -11,6 +13,11 @@ pub struct AppAction {
     has_redo: bool,
     flags: AppActionFlags,
     modals: AppModals,
+    /// 使えるデバイスが無いと伝えたので、利用者が出力を選び直すまでデバイスを探さない
+    device_probe_stopped: bool,
+    /// 書き出し中の WAV
+    #[cfg(not(target_arch = "wasm32"))]
+    render_job: Option<crate::offline_render::RenderJob>,
 }
 
 impl AppAction {
@@ -45,12 +52,14 @@ impl AppAction {
         }
     }
 
+    #[expect(clippy::too_many_arguments)]
     pub fn exec<W>(
         &mut self,
         ctx: &egui::Context,
         parent: Option<&W>,
         registory: &mut FileRegistory,
         state: &mut State,
+        preference: &Preference,
         undoer: &mut Undoer<State>,
         state_filepath: &mut Option<StateFilePath>,
     ) where
@@ -92,8 +101,10 @@ impl AppAction {
             );
         }
         if self.flags.new_project_confirmed {
-            registory.clear(state);
-            *state_filepath = None;
+            match registory.clear(state) {
+                Ok(()) => *state_filepath = None,
+                Err(err) => self.add_error_modal(err),
+            }
         }
 
         // エラーになりうるもの
@@ -126,10 +137,96 @@ impl AppAction {
                 self.do_save(path, registory, state, state_filepath);
             }
         }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.flags.render_wav && self.render_job.is_none() {
+            if let Some(path) = crate::file_dialog::save_wav_dialog(parent) {
+                self.render_job = Some(crate::offline_render::RenderJob::start(
+                    path,
+                    registory,
+                    state,
+                    &preference.render,
+                ));
+            }
+        }
 
         self.flags = AppActionFlags::default();
     }
 
+    pub fn exec_audio_output(
+        &mut self,
+        registory: &mut FileRegistory,
+        audio_output: &mut AudioOutput,
+        preference: &mut Preference,
+    ) {
+        if self.flags.use_null_output || self.flags.connect_audio_device {
+            self.device_probe_stopped = false;
+        }
+        if self.flags.use_null_output {
+            preference.audio_device.use_null = true;
+            if let Err(err) =
+                registory.switch_output(audio_output, AudioOutput::null(preference.null_output))
+            {
+                self.add_error_modal(err);
+            }
+        } else if self.flags.connect_audio_device {
+            preference.audio_device.use_null = false;
+            if let Err(err) = AudioOutput::open(&preference.audio_device)
+                .and_then(|output| registory.switch_output(audio_output, output))
+            {
+                self.add_error_modal(err);
+            }
+        } else if self.flags.recover_audio_device {
+            self.recover_audio_output(registory, audio_output, preference);
+        } else if self.flags.probe_audio_device {
+            // 選んだデバイスが戻ってきたら切り替える
+            if let Err(err) = AudioOutput::open(&preference.audio_device)
+                .and_then(|output| registory.switch_output(audio_output, output))
+            {
+                log::debug!("Preferred audio device is not available: {err}");
+            }
+        }
+    }
+
+    /// 設定のデバイス、既定のデバイス、null 出力の順に開けるものへ切り替える
+    pub fn recover_audio_output(
+        &mut self,
+        registory: &mut FileRegistory,
+        audio_output: &mut AudioOutput,
+        preference: &Preference,
+    ) {
+        let settings = &preference.audio_device;
+        let null_output = AudioOutput::null(preference.null_output);
+        if settings.use_null {
+            if let Err(err) = registory.switch_output(audio_output, null_output) {
+                self.add_error_modal(err);
+            }
+            return;
+        }
+
+        let mut result = AudioOutput::open(settings)
+            .and_then(|output| registory.switch_output(audio_output, output));
+        if result.is_err() && settings.name.is_some() {
+            result = AudioOutput::open(&DeviceSettings {
+                name: None,
+                ..settings.clone()
+            })
+            .and_then(|output| registory.switch_output(audio_output, output));
+        }
+        if let Err(err) = result {
+            self.add_warning_modal(format!(
+                "No audio output device is available, falling back to null output: {err}"
+            ));
+            self.device_probe_stopped = true;
+            if let Err(err) = registory.switch_output(audio_output, null_output) {
+                self.add_error_modal(err);
+            }
+        }
+    }
+
+    pub fn is_device_probe_stopped(&self) -> bool {
+        self.device_probe_stopped
+    }
+
     pub fn has_undo(&self) -> bool {
         self.has_undo
     }
@@ -154,6 +251,26 @@ impl AppAction {
         self.flags.save_as = true;
     }
 
+    pub fn render_wav(&mut self) {
+        self.flags.render_wav = true;
+    }
+
+    pub fn connect_audio_device(&mut self) {
+        self.flags.connect_audio_device = true;
+    }
+
+    pub fn use_null_output(&mut self) {
+        self.flags.use_null_output = true;
+    }
+
+    pub fn recover_audio_device(&mut self) {
+        self.flags.recover_audio_device = true;
+    }
+
+    pub fn probe_audio_device(&mut self) {
+        self.flags.probe_audio_device = true;
+    }
+
     pub fn quit(&mut self) {
         self.flags.quit = true;
     }
@@ -174,14 +291,66 @@ impl AppAction {
         self.modals.errors.push(error);
     }
 
+    pub fn add_warning_modal(&mut self, message: String) {
+        self.modals.warnings.push(message);
+    }
+
+    pub fn add_info_modal(&mut self, message: String) {
+        self.modals.infos.push(message);
+    }
+
     fn add_confirmation_modal(&mut self, message: String, flags: AppActionFlags) {
         self.modals.confirmations.push_back((message, flags));
     }
 
     pub fn show_modal(&mut self, ctx: &egui::Context) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.show_render_job(ctx);
         self.modals.show(ctx, &mut self.flags);
     }
 
+    /// 書き出しの進み具合を表示し、終わったら結果を伝える
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_render_job(&mut self, ctx: &egui::Context) {
+        let Some(job) = self.render_job.as_mut() else {
+            return;
+        };
+        if let Some(result) = job.try_finish() {
+            let message = format!("Rendered to {}", job.path().display());
+            let cancelled = job.is_cancelled();
+            self.render_job = None;
+            match result {
+                Ok(()) => self.add_info_modal(message),
+                Err(_) if cancelled => {}
+                Err(err) => self.add_error_modal(err),
+            }
+            return;
+        }
+
+        let progress = job.progress();
+        let modal = Modal::new(Id::new("modal_render")).show(ctx, |ui| {
+            ui.heading("Rendering");
+            ui.add_space(8.0);
+            ui.label(job.path().display().to_string());
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(format!(
+                    "{:.1} s, {:.1} km/h",
+                    progress.seconds, progress.speed
+                ));
+            });
+            ui.add_space(8.0);
+            Sides::new()
+                .show(ui, |_| {}, |ui| ui.button("Cancel").clicked())
+                .1
+        });
+        if modal.inner {
+            job.cancel();
+        }
+        // 書き出しスレッドの進み具合を表示し続ける
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+
     fn do_save(
         &mut self,
         path: PathBuf,
@@ -204,6 +373,8 @@ impl AppAction {
 #[derive(Debug, Default)]
 struct AppModals {
     errors: Vec<anyhow::Error>,
+    warnings: Vec<String>,
+    infos: Vec<String>,
     confirmations: VecDeque<(String, AppActionFlags)>,
 }
 
@@ -224,6 +395,36 @@ impl AppModals {
             if modal.should_close() || modal.inner {
                 self.errors.clear();
             }
+        } else if !self.warnings.is_empty() {
+            let modal = Modal::new(Id::new("modal_warning")).show(ctx, |ui| {
+                ui.heading("\u{26a0} Warning");
+                for warning in &self.warnings {
+                    ui.add_space(8.0);
+                    ui.label(warning);
+                }
+                ui.add_space(8.0);
+                Sides::new()
+                    .show(ui, |_| {}, |ui| ui.button("Close").clicked())
+                    .1
+            });
+            if modal.should_close() || modal.inner {
+                self.warnings.clear();
+            }
+        } else if !self.infos.is_empty() {
+            let modal = Modal::new(Id::new("modal_info")).show(ctx, |ui| {
+                ui.heading("\u{2139} Information");
+                for info in &self.infos {
+                    ui.add_space(8.0);
+                    ui.label(info);
+                }
+                ui.add_space(8.0);
+                Sides::new()
+                    .show(ui, |_| {}, |ui| ui.button("Close").clicked())
+                    .1
+            });
+            if modal.should_close() || modal.inner {
+                self.infos.clear();
+            }
         } else if let Some((message, modal_flags)) = self.confirmations.front() {
             let modal = Modal::new(Id::new("modal_confirmation")).show(ctx, |ui| {
                 ui.heading("\u{2757} Confirmation");
@@ -256,6 +457,11 @@ struct AppActionFlags {
     open: bool,
     save: bool,
     save_as: bool,
+    render_wav: bool,
+    connect_audio_device: bool,
+    use_null_output: bool,
+    recover_audio_device: bool,
+    probe_audio_device: bool,
     quit: bool,
     add_undo: bool,
     undo: bool,
@@ -272,6 +478,11 @@ impl std::ops::BitOr for AppActionFlags {
             open: self.open || rhs.open,
             save: self.save || rhs.save,
             save_as: self.save_as || rhs.save_as,
+            render_wav: self.render_wav || rhs.render_wav,
+            connect_audio_device: self.connect_audio_device || rhs.connect_audio_device,
+            use_null_output: self.use_null_output || rhs.use_null_output,
+            recover_audio_device: self.recover_audio_device || rhs.recover_audio_device,
+            probe_audio_device: self.probe_audio_device || rhs.probe_audio_device,
             quit: self.quit || rhs.quit,
             add_undo: self.add_undo || rhs.add_undo,
             undo: self.undo || rhs.undo,
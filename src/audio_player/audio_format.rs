@@ -0,0 +1,40 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    OggVorbis,
+    Wav,
+    Flac,
+    Mp3,
+}
+
+impl AudioFormat {
+    pub const EXTENSIONS: [&str; 4] = ["ogg", "wav", "flac", "mp3"];
+
+    /// 先頭のバイト列からコンテナ形式を判定する
+    pub fn detect(buf: &[u8]) -> Option<Self> {
+        if buf.starts_with(b"OggS") {
+            Some(Self::OggVorbis)
+        } else if buf.starts_with(b"RIFF") && buf.get(8..12) == Some(b"WAVE") {
+            Some(Self::Wav)
+        } else if buf.starts_with(b"fLaC") {
+            Some(Self::Flac)
+        } else if buf.starts_with(b"ID3")
+            || buf
+                .first_chunk::<2>()
+                .is_some_and(|h| h[0] == 0xFF && h[1] & 0xE0 == 0xE0)
+        {
+            // ID3タグ付き、もしくはフレーム同期ワードから始まるもの
+            Some(Self::Mp3)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::OggVorbis => "ogg",
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::Mp3 => "mp3",
+        }
+    }
+}
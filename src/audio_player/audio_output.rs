@@ -1,75 +1,352 @@
-use anyhow::bail;
+use crate::preference::DeviceSettings;
+use anyhow::{Context as _, bail};
 use cpal::traits::{DeviceTrait as _, HostTrait as _, StreamTrait as _};
-use std::ops::AddAssign;
+use std::{
+    ops::AddAssign,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// コールバックに渡す 1 ブロックの最大フレーム数
+pub const MAX_BLOCK_FRAMES: usize = 1024;
+
+/// 設定画面で選べるサンプルレート
+const COMMON_SAMPLE_RATES: [u32; 6] = [22050, 44100, 48000, 88200, 96000, 192_000];
+
+/// 出力のサンプルレートとチャンネル数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct OutputConfig {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            channels: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDeviceInfo {
+    pub name: String,
+    pub sample_rates: Vec<u32>,
+    /// 指定できるバッファサイズの範囲 (フレーム数)
+    pub buffer_size_range: Option<(u32, u32)>,
+}
+
+enum OutputBackend {
+    Device {
+        device: cpal::Device,
+        config: cpal::SupportedStreamConfig,
+        buffer_size: cpal::BufferSize,
+        stream: Option<cpal::Stream>,
+        /// ストリームのエラー (デバイスの取り外しなど) で立つ
+        failed: Arc<AtomicBool>,
+    },
+    Null {
+        config: OutputConfig,
+        stream: Option<NullStream>,
+    },
+}
 
 pub struct AudioOutput {
-    device: cpal::Device,
-    stream: Option<cpal::Stream>,
+    backend: OutputBackend,
 }
 
 impl Default for AudioOutput {
     fn default() -> Self {
+        Self::null(OutputConfig::default())
+    }
+}
+
+impl AudioOutput {
+    /// 設定に合うデバイスを開く
+    pub fn open(settings: &DeviceSettings) -> anyhow::Result<Self> {
         let host = cpal::default_host();
-        let device = host
+        let device = match &settings.name {
+            Some(name) => host
+                .output_devices()?
+                .find(|d| d.name().is_ok_and(|n| &n == name))
+                .with_context(|| format!("Output device '{name}' was not found."))?,
+            None => host
+                .default_output_device()
+                .context("Failed to find output device.")?,
+        };
+
+        let default_config = device.default_output_config()?;
+        let config = match settings.sample_rate {
+            Some(rate) if rate != default_config.sample_rate().0 => device
+                .supported_output_configs()?
+                .filter(|c| {
+                    c.channels() == default_config.channels()
+                        && c.sample_format() == default_config.sample_format()
+                })
+                .find_map(|c| c.try_with_sample_rate(cpal::SampleRate(rate)))
+                .with_context(|| format!("Sample rate {rate} Hz is not supported."))?,
+            _ => default_config,
+        };
+
+        let buffer_size = match (settings.buffer_size, config.buffer_size()) {
+            (Some(size), cpal::SupportedBufferSize::Range { min, max }) => {
+                cpal::BufferSize::Fixed(size.clamp(*min, *max))
+            }
+            (Some(size), cpal::SupportedBufferSize::Unknown) => cpal::BufferSize::Fixed(size),
+            (None, _) => cpal::BufferSize::Default,
+        };
+
+        Ok(Self {
+            backend: OutputBackend::Device {
+                device,
+                config,
+                buffer_size,
+                stream: None,
+                failed: Arc::new(AtomicBool::new(false)),
+            },
+        })
+    }
+
+    /// 出力デバイスの一覧
+    pub fn devices() -> anyhow::Result<Vec<OutputDeviceInfo>> {
+        let host = cpal::default_host();
+        let mut devices = vec![];
+        for device in host.output_devices()? {
+            let Ok(name) = device.name() else {
+                continue;
+            };
+            let ranges: Vec<_> = device
+                .supported_output_configs()
+                .map(|c| c.collect())
+                .unwrap_or_default();
+            let sample_rates = COMMON_SAMPLE_RATES
+                .into_iter()
+                .filter(|rate| {
+                    ranges.iter().any(|r: &cpal::SupportedStreamConfigRange| {
+                        (r.min_sample_rate().0..=r.max_sample_rate().0).contains(rate)
+                    })
+                })
+                .collect();
+            let buffer_size_range = ranges.iter().find_map(|r| match r.buffer_size() {
+                cpal::SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+                cpal::SupportedBufferSize::Unknown => None,
+            });
+            devices.push(OutputDeviceInfo {
+                name,
+                sample_rates,
+                buffer_size_range,
+            });
+        }
+        Ok(devices)
+    }
+
+    pub fn default_device_name() -> Option<String> {
+        cpal::default_host()
             .default_output_device()
-            .expect("Failed to find output device.");
+            .and_then(|d| d.name().ok())
+    }
 
+    /// ストリームがエラーで止まったか
+    pub fn has_failed(&self) -> bool {
+        match &self.backend {
+            OutputBackend::Device { failed, .. } => failed.load(Ordering::Relaxed),
+            OutputBackend::Null { .. } => false,
+        }
+    }
+
+    /// 設定で選ばれたデバイスを使っているか
+    pub fn is_preferred(&self, settings: &DeviceSettings) -> bool {
+        match &self.backend {
+            OutputBackend::Device { device, .. } => {
+                let name = device.name().ok();
+                match &settings.name {
+                    Some(preferred) => name.as_ref() == Some(preferred),
+                    None => name.is_some() && name == Self::default_device_name(),
+                }
+            }
+            OutputBackend::Null { .. } => settings.use_null,
+        }
+    }
+
+    /// デバイスがなくてもデコード・ミックスできる仮想出力
+    pub fn null(config: OutputConfig) -> Self {
         Self {
-            device,
-            stream: None,
+            backend: OutputBackend::Null {
+                config,
+                stream: None,
+            },
         }
     }
-}
 
-impl AudioOutput {
-    pub fn config(&self) -> Result<cpal::SupportedStreamConfig, cpal::DefaultStreamConfigError> {
-        self.device.default_output_config()
+    pub fn name(&self) -> String {
+        match &self.backend {
+            OutputBackend::Device { device, .. } => device.name().unwrap_or_default(),
+            OutputBackend::Null { .. } => "Null output".to_owned(),
+        }
+    }
+
+    /// 固定したバッファサイズ (フレーム数)
+    pub fn buffer_size(&self) -> Option<u32> {
+        match &self.backend {
+            OutputBackend::Device {
+                buffer_size: cpal::BufferSize::Fixed(size),
+                ..
+            } => Some(*size),
+            _ => None,
+        }
+    }
+
+    pub fn config(&self) -> OutputConfig {
+        match &self.backend {
+            OutputBackend::Device { config, .. } => OutputConfig {
+                sample_rate: config.sample_rate().0,
+                channels: config.channels(),
+            },
+            OutputBackend::Null { config, .. } => *config,
+        }
     }
 
     pub fn play<F>(&mut self, data_callback: F) -> anyhow::Result<()>
     where
         F: FnMut(&mut [f32]) + Send + 'static,
     {
-        let config = self.device.default_output_config()?;
-        match config.sample_format() {
-            cpal::SampleFormat::I8 => self.run::<i8, _>(&config.into(), data_callback),
-            cpal::SampleFormat::I16 => self.run::<i16, _>(&config.into(), data_callback),
-            //cpal::SampleFormat::I24 => self.run::<cpal::I24, _>(&config.into(), data_callback),
-            cpal::SampleFormat::I32 => self.run::<i32, _>(&config.into(), data_callback),
-            // cpal::SampleFormat::I48 => self.run::<I48, _>(&config.into(), data_callback),
-            cpal::SampleFormat::I64 => self.run::<i64, _>(&config.into(), data_callback),
-            cpal::SampleFormat::U8 => self.run::<u8, _>(&config.into(), data_callback),
-            cpal::SampleFormat::U16 => self.run::<u16, _>(&config.into(), data_callback),
-            // cpal::SampleFormat::U24 => self.run::<U24, _>(&config.into(), data_callback),
-            cpal::SampleFormat::U32 => self.run::<u32, _>(&config.into(), data_callback),
-            // cpal::SampleFormat::U48 => self.run::<U48, _>(&config.into(), data_callback),
-            cpal::SampleFormat::U64 => self.run::<u64, _>(&config.into(), data_callback),
-            cpal::SampleFormat::F32 => self.run::<f32, _>(&config.into(), data_callback),
-            cpal::SampleFormat::F64 => self.run::<f64, _>(&config.into(), data_callback),
+        let (device, config, buffer_size) = match &mut self.backend {
+            OutputBackend::Device {
+                device,
+                config,
+                buffer_size,
+                ..
+            } => (device.clone(), config.clone(), *buffer_size),
+            OutputBackend::Null { config, stream } => {
+                *stream = Some(NullStream::start(*config, data_callback));
+                return Ok(());
+            }
+        };
+        let sample_format = config.sample_format();
+        let config = cpal::StreamConfig {
+            buffer_size,
+            ..config.into()
+        };
+        match sample_format {
+            cpal::SampleFormat::I8 => self.run::<i8, _>(&device, &config, data_callback),
+            cpal::SampleFormat::I16 => self.run::<i16, _>(&device, &config, data_callback),
+            //cpal::SampleFormat::I24 => self.run::<cpal::I24, _>(&device, &config, data_callback),
+            cpal::SampleFormat::I32 => self.run::<i32, _>(&device, &config, data_callback),
+            // cpal::SampleFormat::I48 => self.run::<I48, _>(&device, &config, data_callback),
+            cpal::SampleFormat::I64 => self.run::<i64, _>(&device, &config, data_callback),
+            cpal::SampleFormat::U8 => self.run::<u8, _>(&device, &config, data_callback),
+            cpal::SampleFormat::U16 => self.run::<u16, _>(&device, &config, data_callback),
+            // cpal::SampleFormat::U24 => self.run::<U24, _>(&device, &config, data_callback),
+            cpal::SampleFormat::U32 => self.run::<u32, _>(&device, &config, data_callback),
+            // cpal::SampleFormat::U48 => self.run::<U48, _>(&device, &config, data_callback),
+            cpal::SampleFormat::U64 => self.run::<u64, _>(&device, &config, data_callback),
+            cpal::SampleFormat::F32 => self.run::<f32, _>(&device, &config, data_callback),
+            cpal::SampleFormat::F64 => self.run::<f64, _>(&device, &config, data_callback),
             sample_format => bail!("Unsupported sample format '{sample_format}'"),
         }
     }
 
-    fn run<T, F>(&mut self, config: &cpal::StreamConfig, mut data_callback: F) -> anyhow::Result<()>
+    fn run<T, F>(
+        &mut self,
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        mut data_callback: F,
+    ) -> anyhow::Result<()>
     where
         T: cpal::SizedSample + cpal::FromSample<f32> + AddAssign,
         F: FnMut(&mut [f32]) + Send + 'static,
     {
-        let stream = self.device.build_output_stream(
+        let failed = match &self.backend {
+            OutputBackend::Device { failed, .. } => failed.clone(),
+            OutputBackend::Null { .. } => Arc::new(AtomicBool::new(false)),
+        };
+        // コールバック内で確保しないよう、先に用意したバッファを分割して使う
+        let mut buffer = vec![0.0f32; MAX_BLOCK_FRAMES * config.channels as usize];
+        let new_stream = device.build_output_stream(
             config,
             move |output: &mut [T], _| {
-                let mut buffer = vec![0.0f32; output.len()];
-                data_callback(&mut buffer);
-                for (out, &sample) in output.iter_mut().zip(buffer.iter()) {
-                    *out = T::from_sample(sample);
+                for chunk in output.chunks_mut(buffer.len()) {
+                    let block = &mut buffer[..chunk.len()];
+                    block.fill(0.0);
+                    data_callback(block);
+                    for (out, &sample) in chunk.iter_mut().zip(block.iter()) {
+                        *out = T::from_sample(sample);
+                    }
                 }
             },
-            |err| eprintln!("an error occurred on stream: {err}"),
+            move |err| {
+                log::warn!("an error occurred on stream: {err}");
+                failed.store(true, Ordering::Relaxed);
+            },
             None,
         )?;
-        stream.play()?;
-        self.stream = Some(stream);
+        new_stream.play()?;
+        if let OutputBackend::Device { stream, .. } = &mut self.backend {
+            *stream = Some(new_stream);
+        }
 
         Ok(())
     }
 }
+
+/// 実時間に合わせてコールバックを呼び、結果は捨てる
+struct NullStream {
+    #[cfg(not(target_arch = "wasm32"))]
+    running: Arc<AtomicBool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl NullStream {
+    const BLOCK_FRAMES: usize = 512;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start<F>(config: OutputConfig, mut data_callback: F) -> Self
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        use std::time::{Duration, Instant};
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let handle = std::thread::spawn(move || {
+            let mut buffer = vec![0.0f32; Self::BLOCK_FRAMES * config.channels as usize];
+            let period = Duration::from_secs_f64(
+                Self::BLOCK_FRAMES as f64 / config.sample_rate.max(1) as f64,
+            );
+            let mut deadline = Instant::now();
+            while thread_running.load(Ordering::Relaxed) {
+                buffer.fill(0.0);
+                data_callback(&mut buffer);
+                deadline += period;
+                std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            }
+        });
+
+        Self {
+            running,
+            handle: Some(handle),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn start<F>(_config: OutputConfig, _data_callback: F) -> Self
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        Self {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for NullStream {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
@@ -1,7 +1,12 @@
+use super::AudioFormat;
 use anyhow::Context as _;
 use anyhow::bail;
 use lewton::inside_ogg::OggStreamReader;
 use std::io;
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
 
 #[derive(Debug)]
 pub struct AudioSource {
@@ -12,6 +17,17 @@ pub struct AudioSource {
 }
 
 impl AudioSource {
+    pub fn decode(buf: &[u8], output_channels: usize) -> anyhow::Result<Self> {
+        match AudioFormat::detect(buf) {
+            Some(AudioFormat::OggVorbis) => {
+                let mut ogg = OggStreamReader::new(io::Cursor::new(buf))?;
+                Self::from_ogg(&mut ogg, output_channels)
+            }
+            Some(format) => Self::from_symphonia(buf, format, output_channels),
+            None => bail!("Unsupported audio format."),
+        }
+    }
+
     pub fn from_ogg<T>(
         stream: &mut OggStreamReader<T>,
         output_channels: usize,
@@ -24,25 +40,11 @@ impl AudioSource {
         let mut samples = vec![Vec::new(); output_channels];
         while let Some(pck) = stream.read_dec_packet_itl()? {
             for frame in pck.chunks_exact(in_channels) {
-                let mut frame_f32 = frame.iter().map(|v| *v as f32 / 32768.0);
-                if in_channels == output_channels {
-                    for channel in &mut samples {
-                        channel.push(frame_f32.next().unwrap_or_default());
-                    }
-                } else if in_channels == 1 {
-                    let value = frame_f32.next().unwrap_or_default();
-                    for channel in &mut samples {
-                        channel.push(value);
-                    }
-                } else if output_channels == 1 {
-                    samples[0].push(frame_f32.sum::<f32>() / in_channels as f32);
-                } else {
-                    bail!(
-                        "Unable to convert channels from {} to {}.",
-                        in_channels,
-                        output_channels
-                    );
-                }
+                push_frame(
+                    &mut samples,
+                    frame.iter().map(|v| *v as f32 / 32768.0),
+                    in_channels,
+                )?;
             }
         }
 
@@ -57,4 +59,108 @@ impl AudioSource {
             len,
         })
     }
+
+    fn from_symphonia(
+        buf: &[u8],
+        format: AudioFormat,
+        output_channels: usize,
+    ) -> anyhow::Result<Self> {
+        let stream =
+            MediaSourceStream::new(Box::new(io::Cursor::new(buf.to_vec())), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension(format.extension());
+        let mut reader = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )?
+            .format;
+
+        let track = reader.default_track().context("No audio track found")?;
+        let track_id = track.id;
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .context("Failed to get sample rate")?;
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut samples = vec![Vec::new(); output_channels];
+        let mut sample_buffer: Option<SampleBuffer<f32>> = None;
+        loop {
+            let packet = match reader.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    // ストリーム終端
+                    break;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // 壊れたパケットは飛ばして続ける
+                Err(SymphoniaError::DecodeError(err)) => {
+                    log::warn!("Skipped a corrupt packet: {err}");
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let spec = *decoded.spec();
+            let in_channels = spec.channels.count();
+            let sample_buffer = match &mut sample_buffer {
+                Some(b) if b.capacity() >= decoded.capacity() * in_channels => b,
+                b => b.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+            };
+            sample_buffer.copy_interleaved_ref(decoded);
+
+            for frame in sample_buffer.samples().chunks_exact(in_channels) {
+                push_frame(&mut samples, frame.iter().copied(), in_channels)?;
+            }
+        }
+
+        let len = samples.first().map(|s| s.len()).unwrap_or_default();
+        if len == 0 {
+            bail!("Audio contains no samples.");
+        }
+
+        Ok(Self {
+            samples,
+            sample_rate,
+            channels: output_channels,
+            len,
+        })
+    }
+}
+
+fn push_frame(
+    samples: &mut [Vec<f32>],
+    mut frame: impl Iterator<Item = f32>,
+    in_channels: usize,
+) -> anyhow::Result<()> {
+    let output_channels = samples.len();
+    if in_channels == output_channels {
+        for channel in samples {
+            channel.push(frame.next().unwrap_or_default());
+        }
+    } else if in_channels == 1 {
+        let value = frame.next().unwrap_or_default();
+        for channel in samples {
+            channel.push(value);
+        }
+    } else if output_channels == 1 {
+        samples[0].push(frame.sum::<f32>() / in_channels as f32);
+    } else {
+        bail!(
+            "Unable to convert channels from {} to {}.",
+            in_channels,
+            output_channels
+        );
+    }
+    Ok(())
 }
@@ -0,0 +1,130 @@
+use super::{MAX_PITCH, MIN_PITCH, Smoothing};
+use std::{f64::consts::TAU, ops::AddAssign};
+
+/// 1 つの音源で鳴らせる倍音の数
+pub const MAX_HARMONICS: usize = 16;
+
+/// 基本周波数と倍音ごとの次数・振幅 (Simulation が速度から求める)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HarmonicParams {
+    /// 基本周波数 (Hz)
+    pub fundamental: f32,
+    /// 基本周波数に対する倍率 (歯数など整数でなくてもよい)
+    pub orders: [f32; MAX_HARMONICS],
+    pub amplitudes: [f32; MAX_HARMONICS],
+}
+
+/// モーター・歯車のうなりを正弦波の和で合成する
+///
+/// 振幅の合計が 1 を超えるときは合計で割り、出力が ±1 に収まるようにする。
+pub struct HarmonicSynth {
+    channels: usize,
+    sample_rate: u32,
+    smoothing: Smoothing,
+    phases: [f64; MAX_HARMONICS],
+    params: HarmonicParams,
+    /// ブロックの先頭での値 (ブロック内は直線補間する)
+    current: HarmonicParams,
+    volume: f32,
+    pitch: f32,
+    smoothed_volume: f32,
+    smoothed_pitch: f32,
+}
+
+impl HarmonicSynth {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            channels: channels.max(1),
+            sample_rate,
+            smoothing: Smoothing::default(),
+            phases: [0.0; MAX_HARMONICS],
+            params: HarmonicParams::default(),
+            current: HarmonicParams::default(),
+            volume: 0.0,
+            pitch: 1.0,
+            smoothed_volume: 0.0,
+            smoothed_pitch: 1.0,
+        }
+    }
+
+    pub fn set_volume_pitch(&mut self, volume: f32, pitch: f32) {
+        self.volume = volume;
+        if pitch.is_finite() {
+            self.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        }
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoothing = smoothing;
+    }
+
+    pub fn set_params(&mut self, params: HarmonicParams) {
+        // 計算できない値は前の値のままにする
+        if params.fundamental.is_finite() {
+            self.params.fundamental = params.fundamental.max(0.0);
+        }
+        for i in 0..MAX_HARMONICS {
+            if params.orders[i].is_finite() {
+                self.params.orders[i] = params.orders[i].max(0.0);
+            }
+            if params.amplitudes[i].is_finite() {
+                self.params.amplitudes[i] = params.amplitudes[i].clamp(0.0, 1.0);
+            }
+        }
+    }
+
+    pub fn write_data_additive<T>(&mut self, output: &mut [T])
+    where
+        T: cpal::Sample + cpal::FromSample<f32> + AddAssign,
+    {
+        let frames = output.len() / self.channels;
+        if frames == 0 || self.sample_rate == 0 {
+            return;
+        }
+
+        let block_seconds = frames as f32 / self.sample_rate as f32;
+        self.smoothed_pitch =
+            self.smoothing
+                .glide_pitch(self.smoothed_pitch, self.pitch, block_seconds);
+        let mut target = self.params;
+        target.fundamental *= self.smoothed_pitch;
+        // ナイキスト周波数を超える倍音は鳴らさない
+        let nyquist = self.sample_rate as f32 / 2.0;
+        for (order, amplitude) in target.orders.iter().zip(target.amplitudes.iter_mut()) {
+            if target.fundamental * order >= nyquist {
+                *amplitude = 0.0;
+            }
+        }
+        let start = self.current;
+
+        let dt = 1.0 / self.sample_rate as f64;
+        let (up, down) = self.smoothing.volume_deltas(self.sample_rate);
+        for (i, frame) in output.chunks_exact_mut(self.channels).enumerate() {
+            let t = (i + 1) as f32 / frames as f32;
+            let lerp = |a: f32, b: f32| a + (b - a) * t;
+            let fundamental = lerp(start.fundamental, target.fundamental);
+
+            let mut value = 0.0;
+            let mut amplitude_sum = 0.0;
+            for k in 0..MAX_HARMONICS {
+                let amplitude = lerp(start.amplitudes[k], target.amplitudes[k]);
+                if amplitude <= 0.0 {
+                    continue;
+                }
+                let frequency = (fundamental * target.orders[k]) as f64;
+                self.phases[k] = (self.phases[k] + frequency * dt).fract();
+                value += amplitude * (TAU * self.phases[k]).sin() as f32;
+                amplitude_sum += amplitude;
+            }
+            // 倍音を重ねても音割れしないようにする
+            value /= amplitude_sum.max(1.0);
+
+            let v = self.smoothed_volume;
+            for o in frame {
+                *o += T::from_sample(value * v);
+            }
+            self.smoothed_volume = self.volume.clamp(v - down, v + up);
+        }
+        self.current = target;
+    }
+}
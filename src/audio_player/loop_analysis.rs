@@ -0,0 +1,231 @@
+use super::{AudioSource, LoopRegion};
+
+/// 継ぎ目の比較に使う窓の長さ
+const WINDOW: usize = 512;
+/// スペクトル比較の周波数ビン数
+const SPECTRUM_BINS: usize = 24;
+/// 候補探索の範囲 (秒)
+const SEARCH_SECONDS: f64 = 0.05;
+/// 片側あたりで調べるゼロクロスの最大数
+const MAX_CROSSINGS: usize = 48;
+
+/// 前後のサンプル差の何倍の段差でクリックとみなすか
+const CLICK_JUMP_RATIO: f32 = 4.0;
+/// 継ぎ目前後のスペクトル差 (dB) の許容値
+const CLICK_SPECTRAL_DB: f32 = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeamReport {
+    /// 継ぎ目での段差 (近傍のサンプル差に対する比)
+    pub amplitude_jump: f32,
+    /// 継ぎ目前後の窓の平均スペクトル差 (dB)
+    pub spectral_mismatch: f32,
+}
+
+impl SeamReport {
+    pub fn clicks(&self) -> bool {
+        self.amplitude_jump > CLICK_JUMP_RATIO || self.spectral_mismatch > CLICK_SPECTRAL_DB
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopCandidate {
+    pub start: usize,
+    pub end: usize,
+    /// 継ぎ目前後の波形の正規化相互相関 (1 に近いほど良い)
+    pub similarity: f32,
+}
+
+/// 現在のループ区間の継ぎ目を評価する
+pub fn analyze_seam(source: &AudioSource, loop_region: &LoopRegion) -> SeamReport {
+    let mono = Mono::new(source);
+    let (start, end, crossfade) = loop_region.resolve(mono.len());
+    if mono.len() < 2 || end <= start {
+        return SeamReport {
+            amplitude_jump: 0.0,
+            spectral_mismatch: 0.0,
+        };
+    }
+
+    // 実際に再生される順に、継ぎ目をまたいだ区間を並べる
+    let played = |k: isize| -> f32 {
+        if k < 0 {
+            let cursor = (end as isize + k).max(start as isize) as usize;
+            let fade_start = end - crossfade;
+            if cursor < fade_start {
+                mono.at(cursor)
+            } else {
+                let j = cursor - fade_start;
+                let t = (j as f32 + 0.5) / crossfade as f32;
+                mono.at(cursor) * (1.0 - t) + mono.at(start - crossfade + j) * t
+            }
+        } else {
+            mono.at((start + k as usize).min(end - 1))
+        }
+    };
+
+    // 継ぎ目の段差を、近傍の典型的なサンプル差と比べる
+    let seam_diff = (played(0) - played(-1)).abs();
+    let neighbor_diff = (2..16)
+        .map(|k| (played(-k) - played(-k + 1)).abs() + (played(k - 1) - played(k - 2)).abs())
+        .sum::<f32>()
+        / 28.0;
+    let amplitude_jump = seam_diff / neighbor_diff.max(1e-4);
+
+    let before: Vec<f32> = (-(WINDOW as isize)..0).map(played).collect();
+    let after: Vec<f32> = (0..WINDOW as isize).map(played).collect();
+    let spectral_mismatch = spectral_distance(&before, &after, source.sample_rate);
+
+    SeamReport {
+        amplitude_jump,
+        spectral_mismatch,
+    }
+}
+
+/// ループ開始点・終了点の近くで、向きの揃ったゼロクロス同士の組を波形の類似度順に返す
+pub fn suggest_loop_points(
+    source: &AudioSource,
+    loop_region: &LoopRegion,
+    count: usize,
+) -> Vec<LoopCandidate> {
+    let mono = Mono::new(source);
+    let (start, end, _) = loop_region.resolve(mono.len());
+    let radius = (SEARCH_SECONDS * source.sample_rate as f64) as usize;
+
+    let start_crossings = mono.zero_crossings_near(start, radius);
+    let end_crossings = mono.zero_crossings_near(end, radius);
+
+    let mut candidates = Vec::new();
+    for &(s, s_rising) in &start_crossings {
+        for &(e, e_rising) in &end_crossings {
+            if s_rising != e_rising || e <= s + WINDOW {
+                continue;
+            }
+            if let Some(similarity) = mono.similarity(s, e) {
+                candidates.push(LoopCandidate {
+                    start: s,
+                    end: e,
+                    similarity,
+                });
+            }
+        }
+    }
+
+    // 類似度が同程度なら元の位置に近いものを優先
+    let score = |c: &LoopCandidate| {
+        let distance = c.start.abs_diff(start) + c.end.abs_diff(end);
+        c.similarity - 0.01 * distance as f32 / (2 * radius).max(1) as f32
+    };
+    candidates.sort_by(|a, b| score(b).total_cmp(&score(a)));
+    candidates.truncate(count);
+    candidates
+}
+
+/// 全チャンネルを平均したモノラル信号
+struct Mono<'a> {
+    source: &'a AudioSource,
+    len: usize,
+}
+
+impl<'a> Mono<'a> {
+    fn new(source: &'a AudioSource) -> Self {
+        let len = source
+            .samples
+            .iter()
+            .map(|s| s.len())
+            .fold(source.len, usize::min);
+        Self { source, len }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn at(&self, index: usize) -> f32 {
+        if index >= self.len {
+            return 0.0;
+        }
+        let channels = self.source.samples.len().max(1) as f32;
+        self.source.samples.iter().map(|s| s[index]).sum::<f32>() / channels
+    }
+
+    fn get(&self, index: usize) -> Option<f32> {
+        (index < self.len).then(|| self.at(index))
+    }
+
+    /// `center` に近い順にゼロクロス位置と立ち上がりかどうかを返す
+    fn zero_crossings_near(&self, center: usize, radius: usize) -> Vec<(usize, bool)> {
+        let from = center.saturating_sub(radius).max(1);
+        let to = center.saturating_add(radius).min(self.len());
+        let mut crossings: Vec<(usize, bool)> = (from..to)
+            .filter_map(|i| {
+                let a = self.at(i - 1);
+                let b = self.at(i);
+                if a < 0.0 && b >= 0.0 {
+                    Some((i, true))
+                } else if a >= 0.0 && b < 0.0 {
+                    Some((i, false))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        crossings.sort_by_key(|(i, _)| i.abs_diff(center));
+        crossings.truncate(MAX_CROSSINGS);
+        crossings
+    }
+
+    /// `start` と `end` の前後の波形を比べた正規化相互相関
+    fn similarity(&self, start: usize, end: usize) -> Option<f32> {
+        let half = (WINDOW / 2) as isize;
+        let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+        let mut n = 0;
+        for k in -half..half {
+            let (Some(a), Some(b)) = (
+                start.checked_add_signed(k).and_then(|i| self.get(i)),
+                end.checked_add_signed(k).and_then(|i| self.get(i)),
+            ) else {
+                continue;
+            };
+            dot += a * b;
+            norm_a += a * a;
+            norm_b += b * b;
+            n += 1;
+        }
+        (n >= WINDOW / 4 && norm_a > 0.0 && norm_b > 0.0).then(|| dot / (norm_a * norm_b).sqrt())
+    }
+}
+
+/// 対数間隔の周波数ビンで比べた平均スペクトル差 (dB)
+fn spectral_distance(a: &[f32], b: &[f32], sample_rate: u32) -> f32 {
+    let nyquist = sample_rate as f32 / 2.0;
+    let (low, high) = (50.0f32, nyquist.min(16000.0));
+    let bins: Vec<(f32, f32)> = (0..SPECTRUM_BINS)
+        .map(|bin| {
+            let f = low * (high / low).powf(bin as f32 / (SPECTRUM_BINS - 1) as f32);
+            let f = f / sample_rate as f32;
+            (magnitude_at(a, f), magnitude_at(b, f))
+        })
+        .collect();
+
+    // ほぼ無音の帯域の差は聞こえないので、最大値から -60 dB で底上げ
+    let floor = bins.iter().map(|(ma, mb)| ma.max(*mb)).fold(0.0, f32::max) * 1e-3 + 1e-9;
+    bins.iter()
+        .map(|(ma, mb)| (20.0 * ((ma + floor) / (mb + floor)).log10()).abs())
+        .sum::<f32>()
+        / SPECTRUM_BINS as f32
+}
+
+/// ハン窓をかけた単一周波数の DFT の大きさ
+fn magnitude_at(signal: &[f32], normalized_frequency: f32) -> f32 {
+    let n = signal.len() as f32;
+    let omega = std::f32::consts::TAU * normalized_frequency;
+    let (mut re, mut im) = (0.0, 0.0);
+    for (i, s) in signal.iter().enumerate() {
+        let w = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / n).cos();
+        let phase = omega * i as f32;
+        re += s * w * phase.cos();
+        im -= s * w * phase.sin();
+    }
+    re.hypot(im) / n
+}
@@ -0,0 +1,23 @@
+/// ループ区間 (元音声のサンプル位置)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct LoopRegion {
+    pub start: usize,
+    /// `None` ならファイル終端まで
+    pub end: Option<usize>,
+    pub crossfade: usize,
+}
+
+impl LoopRegion {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// 音声の長さに収まるように `(start, end, crossfade)` を確定する
+    pub fn resolve(&self, len: usize) -> (usize, usize, usize) {
+        let end = self.end.unwrap_or(len).clamp(1, len.max(1));
+        let start = self.start.min(end - 1);
+        // クロスフェードはループ開始点より前のデータと重ねるので、その長さまで
+        let crossfade = self.crossfade.min(start).min(end - start);
+        (start, end, crossfade)
+    }
+}
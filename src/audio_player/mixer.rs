@@ -0,0 +1,137 @@
+use super::{LoopRegion, Smoothing, SynthParams, Voice};
+use crate::state::AudioEntryId;
+
+/// 同時に鳴らせる音声の数 (コールバック内で確保し直さないための上限)
+pub const MAX_VOICES: usize = 256;
+/// 1 フレームあたりに送れるコマンド数の目安
+const COMMAND_CAPACITY: usize = 1024;
+/// 音量・ピッチの更新を溜めておける数
+const PARAMS_CAPACITY: usize = 4096;
+
+pub enum MixerCommand {
+    AddVoice(AudioEntryId, Box<Voice>),
+    RemoveVoice(AudioEntryId),
+    Clear,
+}
+
+/// Simulation から送られる音声ごとのパラメータ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceParams {
+    pub id: AudioEntryId,
+    pub volume: f32,
+    pub pitch: f32,
+    pub loop_region: LoopRegion,
+    pub smoothing: Smoothing,
+    /// 合成音のときだけ使う
+    pub synth: Option<SynthParams>,
+    /// ワンショット音声を鳴らす合図 (ワンショット音声は合図のときだけ送られる)
+    ///
+    /// 転動音では車軸が継ぎ目を踏んだことを表す。
+    pub trigger: bool,
+}
+
+/// オーディオスレッド側で音声を持ち、ミックスする
+///
+/// コールバック内ではロックもメモリ確保・解放もしない。
+/// 不要になった音声は UI スレッドへ返して、そちらで解放する。
+pub struct Mixer {
+    voices: Vec<(AudioEntryId, Box<Voice>)>,
+    commands: rtrb::Consumer<MixerCommand>,
+    params: rtrb::Consumer<VoiceParams>,
+    garbage: rtrb::Producer<Box<Voice>>,
+}
+
+/// UI スレッド側から Mixer を操作する
+pub struct MixerHandle {
+    commands: rtrb::Producer<MixerCommand>,
+    params: Option<rtrb::Producer<VoiceParams>>,
+    garbage: rtrb::Consumer<Box<Voice>>,
+}
+
+impl Mixer {
+    pub fn new() -> (Self, MixerHandle) {
+        let (command_tx, command_rx) = rtrb::RingBuffer::new(COMMAND_CAPACITY);
+        let (params_tx, params_rx) = rtrb::RingBuffer::new(PARAMS_CAPACITY);
+        let (garbage_tx, garbage_rx) = rtrb::RingBuffer::new(COMMAND_CAPACITY + MAX_VOICES);
+        (
+            Self {
+                voices: Vec::with_capacity(MAX_VOICES),
+                commands: command_rx,
+                params: params_rx,
+                garbage: garbage_tx,
+            },
+            MixerHandle {
+                commands: command_tx,
+                params: Some(params_tx),
+                garbage: garbage_rx,
+            },
+        )
+    }
+
+    pub fn process(&mut self, data: &mut [f32]) {
+        while let Ok(command) = self.commands.pop() {
+            self.apply(command);
+        }
+        // ブロックの先頭で最新の値を反映し、ピッチはリサンプラー内で補間される
+        while let Ok(params) = self.params.pop() {
+            if let Some((_, voice)) = self.voices.iter_mut().find(|(i, _)| *i == params.id) {
+                voice.apply_params(&params);
+            }
+        }
+        for (_, voice) in &mut self.voices {
+            voice.write_data_additive(data);
+        }
+    }
+
+    fn apply(&mut self, command: MixerCommand) {
+        match command {
+            MixerCommand::AddVoice(id, voice) => {
+                if let Some((_, slot)) = self.voices.iter_mut().find(|(i, _)| *i == id) {
+                    let old = std::mem::replace(slot, voice);
+                    self.discard(old);
+                } else if self.voices.len() < self.voices.capacity() {
+                    self.voices.push((id, voice));
+                } else {
+                    self.discard(voice);
+                }
+            }
+            MixerCommand::RemoveVoice(id) => {
+                if let Some(index) = self.voices.iter().position(|(i, _)| *i == id) {
+                    let (_, voice) = self.voices.swap_remove(index);
+                    self.discard(voice);
+                }
+            }
+            MixerCommand::Clear => {
+                while let Some((_, voice)) = self.voices.pop() {
+                    self.discard(voice);
+                }
+            }
+        }
+    }
+
+    fn discard(&mut self, voice: Box<Voice>) {
+        if let Err(rtrb::PushError::Full(voice)) = self.garbage.push(voice) {
+            // 返せなければ解放せずに手放す (UI スレッドが長く止まっているときのみ)
+            #[expect(clippy::mem_forget)]
+            std::mem::forget(voice);
+        }
+    }
+}
+
+impl MixerHandle {
+    pub fn send(&mut self, command: MixerCommand) -> anyhow::Result<()> {
+        self.commands
+            .push(command)
+            .map_err(|_command| anyhow::anyhow!("Mixer command queue is full"))
+    }
+
+    /// Simulation に渡す送り口 (一度だけ取り出せる)
+    pub fn take_param_sink(&mut self) -> Option<rtrb::Producer<VoiceParams>> {
+        self.params.take()
+    }
+
+    /// Mixer から返された音声を解放する
+    pub fn collect_garbage(&mut self) {
+        while self.garbage.pop().is_ok() {}
+    }
+}
@@ -1,6 +1,28 @@
+mod audio_format;
 mod audio_output;
 mod audio_source;
+mod harmonic_synth;
+mod loop_analysis;
+mod loop_region;
+mod mixer;
+mod noise_synth;
+mod one_shot_audio;
+mod pwm_synth;
 mod resampled_loop_audio;
-pub use audio_output::AudioOutput;
+mod smoothing;
+mod voice;
+pub use audio_format::AudioFormat;
+pub use audio_output::{AudioOutput, OutputConfig, OutputDeviceInfo};
 pub use audio_source::AudioSource;
-pub use resampled_loop_audio::ResampledLoopAudio;
+pub use harmonic_synth::{HarmonicParams, HarmonicSynth, MAX_HARMONICS};
+pub use loop_analysis::{LoopCandidate, SeamReport, analyze_seam, suggest_loop_points};
+pub use loop_region::LoopRegion;
+pub use mixer::{Mixer, MixerCommand, MixerHandle, VoiceParams};
+pub use noise_synth::{NoiseParams, NoiseSynth};
+pub use one_shot_audio::OneShotAudio;
+pub use pwm_synth::{CarrierMode, PwmParams, PwmSynth};
+pub use resampled_loop_audio::{
+    MAX_PITCH, MIN_PITCH, ResampledLoopAudio, ResamplerQuality, is_pitch_in_range,
+};
+pub use smoothing::Smoothing;
+pub use voice::{SynthKind, SynthParams, Voice};
@@ -0,0 +1,178 @@
+use super::{MAX_PITCH, MIN_PITCH, Smoothing};
+use std::{f32::consts::PI, ops::AddAssign};
+
+/// 遮断周波数の上限 (ナイキスト周波数に対する割合)
+const MAX_CUTOFF_RATIO: f32 = 0.9;
+/// 継ぎ目の音のフィルターの Q
+const JOINT_Q: f32 = 2.0;
+
+/// 転動音と継ぎ目の音の大きさ・音色 (Simulation が速度から求める)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NoiseParams {
+    /// 転動音のローパスフィルターの遮断周波数 (Hz)
+    pub rolling_cutoff: f32,
+    pub rolling_level: f32,
+    /// 継ぎ目の音のバンドパスフィルターの中心周波数 (Hz)
+    pub joint_cutoff: f32,
+    pub joint_level: f32,
+    /// 継ぎ目の音が 1/e になるまでの時間 (s)
+    pub joint_decay: f32,
+}
+
+/// 状態変数フィルター
+#[derive(Debug, Default, Clone, Copy)]
+struct Svf {
+    ic1: f32,
+    ic2: f32,
+}
+
+impl Svf {
+    /// (ローパス, バンドパス) を返す
+    fn process(&mut self, input: f32, g: f32, k: f32) -> (f32, f32) {
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+        let v3 = input - self.ic2;
+        let v1 = a1 * self.ic1 + a2 * v3;
+        let v2 = self.ic2 + a2 * self.ic1 + a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+        (v2, v1)
+    }
+}
+
+/// フィルターをかけたノイズで転動音を合成し、合図で継ぎ目の音を重ねる
+pub struct NoiseSynth {
+    channels: usize,
+    sample_rate: u32,
+    smoothing: Smoothing,
+    params: NoiseParams,
+    volume: f32,
+    pitch: f32,
+    smoothed_volume: f32,
+    smoothed_pitch: f32,
+    seed: u32,
+    rolling: Svf,
+    joint: Svf,
+    /// 継ぎ目の音の包絡線 (合図の直後が 1)
+    joint_envelope: f32,
+}
+
+impl NoiseSynth {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            channels: channels.max(1),
+            sample_rate,
+            smoothing: Smoothing::default(),
+            params: NoiseParams::default(),
+            volume: 0.0,
+            pitch: 1.0,
+            smoothed_volume: 0.0,
+            smoothed_pitch: 1.0,
+            seed: 0x1234_5678,
+            rolling: Svf::default(),
+            joint: Svf::default(),
+            joint_envelope: 0.0,
+        }
+    }
+
+    pub fn set_volume_pitch(&mut self, volume: f32, pitch: f32) {
+        self.volume = volume;
+        if pitch.is_finite() {
+            self.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        }
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoothing = smoothing;
+    }
+
+    pub fn set_params(&mut self, params: NoiseParams) {
+        // 計算できない値は前の値のままにする
+        let valid = |v: f32| v.is_finite().then_some(v.max(0.0));
+        if let Some(f) = valid(params.rolling_cutoff) {
+            self.params.rolling_cutoff = f;
+        }
+        if let Some(l) = valid(params.rolling_level) {
+            self.params.rolling_level = l;
+        }
+        if let Some(f) = valid(params.joint_cutoff) {
+            self.params.joint_cutoff = f;
+        }
+        if let Some(l) = valid(params.joint_level) {
+            self.params.joint_level = l;
+        }
+        if let Some(d) = valid(params.joint_decay) {
+            self.params.joint_decay = d;
+        }
+    }
+
+    /// 車軸が継ぎ目を踏んだ
+    pub fn trigger_joint(&mut self) {
+        self.joint_envelope = 1.0;
+    }
+
+    pub fn write_data_additive<T>(&mut self, output: &mut [T])
+    where
+        T: cpal::Sample + cpal::FromSample<f32> + AddAssign,
+    {
+        let frames = output.len() / self.channels;
+        if frames == 0 || self.sample_rate == 0 {
+            return;
+        }
+
+        // 遮断周波数はブロック単位で変える
+        let block_seconds = frames as f32 / self.sample_rate as f32;
+        self.smoothed_pitch =
+            self.smoothing
+                .glide_pitch(self.smoothed_pitch, self.pitch, block_seconds);
+        let sample_rate = self.sample_rate as f32;
+        let (rolling_g, rolling_gain) = Self::coefficients(
+            self.params.rolling_cutoff * self.smoothed_pitch,
+            sample_rate,
+        );
+        let (joint_g, joint_gain) =
+            Self::coefficients(self.params.joint_cutoff * self.smoothed_pitch, sample_rate);
+        let rolling_level = self.params.rolling_level * rolling_gain;
+        let joint_level = self.params.joint_level * joint_gain;
+        let joint_release = if self.params.joint_decay > 0.0 {
+            (-1.0 / (self.params.joint_decay * sample_rate)).exp()
+        } else {
+            0.0
+        };
+
+        let (up, down) = self.smoothing.volume_deltas(self.sample_rate);
+        for frame in output.chunks_exact_mut(self.channels) {
+            let noise = self.next_noise();
+            let (rolling, _) = self
+                .rolling
+                .process(noise, rolling_g, std::f32::consts::SQRT_2);
+            let (_, joint) = self.joint.process(noise, joint_g, 1.0 / JOINT_Q);
+            let value = rolling_level * rolling + joint_level * self.joint_envelope * joint;
+            self.joint_envelope *= joint_release;
+
+            let v = self.smoothed_volume;
+            for o in frame {
+                *o += T::from_sample(value * v);
+            }
+            self.smoothed_volume = self.volume.clamp(v - down, v + up);
+        }
+    }
+
+    /// フィルターの係数と、帯域を狭めても音量が変わらないようにする倍率
+    fn coefficients(cutoff: f32, sample_rate: f32) -> (f32, f32) {
+        let nyquist = sample_rate / 2.0;
+        let cutoff = cutoff.clamp(1.0, nyquist * MAX_CUTOFF_RATIO);
+        ((PI * cutoff / sample_rate).tan(), (nyquist / cutoff).sqrt())
+    }
+
+    /// -1 から 1 の白色雑音 (xorshift)
+    fn next_noise(&mut self) -> f32 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
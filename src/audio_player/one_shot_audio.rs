@@ -0,0 +1,95 @@
+use super::{AudioSource, MAX_PITCH, MIN_PITCH};
+use std::{ops::AddAssign, sync::Arc};
+
+/// 重ねて鳴らせる数 (超えたら一番古いものを止める)
+const MAX_PLAYS: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Play {
+    /// 元の音声での位置 (フレーム)
+    position: f64,
+    step: f64,
+    volume: f32,
+}
+
+/// 合図を受けるたびに先頭から最後まで一度だけ鳴らす音声
+///
+/// 短い音なのでリサンプラーは使わず、直線補間で読み進める。
+pub struct OneShotAudio {
+    source: Arc<AudioSource>,
+    output_sample_rate: u32,
+    plays: [Option<Play>; MAX_PLAYS],
+}
+
+impl OneShotAudio {
+    pub fn new(source: Arc<AudioSource>, output_sample_rate: u32) -> Self {
+        Self {
+            source,
+            output_sample_rate,
+            plays: [None; MAX_PLAYS],
+        }
+    }
+
+    pub fn trigger(&mut self, volume: f32, pitch: f32) {
+        if !volume.is_finite() || volume <= 0.0 || !pitch.is_finite() {
+            return;
+        }
+        let step = self.source.sample_rate as f64 / self.output_sample_rate as f64
+            * pitch.clamp(MIN_PITCH, MAX_PITCH) as f64;
+        let play = Play {
+            position: 0.0,
+            step,
+            volume,
+        };
+        let slot = match self.plays.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => self
+                .plays
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| {
+                    let a = a.map_or(0.0, |p| p.position);
+                    let b = b.map_or(0.0, |p| p.position);
+                    a.total_cmp(&b)
+                })
+                .map_or(0, |(index, _)| index),
+        };
+        self.plays[slot] = Some(play);
+    }
+
+    pub fn write_data_additive<T>(&mut self, output: &mut [T])
+    where
+        T: cpal::Sample + cpal::FromSample<f32> + AddAssign,
+    {
+        let source = &self.source;
+        let channels = source.channels;
+        // Ogg の長さはヘッダーから取るので、実際のサンプル数を超えないようにする
+        let len = source
+            .samples
+            .iter()
+            .map(Vec::len)
+            .fold(source.len, usize::min);
+        if channels == 0 {
+            return;
+        }
+        for slot in &mut self.plays {
+            let Some(play) = slot else {
+                continue;
+            };
+            for frame in output.chunks_exact_mut(channels) {
+                let index = play.position as usize;
+                if index + 1 >= len {
+                    *slot = None;
+                    break;
+                }
+                let t = (play.position - index as f64) as f32;
+                for (channel, out) in frame.iter_mut().enumerate() {
+                    let samples = &source.samples[channel];
+                    let value = samples[index] + (samples[index + 1] - samples[index]) * t;
+                    *out += T::from_sample(value * play.volume);
+                }
+                play.position += play.step;
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use super::{MAX_PITCH, MIN_PITCH, Smoothing};
+use std::{f64::consts::TAU, ops::AddAssign};
+
+/// 1 サンプルを何回に分けて計算するか (折り返し雑音を抑える)
+const OVERSAMPLING: usize = 8;
+/// 変調率の上限 (これ以上は 1 パルスとほぼ同じ)
+const MAX_MODULATION_INDEX: f32 = 4.0;
+/// 広域パルスで使う変調率の下限 (過変調)
+const WIDE_PULSE_MODULATION_INDEX: f64 = 1.5;
+
+/// 搬送波の決め方
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CarrierMode {
+    /// 搬送波の周波数を曲線で決める
+    #[default]
+    Asynchronous,
+    /// 信号波 1 周期あたりのパルス数を固定する
+    Synchronous(u32),
+    /// 過変調の同期 3 パルス
+    WidePulse,
+    /// 信号波 1 周期に 1 パルス (方形波)
+    OnePulse,
+}
+
+impl CarrierMode {
+    pub fn label_text(&self) -> String {
+        match self {
+            Self::Asynchronous => "Asynchronous".to_owned(),
+            Self::Synchronous(pulses) => format!("Synchronous {pulses}-pulse"),
+            Self::WidePulse => "Wide pulse".to_owned(),
+            Self::OnePulse => "One pulse".to_owned(),
+        }
+    }
+}
+
+/// インバーターの周波数 (Simulation が速度から求める)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PwmParams {
+    /// 搬送波の周波数 (Hz)
+    pub carrier_frequency: f32,
+    /// 信号波 (インバーター出力) の周波数 (Hz)
+    pub signal_frequency: f32,
+    /// 信号波の振幅と搬送波の振幅の比
+    pub modulation_index: f32,
+    pub mode: CarrierMode,
+}
+
+/// 三角波比較の三相 PWM で線間電圧を合成する
+pub struct PwmSynth {
+    channels: usize,
+    sample_rate: u32,
+    smoothing: Smoothing,
+    carrier_phase: f64,
+    signal_phase: f64,
+    params: PwmParams,
+    /// ブロックの先頭での周波数 (ブロック内は直線補間する)
+    current: PwmParams,
+    volume: f32,
+    pitch: f32,
+    smoothed_volume: f32,
+    smoothed_pitch: f32,
+}
+
+impl PwmSynth {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            channels: channels.max(1),
+            sample_rate,
+            smoothing: Smoothing::default(),
+            carrier_phase: 0.0,
+            signal_phase: 0.0,
+            params: PwmParams::default(),
+            current: PwmParams::default(),
+            volume: 0.0,
+            pitch: 1.0,
+            smoothed_volume: 0.0,
+            smoothed_pitch: 1.0,
+        }
+    }
+
+    pub fn set_volume_pitch(&mut self, volume: f32, pitch: f32) {
+        self.volume = volume;
+        if pitch.is_finite() {
+            self.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        }
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoothing = smoothing;
+    }
+
+    pub fn set_params(&mut self, params: PwmParams) {
+        // 計算できない値は前の値のままにする
+        let valid = |v: f32| v.is_finite().then_some(v.max(0.0));
+        let nyquist = self.sample_rate as f32 / 2.0;
+        if let Some(f) = valid(params.carrier_frequency) {
+            self.params.carrier_frequency = f.min(nyquist);
+        }
+        if let Some(f) = valid(params.signal_frequency) {
+            self.params.signal_frequency = f.min(nyquist);
+        }
+        if let Some(m) = valid(params.modulation_index) {
+            self.params.modulation_index = m.min(MAX_MODULATION_INDEX);
+        }
+        self.params.mode = params.mode;
+    }
+
+    pub fn write_data_additive<T>(&mut self, output: &mut [T])
+    where
+        T: cpal::Sample + cpal::FromSample<f32> + AddAssign,
+    {
+        let frames = output.len() / self.channels;
+        if frames == 0 || self.sample_rate == 0 {
+            return;
+        }
+
+        // ピッチ (周波数の倍率) はブロック単位でなめらかに目標へ近づける
+        let block_seconds = frames as f32 / self.sample_rate as f32;
+        self.smoothed_pitch =
+            self.smoothing
+                .glide_pitch(self.smoothed_pitch, self.pitch, block_seconds);
+        let target = PwmParams {
+            carrier_frequency: self.params.carrier_frequency * self.smoothed_pitch,
+            signal_frequency: self.params.signal_frequency * self.smoothed_pitch,
+            modulation_index: self.params.modulation_index,
+            mode: self.params.mode,
+        };
+        let start = self.current;
+
+        let dt = 1.0 / (self.sample_rate as f64 * OVERSAMPLING as f64);
+        let (up, down) = self.smoothing.volume_deltas(self.sample_rate);
+        for (i, frame) in output.chunks_exact_mut(self.channels).enumerate() {
+            let t = (i + 1) as f32 / frames as f32;
+            let lerp = |a: f32, b: f32| (a + (b - a) * t) as f64;
+            let carrier = lerp(start.carrier_frequency, target.carrier_frequency);
+            let signal = lerp(start.signal_frequency, target.signal_frequency);
+            let m = lerp(start.modulation_index, target.modulation_index);
+
+            let mut sum = 0.0;
+            for _ in 0..OVERSAMPLING {
+                self.carrier_phase = (self.carrier_phase + carrier * dt).fract();
+                self.signal_phase = (self.signal_phase + signal * dt).fract();
+                sum += match target.mode {
+                    CarrierMode::Asynchronous => {
+                        Self::line_voltage(self.carrier_phase, self.signal_phase, m)
+                    }
+                    // 同期モードは搬送波を信号波の位相に合わせる
+                    CarrierMode::Synchronous(pulses) => {
+                        let phase = (self.signal_phase * pulses.max(1) as f64).fract();
+                        Self::line_voltage(phase, self.signal_phase, m)
+                    }
+                    CarrierMode::WidePulse => {
+                        let phase = (self.signal_phase * 3.0).fract();
+                        Self::line_voltage(
+                            phase,
+                            self.signal_phase,
+                            m.max(WIDE_PULSE_MODULATION_INDEX),
+                        )
+                    }
+                    // 搬送波を 0 に固定すると正弦波の符号になる
+                    CarrierMode::OnePulse => Self::line_voltage(0.25, self.signal_phase, 1.0),
+                };
+            }
+            let value = (sum / OVERSAMPLING as f64) as f32;
+
+            let v = self.smoothed_volume;
+            for o in frame {
+                *o += T::from_sample(value * v);
+            }
+            self.smoothed_volume = self.volume.clamp(v - down, v + up);
+        }
+        self.current = target;
+    }
+
+    /// U 相と V 相の差 (-1, 0, 1)
+    fn line_voltage(carrier_phase: f64, signal_phase: f64, modulation_index: f64) -> f64 {
+        // 搬送波は -1 から 1 の三角波
+        let carrier = 1.0 - 4.0 * (carrier_phase - 0.5).abs();
+        let switch = |offset: f64| {
+            let signal = modulation_index * (TAU * (signal_phase - offset)).sin();
+            if signal > carrier { 1.0 } else { -1.0 }
+        };
+        (switch(0.0) - switch(1.0 / 3.0)) / 2.0
+    }
+}
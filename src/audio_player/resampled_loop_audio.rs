@@ -1,54 +1,129 @@
-use super::AudioSource;
-use rubato::{FastFixedIn, Resampler as _};
-use std::{collections::VecDeque, ops::AddAssign};
+use super::{AudioSource, LoopRegion, Smoothing, audio_output::MAX_BLOCK_FRAMES};
+use rubato::{FastFixedIn, SincFixedIn, VecResampler};
+use std::{collections::VecDeque, ops::AddAssign, sync::Arc};
+
+/// リサンプラーが扱える相対比の上限
+const MAX_RELATIVE_RATIO: f64 = 10.0;
+/// リサンプラーに渡すピッチの上限 (ランプ中に上限を超えないよう余裕を持たせる)
+const RESAMPLER_PITCH_LIMIT: f32 = 8.0;
+/// 間引き・補間の前処理を含めて扱えるピッチの範囲
+pub const MIN_PITCH: f32 = 1.0 / 64.0;
+pub const MAX_PITCH: f32 = 64.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum ResamplerQuality {
+    /// 多項式補間 (軽い)
+    #[default]
+    Fast,
+    /// 窓付き sinc 補間 (高品質)
+    Sinc,
+}
+
+impl ResamplerQuality {
+    pub const ALL: [Self; 2] = [Self::Fast, Self::Sinc];
+
+    pub fn label_text(&self) -> &str {
+        match self {
+            Self::Fast => "Fast (cubic)",
+            Self::Sinc => "High quality (sinc)",
+        }
+    }
+}
+
+/// ピッチが扱える範囲か
+pub fn is_pitch_in_range(pitch: f32) -> bool {
+    (MIN_PITCH..=MAX_PITCH).contains(&pitch)
+}
 
 pub struct ResampledLoopAudio {
-    source: AudioSource,
+    source: Arc<AudioSource>,
     source_cursor: usize,
-    resampler: FastFixedIn<f32>,
+    /// 前処理で補間するときの小数部分
+    source_fraction: f64,
+    loop_region: LoopRegion,
+    smoothing: Smoothing,
+    output_sample_rate: u32,
+    resampler: Box<dyn VecResampler<f32>>,
     resampler_input_buffer: Vec<Vec<f32>>,
     resampler_output_buffer: Vec<Vec<f32>>,
     volume: f32,
     pitch: f32,
     smoothed_volume: f32,
-    smoothed_volume_delta: f32,
+    smoothed_pitch: f32,
     output_buffer: VecDeque<f32>,
 }
 
 impl ResampledLoopAudio {
     pub fn new(
-        source: AudioSource,
+        source: Arc<AudioSource>,
         output_sample_rate: u32,
         chunk_size: usize,
+        quality: ResamplerQuality,
     ) -> anyhow::Result<Self> {
         let channels = source.channels;
-        let resampler = FastFixedIn::new(
-            output_sample_rate as f64 / source.sample_rate as f64,
-            10.0,
-            rubato::PolynomialDegree::Cubic,
-            chunk_size,
-            channels,
-        )?;
+        let ratio = output_sample_rate as f64 / source.sample_rate as f64;
+        let resampler: Box<dyn VecResampler<f32>> = match quality {
+            ResamplerQuality::Fast => Box::new(FastFixedIn::new(
+                ratio,
+                MAX_RELATIVE_RATIO,
+                rubato::PolynomialDegree::Cubic,
+                chunk_size,
+                channels,
+            )?),
+            ResamplerQuality::Sinc => Box::new(SincFixedIn::new(
+                ratio,
+                MAX_RELATIVE_RATIO,
+                rubato::SincInterpolationParameters {
+                    sinc_len: 128,
+                    f_cutoff: 0.925,
+                    interpolation: rubato::SincInterpolationType::Cubic,
+                    oversampling_factor: 128,
+                    window: rubato::WindowFunction::BlackmanHarris2,
+                },
+                chunk_size,
+                channels,
+            )?),
+        };
         let resampler_input_buffer = resampler.input_buffer_allocate(true);
         let resampler_output_buffer = resampler.output_buffer_allocate(true);
-        let output_buffer_size = resampler_output_buffer.iter().map(|b| b.len()).sum();
+        // コールバック内で伸ばさないよう、1 ブロック分と 1 回のリサンプル分を確保
+        let output_buffer_size = resampler_output_buffer
+            .iter()
+            .map(|b| b.len())
+            .sum::<usize>()
+            + MAX_BLOCK_FRAMES * channels;
         Ok(Self {
             source,
             source_cursor: 0,
+            source_fraction: 0.0,
+            loop_region: LoopRegion::default(),
+            smoothing: Smoothing::default(),
+            output_sample_rate,
             resampler,
             resampler_input_buffer,
             resampler_output_buffer,
             volume: 0.0,
             pitch: 1.0,
             smoothed_volume: 0.0,
-            smoothed_volume_delta: 1.0 / output_sample_rate as f32,
+            smoothed_pitch: 1.0,
             output_buffer: VecDeque::with_capacity(output_buffer_size),
         })
     }
 
     pub fn set_volume_pitch(&mut self, volume: f32, pitch: f32) {
         self.volume = volume;
-        self.pitch = pitch;
+        // 範囲外のピッチは端に揃える (UI 側で警告する)
+        if pitch.is_finite() {
+            self.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        }
+    }
+
+    pub fn set_loop_region(&mut self, loop_region: LoopRegion) {
+        self.loop_region = loop_region;
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoothing = smoothing;
     }
 
     pub fn write_data_additive<T>(&mut self, output: &mut [T])
@@ -57,24 +132,32 @@ impl ResampledLoopAudio {
     {
         // バッファが足りなければ取得
         while self.output_buffer.len() < output.len() {
-            // 先のフレームをsourceから取得 端までいったらループ
-            let c = self.source_cursor;
-            let l = self.source.len;
-            for (buffer, source) in self
-                .resampler_input_buffer
-                .iter_mut()
-                .zip(self.source.samples.iter())
-            {
-                for (i, b) in buffer.iter_mut().enumerate() {
-                    *b = source[(c + i) % l];
-                }
+            let len = self
+                .source
+                .samples
+                .iter()
+                .map(|s| s.len())
+                .fold(self.source.len, usize::min);
+            if len == 0 {
+                break;
             }
-            self.source_cursor = (c + self.resampler_input_buffer[0].len()) % l;
+
+            // ピッチをなめらかに目標へ近づけ、チャンク内はリサンプラーが直線補間する
+            let chunk_seconds = self.resampler_input_buffer[0].len() as f32
+                / (self.source.sample_rate as f32 * self.smoothed_pitch.max(MIN_PITCH));
+            self.smoothed_pitch =
+                self.smoothing
+                    .glide_pitch(self.smoothed_pitch, self.pitch, chunk_seconds);
+
+            // リサンプラーの範囲を超える分は、先に間引き・補間して読み出す速さを変える
+            let step = Self::pre_step(self.smoothed_pitch);
+            self.fill_input(len, step);
 
             // リサンプル
+            let ratio = step / self.smoothed_pitch as f64;
             if let Ok(result) = self
                 .resampler
-                .set_resample_ratio_relative(1.0 / self.pitch as f64, true)
+                .set_resample_ratio_relative(ratio, true)
                 .and_then(|_| {
                     self.resampler.process_into_buffer(
                         &self.resampler_input_buffer,
@@ -91,16 +174,118 @@ impl ResampledLoopAudio {
                     }
                 }
             } else {
-                break;
+                // 失敗したチャンクも直線補間で埋めて音を途切れさせない
+                self.push_linear(ratio);
             }
         }
 
-        let dv = self.smoothed_volume_delta;
-        // バッファから出力へ移す
-        for o in output.iter_mut() {
+        let (up, down) = self.smoothing.volume_deltas(self.output_sample_rate);
+        // バッファから出力へ移す (音量はフレームごとに変える)
+        for frame in output.chunks_exact_mut(self.source.channels) {
             let v = self.smoothed_volume;
-            *o += T::from_sample(self.output_buffer.pop_front().unwrap_or_default() * v);
-            self.smoothed_volume = self.volume.clamp(v - dv, v + dv);
+            for o in frame {
+                *o += T::from_sample(self.output_buffer.pop_front().unwrap_or_default() * v);
+            }
+            self.smoothed_volume = self.volume.clamp(v - down, v + up);
+        }
+    }
+
+    /// リサンプラーの代わりに、入力を直線補間して出力バッファへ積む
+    fn push_linear(&mut self, relative_ratio: f64) {
+        let ratio =
+            self.output_sample_rate as f64 / self.source.sample_rate as f64 * relative_ratio;
+        let input_len = self.resampler_input_buffer[0].len();
+        let last = input_len.saturating_sub(1);
+        let output_len = (input_len as f64 * ratio) as usize;
+        for j in 0..output_len {
+            let pos = j as f64 / ratio;
+            let i = (pos as usize).min(last);
+            let t = (pos - i as f64) as f32;
+            for channel in &self.resampler_input_buffer {
+                let (a, b) = (channel[i], channel[(i + 1).min(last)]);
+                self.output_buffer.push_back(a + (b - a) * t);
+            }
+        }
+    }
+
+    /// 元音声を何サンプルずつ進めて読むか (2 のべき乗)
+    fn pre_step(pitch: f32) -> f64 {
+        if pitch > RESAMPLER_PITCH_LIMIT {
+            2f64.powf((pitch / RESAMPLER_PITCH_LIMIT).log2().ceil() as f64)
+        } else if pitch < 1.0 / RESAMPLER_PITCH_LIMIT {
+            0.5f64.powf((1.0 / (pitch * RESAMPLER_PITCH_LIMIT)).log2().ceil() as f64)
+        } else {
+            1.0
+        }
+    }
+
+    /// 先のフレームをsourceから取得 ループ終端までいったらループ開始点へ
+    fn fill_input(&mut self, len: usize, step: f64) {
+        let (start, end, crossfade) = self.loop_region.resolve(len);
+        let next = |c: usize| if c + 1 >= end { start } else { c + 1 };
+        let mut c = if self.source_cursor >= end {
+            start
+        } else {
+            self.source_cursor
+        };
+
+        for i in 0..self.resampler_input_buffer[0].len() {
+            if step >= 1.0 {
+                // 間引き: 区間の平均をとって折り返し雑音を抑える
+                let n = step as usize;
+                for (buffer, source) in self
+                    .resampler_input_buffer
+                    .iter_mut()
+                    .zip(self.source.samples.iter())
+                {
+                    let mut k = c;
+                    let mut sum = 0.0;
+                    for _ in 0..n {
+                        sum += Self::sample_with_crossfade(source, k, start, end, crossfade);
+                        k = next(k);
+                    }
+                    buffer[i] = sum / n as f32;
+                }
+                for _ in 0..n {
+                    c = next(c);
+                }
+                self.source_fraction = 0.0;
+            } else {
+                // 補間: 隣のサンプルと直線補間してゆっくり進む
+                let t = self.source_fraction as f32;
+                for (buffer, source) in self
+                    .resampler_input_buffer
+                    .iter_mut()
+                    .zip(self.source.samples.iter())
+                {
+                    let a = Self::sample_with_crossfade(source, c, start, end, crossfade);
+                    let b = Self::sample_with_crossfade(source, next(c), start, end, crossfade);
+                    buffer[i] = a * (1.0 - t) + b * t;
+                }
+                self.source_fraction += step;
+                while self.source_fraction >= 1.0 {
+                    self.source_fraction -= 1.0;
+                    c = next(c);
+                }
+            }
+        }
+        self.source_cursor = c;
+    }
+
+    fn sample_with_crossfade(
+        source: &[f32],
+        cursor: usize,
+        start: usize,
+        end: usize,
+        crossfade: usize,
+    ) -> f32 {
+        let fade_start = end - crossfade;
+        if cursor < fade_start {
+            return source[cursor];
         }
+        // ループ終端の手前をループ開始点の手前と重ねて継ぎ目を消す
+        let k = cursor - fade_start;
+        let t = (k as f32 + 0.5) / crossfade as f32;
+        source[cursor] * (1.0 - t) + source[start - crossfade + k] * t
     }
 }
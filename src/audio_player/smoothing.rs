@@ -0,0 +1,47 @@
+/// 音量・ピッチの変化にかける時間 (秒)
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Smoothing {
+    /// 音量 0 から 1 まで上がるのにかかる時間
+    pub attack: f32,
+    /// 音量 1 から 0 まで下がるのにかかる時間
+    pub release: f32,
+    /// ピッチが目標に近づく時定数
+    pub glide: f32,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self {
+            attack: 1.0,
+            release: 1.0,
+            glide: 0.0,
+        }
+    }
+}
+
+impl Smoothing {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// 1 サンプルあたりの音量変化の上限 (上昇, 下降)
+    pub fn volume_deltas(&self, sample_rate: u32) -> (f32, f32) {
+        let delta = |seconds: f32| {
+            if seconds > 0.0 {
+                1.0 / (seconds * sample_rate as f32)
+            } else {
+                f32::INFINITY
+            }
+        };
+        (delta(self.attack), delta(self.release))
+    }
+
+    /// `dt` 秒後のピッチ
+    pub fn glide_pitch(&self, current: f32, target: f32, dt: f32) -> f32 {
+        if self.glide > 0.0 && current.is_finite() {
+            target + (current - target) * (-dt / self.glide).exp()
+        } else {
+            target
+        }
+    }
+}
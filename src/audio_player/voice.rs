@@ -0,0 +1,119 @@
+use super::{
+    AudioSource, HarmonicParams, HarmonicSynth, NoiseParams, NoiseSynth, OneShotAudio,
+    OutputConfig, PwmParams, PwmSynth, ResampledLoopAudio, ResamplerQuality, VoiceParams,
+};
+use std::{ops::AddAssign, sync::Arc};
+
+/// 音声ファイルを使わずに合成する音の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SynthKind {
+    Pwm,
+    Harmonic,
+    Noise,
+}
+
+/// 合成音のパラメータ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SynthParams {
+    Pwm(PwmParams),
+    Harmonic(HarmonicParams),
+    Noise(NoiseParams),
+}
+
+/// Mixer が鳴らす音声
+pub enum Voice {
+    Sample(ResampledLoopAudio),
+    OneShot(OneShotAudio),
+    Pwm(PwmSynth),
+    Harmonic(Box<HarmonicSynth>),
+    Noise(NoiseSynth),
+}
+
+impl Voice {
+    pub fn sample(
+        source: Arc<AudioSource>,
+        output_sample_rate: u32,
+        chunk_size: usize,
+        quality: ResamplerQuality,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::Sample(ResampledLoopAudio::new(
+            source,
+            output_sample_rate,
+            chunk_size,
+            quality,
+        )?))
+    }
+
+    pub fn one_shot(source: Arc<AudioSource>, output_sample_rate: u32) -> Self {
+        Self::OneShot(OneShotAudio::new(source, output_sample_rate))
+    }
+
+    pub fn synth(kind: SynthKind, config: OutputConfig) -> Self {
+        match kind {
+            SynthKind::Pwm => {
+                Self::Pwm(PwmSynth::new(config.sample_rate, config.channels as usize))
+            }
+            SynthKind::Harmonic => Self::Harmonic(Box::new(HarmonicSynth::new(
+                config.sample_rate,
+                config.channels as usize,
+            ))),
+            SynthKind::Noise => Self::Noise(NoiseSynth::new(
+                config.sample_rate,
+                config.channels as usize,
+            )),
+        }
+    }
+
+    pub fn apply_params(&mut self, params: &VoiceParams) {
+        match self {
+            Self::Sample(audio) => {
+                audio.set_volume_pitch(params.volume, params.pitch);
+                audio.set_loop_region(params.loop_region);
+                audio.set_smoothing(params.smoothing);
+            }
+            Self::OneShot(audio) => {
+                if params.trigger {
+                    audio.trigger(params.volume, params.pitch);
+                }
+            }
+            Self::Pwm(synth) => {
+                synth.set_volume_pitch(params.volume, params.pitch);
+                synth.set_smoothing(params.smoothing);
+                if let Some(SynthParams::Pwm(pwm)) = params.synth {
+                    synth.set_params(pwm);
+                }
+            }
+            Self::Harmonic(synth) => {
+                synth.set_volume_pitch(params.volume, params.pitch);
+                synth.set_smoothing(params.smoothing);
+                if let Some(SynthParams::Harmonic(harmonic)) = params.synth {
+                    synth.set_params(harmonic);
+                }
+            }
+            Self::Noise(synth) => {
+                synth.set_volume_pitch(params.volume, params.pitch);
+                synth.set_smoothing(params.smoothing);
+                if let Some(SynthParams::Noise(noise)) = params.synth {
+                    synth.set_params(noise);
+                }
+                // 合図は継ぎ目を踏んだとき
+                if params.trigger {
+                    synth.trigger_joint();
+                }
+            }
+        }
+    }
+
+    pub fn write_data_additive<T>(&mut self, output: &mut [T])
+    where
+        T: cpal::Sample + cpal::FromSample<f32> + AddAssign,
+    {
+        match self {
+            Self::Sample(audio) => audio.write_data_additive(output),
+            Self::OneShot(audio) => audio.write_data_additive(output),
+            Self::Pwm(synth) => synth.write_data_additive(output),
+            Self::Harmonic(synth) => synth.write_data_additive(output),
+            Self::Noise(synth) => synth.write_data_additive(output),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::audio_player::AudioFormat;
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use rfd::FileDialog;
 use std::path::PathBuf;
@@ -14,8 +15,17 @@ fn filter_project(dialog: FileDialog) -> FileDialog {
     dialog.add_filter("SW VVVF Project", &["swvf"])
 }
 
-fn filter_ogg(dialog: FileDialog) -> FileDialog {
-    dialog.add_filter("Ogg Vorbis File", &["ogg"])
+fn filter_audio(dialog: FileDialog) -> FileDialog {
+    dialog
+        .add_filter("Audio File", &AudioFormat::EXTENSIONS)
+        .add_filter("Ogg Vorbis File", &["ogg"])
+        .add_filter("WAV File", &["wav"])
+        .add_filter("FLAC File", &["flac"])
+        .add_filter("MP3 File", &["mp3"])
+}
+
+fn filter_wav(dialog: FileDialog) -> FileDialog {
+    dialog.add_filter("WAV File", &["wav"])
 }
 
 pub fn save_project_dialog<W: HasWindowHandle + HasDisplayHandle>(
@@ -30,8 +40,14 @@ pub fn open_project_dialog<W: HasWindowHandle + HasDisplayHandle>(
     filter_project(dialog_with_parent(parent)).pick_file()
 }
 
-pub fn add_ogg_dialog<W: HasWindowHandle + HasDisplayHandle>(
+pub fn save_wav_dialog<W: HasWindowHandle + HasDisplayHandle>(
+    parent: Option<&W>,
+) -> Option<PathBuf> {
+    filter_wav(dialog_with_parent(parent)).save_file()
+}
+
+pub fn add_audio_dialog<W: HasWindowHandle + HasDisplayHandle>(
     parent: Option<&W>,
 ) -> Option<Vec<PathBuf>> {
-    filter_ogg(dialog_with_parent(parent)).pick_files()
+    filter_audio(dialog_with_parent(parent)).pick_files()
 }
@@ -0,0 +1,419 @@
+use anyhow::{Context as _, bail, ensure};
+
+/// 最初に区切る数 (細い山を見落とさないように)
+const BAKE_INITIAL_SEGMENTS: usize = 16;
+/// 区間を半分にする最大の回数
+const BAKE_MAX_DEPTH: u32 = 10;
+
+/// 関数を直線でつないだ点にする
+///
+/// 直線からのずれが `tolerance` を超えるところを細かく区切って調べ、
+/// ずれが `tolerance` 以内に収まる範囲で点を間引く。値を持たない x は飛ばす。
+pub fn bake_points(f: impl Fn(f64) -> f64, range: (f64, f64), tolerance: f64) -> Vec<(f64, f64)> {
+    let (x0, x1) = (range.0.min(range.1), range.0.max(range.1));
+    // 調べた点の間のずれと、間引いたときのずれを合わせて tolerance 以内にする
+    let (sample_tolerance, simplify_tolerance) = (tolerance / 4.0, tolerance * 3.0 / 4.0);
+    let sample = |x: f64| (x, f(x));
+    let mut samples = vec![sample(x0)];
+    if x1 > x0 {
+        let step = (x1 - x0) / BAKE_INITIAL_SEGMENTS as f64;
+        for i in 1..=BAKE_INITIAL_SEGMENTS {
+            let b = sample(x0 + step * i as f64);
+            let a = samples[samples.len() - 1];
+            refine(
+                &sample,
+                a,
+                b,
+                sample_tolerance,
+                BAKE_MAX_DEPTH,
+                &mut samples,
+            );
+            samples.push(b);
+        }
+    }
+    samples.retain(|p| p.1.is_finite());
+    simplify(&samples, simplify_tolerance)
+}
+
+/// a と b の間で直線からずれている所に点を足す
+fn refine(
+    sample: &impl Fn(f64) -> (f64, f64),
+    a: (f64, f64),
+    b: (f64, f64),
+    tolerance: f64,
+    depth: u32,
+    samples: &mut Vec<(f64, f64)>,
+) {
+    if depth == 0 {
+        return;
+    }
+    let m = sample((a.0 + b.0) / 2.0);
+    // 中点だけだと対称な形を見落とすので 1/4 の所も見る
+    let q0 = sample((3.0 * a.0 + b.0) / 4.0);
+    let q1 = sample((a.0 + 3.0 * b.0) / 4.0);
+    let straight = [q0, m, q1]
+        .iter()
+        .all(|p| line_distance(a, b, *p) <= tolerance);
+    if straight {
+        return;
+    }
+    refine(sample, a, m, tolerance, depth - 1, samples);
+    samples.push(m);
+    refine(sample, m, b, tolerance, depth - 1, samples);
+}
+
+/// 直線 a-b の上の値と p の値の差 (値を持たない点があれば無限大)
+fn line_distance(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> f64 {
+    let d = if b.0 > a.0 {
+        (a.1 + (b.1 - a.1) * (p.0 - a.0) / (b.0 - a.0) - p.1).abs()
+    } else {
+        (a.1 - p.1).abs()
+    };
+    if d.is_nan() { f64::INFINITY } else { d }
+}
+
+/// 直線でつないだときのずれが `tolerance` 以内になる範囲で点を減らす (Ramer-Douglas-Peucker)
+fn simplify(points: &[(f64, f64)], tolerance: f64) -> Vec<(f64, f64)> {
+    let Some((&first, rest)) = points.split_first() else {
+        return vec![];
+    };
+    if rest.is_empty() {
+        return vec![first];
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((i0, i1)) = stack.pop() {
+        let farthest = (i0 + 1..i1)
+            .map(|i| (i, line_distance(points[i0], points[i1], points[i])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, d)) = farthest {
+            if d > tolerance {
+                keep[i] = true;
+                stack.push((i0, i));
+                stack.push((i, i1));
+            }
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(p, k)| k.then_some(*p))
+        .collect()
+}
+
+/// 点に当てはめる式の形
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitFamily {
+    /// `a + b x + c x^2 + ...` (次数)
+    Polynomial(usize),
+    /// `a x^b`
+    #[default]
+    PowerLaw,
+    /// `(a + b x) / (1 + c x)`
+    Rational,
+}
+
+impl FitFamily {
+    pub const MAX_DEGREE: usize = 5;
+
+    pub fn label_text(self) -> String {
+        match self {
+            Self::Polynomial(1) => "Linear".to_owned(),
+            Self::Polynomial(degree) => format!("Polynomial (degree {degree})"),
+            Self::PowerLaw => "Power law".to_owned(),
+            Self::Rational => "Rational".to_owned(),
+        }
+    }
+
+    pub fn all() -> impl Iterator<Item = Self> {
+        (1..=Self::MAX_DEGREE)
+            .map(Self::Polynomial)
+            .chain([Self::PowerLaw, Self::Rational])
+    }
+}
+
+/// 当てはめた式と、点からのずれ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    pub expression: String,
+    /// 点での誤差の二乗平均平方根
+    pub rms_error: f64,
+    /// 点での誤差の最大
+    pub max_error: f64,
+}
+
+/// 点に式を当てはめる (最小二乗法)
+///
+/// 誤差は書き出した式 (丸めた係数) で求める。
+pub fn fit_points(points: &[(f64, f64)], family: FitFamily) -> anyhow::Result<Fit> {
+    let expression = match family {
+        FitFamily::Polynomial(degree) => {
+            let (coefficients, scale) = fit_polynomial(points, degree)?;
+            // 2 次以上は x をそのまま使うと係数の桁が大きく違い、丸めると合わなくなる
+            let variable = if degree >= 2 && scale != 1.0 {
+                format!("(x / {})", format_number(scale))
+            } else {
+                "x".to_owned()
+            };
+            polynomial_expression(&coefficients, &variable)
+        }
+        FitFamily::PowerLaw => {
+            let (a, b) = fit_power_law(points)?;
+            format!("{} * x^({})", format_number(a), format_number(b))
+        }
+        FitFamily::Rational => {
+            let (a, b, c) = fit_rational(points)?;
+            format!(
+                "({}) / (1{})",
+                polynomial_expression(&[a, b], "x"),
+                signed_term(c, "x")
+            )
+        }
+    };
+
+    let f = expression
+        .parse::<meval::Expr>()
+        .and_then(|expr| expr.bind("x"))
+        .context("Unable to evaluate the fitted expression")?;
+    let errors: Vec<f64> = points.iter().map(|p| (f(p.0) - p.1).abs()).collect();
+    ensure!(
+        errors.iter().all(|e| e.is_finite()),
+        "The fitted expression has no value at some points"
+    );
+    let rms_error = (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt();
+    let max_error = errors.iter().copied().fold(0.0, f64::max);
+    Ok(Fit {
+        expression,
+        rms_error,
+        max_error,
+    })
+}
+
+/// `x / scale` についての多項式の係数 (定数項から) と scale
+fn fit_polynomial(points: &[(f64, f64)], degree: usize) -> anyhow::Result<(Vec<f64>, f64)> {
+    ensure!(
+        points.len() > degree,
+        "At least {} points are needed for degree {degree}",
+        degree + 1
+    );
+    // 桁が大きく違うと解けなくなるので x を -1..1 程度にしてから解く
+    // 式に書いたときと同じ値になるように丸めておく
+    let scale = points.iter().map(|p| p.0.abs()).fold(0.0, f64::max);
+    let scale = if scale > 0.0 {
+        format_number(scale).parse().unwrap_or(scale)
+    } else {
+        1.0
+    };
+    let rows: Vec<(Vec<f64>, f64)> = points
+        .iter()
+        .map(|p| {
+            let t = p.0 / scale;
+            ((0..=degree).map(|k| t.powi(k as i32)).collect(), p.1)
+        })
+        .collect();
+    let coefficients = least_squares(&rows)?;
+    if degree >= 2 {
+        return Ok((coefficients, scale));
+    }
+    let coefficients = coefficients
+        .iter()
+        .enumerate()
+        .map(|(k, c)| c / scale.powi(k as i32))
+        .collect();
+    Ok((coefficients, 1.0))
+}
+
+/// `a x^b` の (a, b)
+fn fit_power_law(points: &[(f64, f64)]) -> anyhow::Result<(f64, f64)> {
+    // x = 0 の点は b > 0 なら 0 になるので当てはめには使わない
+    let positive: Vec<(f64, f64)> = points.iter().copied().filter(|p| p.0 > 0.0).collect();
+    ensure!(
+        positive.iter().all(|p| p.1 > 0.0),
+        "A power law needs positive values where x is positive"
+    );
+    ensure!(
+        positive.len() >= 2,
+        "At least 2 points with positive x are needed"
+    );
+    let rows: Vec<(Vec<f64>, f64)> = positive
+        .iter()
+        .map(|p| (vec![1.0, p.0.ln()], p.1.ln()))
+        .collect();
+    let solution = least_squares(&rows)?;
+    Ok((solution[0].exp(), solution[1]))
+}
+
+/// `(a + b x) / (1 + c x)` の (a, b, c)
+fn fit_rational(points: &[(f64, f64)]) -> anyhow::Result<(f64, f64, f64)> {
+    ensure!(points.len() >= 3, "At least 3 points are needed");
+    // y (1 + c x) = a + b x を a, b, c について解く
+    let rows: Vec<(Vec<f64>, f64)> = points
+        .iter()
+        .map(|p| (vec![1.0, p.0, -p.0 * p.1], p.1))
+        .collect();
+    let solution = least_squares(&rows)?;
+    let (a, b, c) = (solution[0], solution[1], solution[2]);
+    let (x0, x1) = points
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+            (lo.min(p.0), hi.max(p.0))
+        });
+    if 1.0 + c * x0 <= 0.0 || 1.0 + c * x1 <= 0.0 {
+        bail!("The rational fit has a pole inside the point range");
+    }
+    Ok((a, b, c))
+}
+
+/// 各行 (係数, 値) の最小二乗解 (正規方程式をピボット選択付きの掃き出し法で解く)
+fn least_squares(rows: &[(Vec<f64>, f64)]) -> anyhow::Result<Vec<f64>> {
+    let n = rows.first().context("No points to fit")?.0.len();
+    let mut matrix = vec![vec![0.0; n + 1]; n];
+    for (row, value) in rows {
+        for i in 0..n {
+            for j in 0..n {
+                matrix[i][j] += row[i] * row[j];
+            }
+            matrix[i][n] += row[i] * value;
+        }
+    }
+
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|a, b| matrix[*a][col].abs().total_cmp(&matrix[*b][col].abs()))
+            .unwrap_or(col);
+        ensure!(
+            matrix[pivot][col].abs() > 1e-12,
+            "The points do not determine the expression (try fewer terms)"
+        );
+        matrix.swap(col, pivot);
+        for row in 0..n {
+            if row != col {
+                let factor = matrix[row][col] / matrix[col][col];
+                for k in col..=n {
+                    matrix[row][k] -= factor * matrix[col][k];
+                }
+            }
+        }
+    }
+    Ok((0..n).map(|i| matrix[i][n] / matrix[i][i]).collect())
+}
+
+/// `c0 + c1 * x + c2 * x^2 ...` (x の代わりに `variable` を書く)
+fn polynomial_expression(coefficients: &[f64], variable: &str) -> String {
+    let mut expression = format_number(coefficients.first().copied().unwrap_or(0.0));
+    for (k, c) in coefficients.iter().enumerate().skip(1) {
+        let power = if k == 1 {
+            variable.to_owned()
+        } else {
+            format!("{variable}^{k}")
+        };
+        expression += &signed_term(*c, &power);
+    }
+    expression
+}
+
+/// ` + c * name` または ` - |c| * name`
+fn signed_term(c: f64, name: &str) -> String {
+    let sign = if c < 0.0 { '-' } else { '+' };
+    format!(" {sign} {} * {name}", format_number(c.abs()))
+}
+
+/// 式に書く数 (有効数字 6 桁)
+fn format_number(v: f64) -> String {
+    if v == 0.0 || !v.is_finite() {
+        return "0".to_owned();
+    }
+    let magnitude = v.abs().log10().floor();
+    if !(-4.0..9.0).contains(&magnitude) {
+        let text = format!("{v:.5e}");
+        return match text.split_once('e') {
+            Some((mantissa, exponent)) => format!("{}e{exponent}", trim_zeros(mantissa)),
+            None => text,
+        };
+    }
+    let decimals = (5.0 - magnitude).max(0.0) as usize;
+    trim_zeros(&format!("{v:.decimals$}")).to_owned()
+}
+
+/// 小数点以下の末尾の 0 を取る
+fn trim_zeros(text: &str) -> &str {
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(f: impl Fn(f64) -> f64, xs: impl Iterator<Item = f64>) -> Vec<(f64, f64)> {
+        xs.map(|x| (x, f(x))).collect()
+    }
+
+    /// 書き出した式が点をほぼ通る (丸めによるずれは値の大きさに比べて小さい)
+    fn assert_round_trip(points: &[(f64, f64)], family: FitFamily, relative: f64) {
+        let fit = fit_points(points, family).expect("fit");
+        let magnitude = points.iter().map(|p| p.1.abs()).fold(0.0, f64::max);
+        assert!(
+            fit.max_error <= relative * magnitude,
+            "{}: max error {} for values up to {magnitude}",
+            fit.expression,
+            fit.max_error
+        );
+        assert!(fit.rms_error <= fit.max_error);
+    }
+
+    #[test]
+    fn polynomial_round_trip() {
+        let xs = || (0..=24).map(|i| f64::from(i) * 5.0);
+        let line = samples(|x| 3.0 * x + 1.0, xs());
+        assert_round_trip(&line, FitFamily::Polynomial(1), 1e-9);
+        let cubic = samples(|x| 5.0 - 0.3 * x + 0.02 * x * x - 1e-4 * x.powi(3), xs());
+        assert_round_trip(&cubic, FitFamily::Polynomial(3), 1e-5);
+        let quintic = samples(
+            |x| 1000.0 + 2.0 * x - 1e-3 * x.powi(3) + 3e-8 * x.powi(5),
+            xs(),
+        );
+        assert_round_trip(&quintic, FitFamily::Polynomial(5), 1e-5);
+    }
+
+    #[test]
+    fn power_law_round_trip() {
+        let xs = || (1..=20).map(|i| f64::from(i) * 5.0);
+        assert_round_trip(
+            &samples(|x| 2.5 * x.powf(1.7), xs()),
+            FitFamily::PowerLaw,
+            1e-5,
+        );
+        assert_round_trip(
+            &samples(|x| 4.0 * x.powf(-0.5), xs()),
+            FitFamily::PowerLaw,
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn rational_round_trip() {
+        let points = samples(
+            |x| (1.0 + 2.0 * x) / (1.0 + 0.05 * x),
+            (0..=20).map(|i| f64::from(i) * 5.0),
+        );
+        assert_round_trip(&points, FitFamily::Rational, 1e-5);
+    }
+
+    #[test]
+    fn bake_stays_within_tolerance() {
+        let f = |x: f64| (x / 10.0).sin() * 50.0;
+        let tolerance = 0.1;
+        let points = bake_points(f, (0.0, 100.0), tolerance);
+        for i in 0..=1000 {
+            let x = f64::from(i) / 10.0;
+            let segment = points.windows(2).find(|w| x <= w[1].0).expect("in range");
+            assert!(line_distance(segment[0], segment[1], (x, f(x))) <= tolerance);
+        }
+    }
+}
@@ -1,4 +1,9 @@
-use super::FuncEdit;
+use super::{
+    ExprContext, ExprError, Extrapolation, Fit, FitFamily, FuncEdit, Interpolation, Piecewise,
+    convert::{bake_points, fit_points},
+    expr_context::rename_calls,
+    interpolation::{catmull_rom_tangent, hermite, monotone_tangent, secant},
+};
 use core::panic;
 use std::str::FromStr as _;
 
@@ -6,6 +11,8 @@ use std::str::FromStr as _;
 pub enum EditableFuncMode {
     Points,
     Expression,
+    /// x の区間ごとに点と式を使い分ける
+    Piecewise,
 }
 
 type FnClamp = fn((f64, f64)) -> (f64, f64);
@@ -23,8 +30,17 @@ pub struct EditableFunc {
     pub mode: EditableFuncMode,
     #[serde(skip_serializing_if = "is_points_default", default = "points_default")]
     points: Vec<(f64, f64)>,
+    #[serde(skip_serializing_if = "Interpolation::is_linear", default)]
+    interpolation: Interpolation,
+    #[serde(skip_serializing_if = "Extrapolation::is_linear", default)]
+    pub extrapolation: Extrapolation,
+    /// ベジェ補間での点ごとの傾き (点と同じ数だけある)
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    tangents: Vec<f64>,
     #[serde(skip_serializing_if = "String::is_empty", default = "String::new")]
     pub expression: String,
+    #[serde(skip_serializing_if = "Piecewise::is_empty", default)]
+    piecewise: Piecewise,
     #[serde(skip)]
     expr_result: Option<(Result<meval::Expr, meval::Error>, String)>,
 }
@@ -34,7 +50,11 @@ impl Default for EditableFunc {
         Self {
             mode: EditableFuncMode::Points,
             points: points_default(),
+            interpolation: Interpolation::Linear,
+            extrapolation: Extrapolation::Linear,
+            tangents: Vec::new(),
             expression: String::new(),
+            piecewise: Piecewise::default(),
             expr_result: None,
         }
     }
@@ -49,16 +69,26 @@ impl FuncEdit for EditableFunc {
         &mut self.mode
     }
 
+    fn is_mode_points(&self) -> bool {
+        match self.active_piece() {
+            Some(piece) => piece.is_mode_points(),
+            None => self.mode == EditableFuncMode::Points,
+        }
+    }
+
     fn points(&self) -> Option<&Vec<(f64, f64)>> {
-        Some(&self.points)
+        match self.active_piece() {
+            Some(piece) => piece.points(),
+            None => Some(&self.points),
+        }
     }
 
-    fn value_at(&self, x: f64) -> f64 {
-        self.value_at_clamped(x, |p| p)
+    fn value_at(&self, x: f64, context: &ExprContext) -> f64 {
+        self.value_at_clamped(x, context, |p| p)
     }
 
-    fn checked_value_at(&self, x: f64) -> Option<f64> {
-        self.checked_value_at_clamped(x, |p| p)
+    fn checked_value_at(&self, x: f64, context: &ExprContext) -> Option<f64> {
+        self.checked_value_at_clamped(x, context, |p| p)
     }
 
     fn insert_point(&mut self, pos: (f64, f64)) -> (usize, (f64, f64)) {
@@ -69,7 +99,7 @@ impl FuncEdit for EditableFunc {
         self.insert_point_by_index_clamped(index, |p| p)
     }
 
-    fn split_segment(&mut self, x: f64) -> (usize, (f64, f64)) {
+    fn split_segment(&mut self, x: f64) -> Option<(usize, (f64, f64))> {
         self.split_segment_clamped(x, |p| p)
     }
 
@@ -78,24 +108,170 @@ impl FuncEdit for EditableFunc {
     }
 
     fn remove_point(&mut self, index: usize) -> Option<(f64, f64)> {
-        if self.points.len() >= 2 {
+        if let Some(piece) = self.active_piece_mut() {
+            return piece.remove_point(index);
+        }
+        if self.points.len() >= 2 && index < self.points.len() {
+            if self.has_tangents() {
+                self.tangents.remove(index);
+            }
             Some(self.points.remove(index))
         } else {
             None
         }
     }
 
+    fn interpolation(&self) -> Interpolation {
+        self.active_piece()
+            .map_or(self.interpolation, |piece| piece.interpolation)
+    }
+
+    fn set_interpolation(&mut self, interpolation: Interpolation) {
+        if let Some(piece) = self.active_piece_mut() {
+            piece.set_interpolation(interpolation);
+            return;
+        }
+        // ベジェに切り替えたときは今の形に近い傾きから始める
+        if interpolation == Interpolation::Bezier && !self.has_tangents() {
+            self.tangents = (0..self.points.len()).map(|i| self.tangent(i)).collect();
+        }
+        self.interpolation = interpolation;
+    }
+
+    fn extrapolation_mut(&mut self) -> &mut Extrapolation {
+        if let Some(active) = self.active_piece().and(self.piecewise.active_index()) {
+            return &mut self.piecewise.pieces[active].func.extrapolation;
+        }
+        &mut self.extrapolation
+    }
+
+    fn defined_range(&self) -> Option<(f64, f64)> {
+        let first = self.points.first()?;
+        let last = self.points.last()?;
+        (self.mode == EditableFuncMode::Points && self.extrapolation == Extrapolation::Nan)
+            .then_some((first.0, last.0))
+    }
+
+    fn tangent_handles(&self) -> Vec<(usize, (f64, f64))> {
+        if let Some(piece) = self.active_piece() {
+            return piece.tangent_handles();
+        }
+        if self.mode != EditableFuncMode::Points || self.interpolation != Interpolation::Bezier {
+            return vec![];
+        }
+        // ハンドルは隣の点までの 1/3 の位置 (ベジェの制御点)
+        let mut handles = vec![];
+        for (i, p) in self.points.iter().enumerate() {
+            let m = self.tangent(i);
+            if let Some(prev) = i.checked_sub(1).and_then(|j| self.points.get(j)) {
+                let d = (p.0 - prev.0) / 3.0;
+                handles.push((i, (p.0 - d, p.1 - m * d)));
+            }
+            if let Some(next) = self.points.get(i + 1) {
+                let d = (next.0 - p.0) / 3.0;
+                handles.push((i, (p.0 + d, p.1 + m * d)));
+            }
+        }
+        handles
+    }
+
+    fn move_tangent_handle(&mut self, index: usize, pos: (f64, f64)) {
+        if let Some(piece) = self.active_piece_mut() {
+            piece.move_tangent_handle(index, pos);
+            return;
+        }
+        let Some(p) = self.points.get(index).copied() else {
+            return;
+        };
+        let dx = pos.0 - p.0;
+        if dx.abs() < f64::EPSILON {
+            return;
+        }
+        if !self.has_tangents() {
+            self.tangents = (0..self.points.len()).map(|i| self.tangent(i)).collect();
+        }
+        self.tangents[index] = (pos.1 - p.1) / dx;
+    }
+
+    fn piecewise_mut(&mut self) -> Option<&mut Piecewise> {
+        if self.mode != EditableFuncMode::Piecewise {
+            return None;
+        }
+        // 初めて区分けするときは今の点の関数から始める
+        if self.piecewise.is_empty() {
+            self.piecewise = Piecewise::with_func(Self {
+                piecewise: Piecewise::default(),
+                expr_result: None,
+                mode: EditableFuncMode::Points,
+                ..self.clone()
+            });
+        }
+        Some(&mut self.piecewise)
+    }
+
+    fn piece_starts(&self) -> Vec<(usize, f64)> {
+        if self.mode != EditableFuncMode::Piecewise {
+            return vec![];
+        }
+        self.piecewise
+            .pieces
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, p)| (i, p.start))
+            .collect()
+    }
+
+    fn move_piece_start(&mut self, index: usize, x: f64) {
+        self.piecewise.move_start(index, x);
+    }
+
     fn expression_mut(&mut self) -> Option<&mut String> {
+        if let Some(active) = self.active_piece().and(self.piecewise.active_index()) {
+            return self.piecewise.pieces[active].func.expression_mut();
+        }
         Some(&mut self.expression)
     }
 
-    fn expression_err(&self) -> Option<&meval::Error> {
-        self.expr_result
-            .as_ref()
-            .and_then(|(e, _)| e.as_ref().err())
+    fn expression_err(&self, context: &ExprContext) -> Option<ExprError> {
+        if let Some(piece) = self.active_piece() {
+            return piece.expression_err(context);
+        }
+        let (result, expression) = self.expr_result.as_ref()?;
+        let expr = match result {
+            Ok(expr) => expr,
+            Err(e) => return Some(ExprError::new(e, expression)),
+        };
+        if let Some((reference, path)) = context.find_cycle(&Self::expr_references(expr)) {
+            return Some(ExprError::cycle(&reference, &path, expression));
+        }
+        // 知らない名前などの x によらない誤りは試しに計算して見つける
+        context
+            .eval(expr, 1.0)
+            .err()
+            .map(|e| ExprError::new(&e, expression))
+    }
+
+    fn references(&self) -> Vec<String> {
+        let own = match (&self.mode, &self.expr_result) {
+            (EditableFuncMode::Expression, Some((Ok(expr), _))) => Self::expr_references(expr),
+            _ => vec![],
+        };
+        let pieces = self
+            .piecewise
+            .pieces
+            .iter()
+            .flat_map(|p| p.func.references());
+        match self.mode {
+            EditableFuncMode::Piecewise => own.into_iter().chain(pieces).collect(),
+            _ => own,
+        }
     }
 
     fn update_expression(&mut self) {
+        for piece in &mut self.piecewise.pieces {
+            piece.func.update_expression();
+        }
         if matches!(self.mode, EditableFuncMode::Expression)
             && (self.expression.is_empty() != self.expr_result.is_none()
                 || self
@@ -113,9 +289,42 @@ impl FuncEdit for EditableFunc {
             }
         }
     }
+
+    fn rename_references(&mut self, rename: &dyn Fn(&str) -> Option<String>) {
+        for piece in &mut self.piecewise.pieces {
+            piece.func.rename_references(rename);
+        }
+        self.expression = rename_calls(&self.expression, rename);
+        self.update_expression();
+    }
+
+    fn bake_to_points(&mut self, range: (f64, f64), tolerance: f64, context: &ExprContext) -> bool {
+        self.bake_to_points_clamped(range, tolerance, context, |p| p)
+    }
+
+    fn fit_expression(&mut self, family: FitFamily) -> anyhow::Result<Fit> {
+        if let Some(piece) = self.active_piece_mut() {
+            return piece.fit_expression(family);
+        }
+        let fit = fit_points(&self.points, family)?;
+        self.expression.clone_from(&fit.expression);
+        self.mode = EditableFuncMode::Expression;
+        self.update_expression();
+        Ok(fit)
+    }
 }
 
 impl EditableFunc {
+    /// 式の中で呼んでいる関数 (曲線かもしれない) の名前
+    fn expr_references(expr: &meval::Expr) -> Vec<String> {
+        expr.iter()
+            .filter_map(|token| match token {
+                meval::tokenizer::Token::Func(name, _) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn with_points(points: Vec<(f64, f64)>) -> Self {
         Self {
             points,
@@ -133,39 +342,150 @@ impl EditableFunc {
         s
     }
 
-    pub(super) fn value_at_clamped(&self, x: f64, clamp: FnClamp) -> f64 {
+    /// 区分けしているときに編集している区間の関数
+    fn active_piece(&self) -> Option<&Self> {
+        if self.mode != EditableFuncMode::Piecewise {
+            return None;
+        }
+        let piecewise = &self.piecewise;
+        piecewise
+            .active_index()
+            .and_then(|i| piecewise.pieces.get(i))
+            .map(|p| &p.func)
+    }
+
+    fn active_piece_mut(&mut self) -> Option<&mut Self> {
+        if self.mode != EditableFuncMode::Piecewise {
+            return None;
+        }
+        let active = self.piecewise.active_index()?;
+        self.piecewise.pieces.get_mut(active).map(|p| &mut p.func)
+    }
+
+    pub(super) fn bake_to_points_clamped(
+        &mut self,
+        range: (f64, f64),
+        tolerance: f64,
+        context: &ExprContext,
+        clamp: FnClamp,
+    ) -> bool {
+        if let Some(piece) = self.active_piece_mut() {
+            return piece.bake_to_points_clamped(range, tolerance, context, clamp);
+        }
+        let points = bake_points(
+            |x| self.value_at_clamped(x, context, clamp),
+            range,
+            tolerance,
+        );
+        let mut points: Vec<(f64, f64)> = points.into_iter().map(clamp).collect();
+        points.dedup_by(|a, b| a.0 == b.0);
+        if points.is_empty() {
+            return false;
+        }
+        self.points = points;
+        // 点の間は直線でつないだときのずれで間引いている
+        self.interpolation = Interpolation::Linear;
+        self.tangents.clear();
+        self.mode = EditableFuncMode::Points;
+        true
+    }
+
+    pub(super) fn value_at_clamped(&self, x: f64, context: &ExprContext, clamp: FnClamp) -> f64 {
         match self.mode {
-            EditableFuncMode::Points => {
-                let i = self.find_segment(x);
-                if i == 0 {
-                    if let Some(first2) = self.points.first_chunk::<2>() {
-                        let y = Self::value_at_line(first2[0], first2[1], x);
-                        clamp((x, y)).1
-                    } else {
-                        self.points.first().map(|p| p.1).unwrap_or(0.0)
-                    }
-                } else if i >= self.points.len() {
-                    if let Some(last2) = self.points.last_chunk::<2>() {
-                        let y = Self::value_at_line(last2[0], last2[1], x);
-                        clamp((x, y)).1
-                    } else {
-                        self.points.last().map(|p| p.1).unwrap_or(0.0)
-                    }
+            EditableFuncMode::Points | EditableFuncMode::Piecewise => {
+                let y = if self.piecewise.is_empty() || self.mode == EditableFuncMode::Points {
+                    self.points_value_at(x)
                 } else {
-                    Self::value_at_line(self.points[i - 1], self.points[i], x)
-                }
+                    self.piecewise.value_at(x, context)
+                };
+                // 範囲外で値を持たないときは NaN のまま返す
+                if y.is_nan() { y } else { clamp((x, y)).1 }
             }
             EditableFuncMode::Expression => self
                 .expr_result
                 .as_ref()
                 .and_then(|(e, _)| e.as_ref().ok())
-                .and_then(|e| e.eval_with_context(meval::Context::new().var("x", x)).ok())
+                .and_then(|e| context.eval(e, x).ok())
                 .unwrap_or(f64::NAN),
         }
     }
 
-    pub(super) fn checked_value_at_clamped(&self, x: f64, clamp: FnClamp) -> Option<f64> {
-        let v = self.value_at_clamped(x, clamp);
+    /// 点の間を補間した値
+    fn points_value_at(&self, x: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return 0.0;
+        };
+        let i = self.find_segment(x);
+        if i > 0 && i < self.points.len() {
+            return self.interpolate(i, x);
+        }
+        // 最後の点ちょうどは範囲内
+        if x == last.0 {
+            return last.1;
+        }
+        match self.extrapolation {
+            Extrapolation::Hold => {
+                if i == 0 {
+                    first.1
+                } else {
+                    last.1
+                }
+            }
+            // 端の 2 点が縦に並んでいるときは端の値のまま
+            Extrapolation::Linear => match (self.points.first_chunk::<2>(), i) {
+                (Some([p0, p1]), 0) => p0.1 + secant(*p0, *p1) * (x - p0.0),
+                (Some(_), _) => {
+                    let [p0, p1] = self.points.last_chunk::<2>().expect("more than 2 points");
+                    p1.1 + secant(*p0, *p1) * (x - p1.0)
+                }
+                (None, _) => first.1,
+            },
+            Extrapolation::Repeat => {
+                let period = last.0 - first.0;
+                if period > 0.0 {
+                    let x = first.0 + (x - first.0).rem_euclid(period);
+                    let i = self.find_segment(x).clamp(1, self.points.len() - 1);
+                    self.interpolate(i, x)
+                } else {
+                    first.1
+                }
+            }
+            Extrapolation::Nan => f64::NAN,
+        }
+    }
+
+    /// `i - 1` 番目と `i` 番目の点の間の値
+    fn interpolate(&self, i: usize, x: f64) -> f64 {
+        let (p0, p1) = (self.points[i - 1], self.points[i]);
+        match self.interpolation {
+            Interpolation::Linear => Self::value_at_line(p0, p1, x),
+            Interpolation::Step => p0.1,
+            Interpolation::MonotoneCubic | Interpolation::CatmullRom | Interpolation::Bezier => {
+                hermite(p0, p1, self.tangent(i - 1), self.tangent(i), x)
+            }
+        }
+    }
+
+    /// `index` 番目の点での傾き
+    fn tangent(&self, index: usize) -> f64 {
+        match self.interpolation {
+            Interpolation::MonotoneCubic => monotone_tangent(&self.points, index),
+            Interpolation::Bezier if self.has_tangents() => self.tangents[index],
+            _ => catmull_rom_tangent(&self.points, index),
+        }
+    }
+
+    fn has_tangents(&self) -> bool {
+        !self.tangents.is_empty() && self.tangents.len() == self.points.len()
+    }
+
+    pub(super) fn checked_value_at_clamped(
+        &self,
+        x: f64,
+        context: &ExprContext,
+        clamp: FnClamp,
+    ) -> Option<f64> {
+        let v = self.value_at_clamped(x, context, clamp);
         if v.is_finite() { Some(v) } else { None }
     }
 
@@ -174,8 +494,18 @@ impl EditableFunc {
         pos: (f64, f64),
         clamp: FnClamp,
     ) -> (usize, (f64, f64)) {
+        if let Some(piece) = self.active_piece_mut() {
+            return piece.insert_point_clamped(pos, clamp);
+        }
         let pos = clamp(pos);
         let index = self.find_segment(pos.0);
+        if self.has_tangents() {
+            // 今の曲線の傾きを引き継ぐ
+            let d = 1e-3 * pos.0.abs().max(1.0);
+            let m = (self.points_value_at(pos.0 + d) - self.points_value_at(pos.0 - d)) / (2.0 * d);
+            self.tangents
+                .insert(index, if m.is_finite() { m } else { 0.0 });
+        }
         self.points.insert(index, pos);
         (index, pos)
     }
@@ -185,13 +515,16 @@ impl EditableFunc {
         index: usize,
         clamp: FnClamp,
     ) -> (usize, (f64, f64)) {
+        if let Some(piece) = self.active_piece_mut() {
+            return piece.insert_point_by_index_clamped(index, clamp);
+        }
         if index == 0 {
             if let Some(p) = self.points.first_chunk::<2>() {
                 let x0 = p[0].0;
                 let x1 = p[1].0;
-                self.split_segment_clamped(x0 - (x1 - x0), clamp)
+                self.insert_outside_clamped(x0 - (x1 - x0), clamp)
             } else if let Some(p) = self.points.first() {
-                self.split_segment_clamped(p.0 - p.0.abs() * 0.2, clamp)
+                self.insert_outside_clamped(p.0 - p.0.abs() * 0.2, clamp)
             } else {
                 panic!("EditableFunc has no point")
             }
@@ -199,21 +532,58 @@ impl EditableFunc {
             if let Some(p) = self.points.last_chunk::<2>() {
                 let x0 = p[0].0;
                 let x1 = p[1].0;
-                self.split_segment_clamped(x0 + 2.0 * (x1 - x0), clamp)
+                self.insert_outside_clamped(x0 + 2.0 * (x1 - x0), clamp)
             } else if let Some(p) = self.points.last() {
-                self.split_segment_clamped(p.0 + p.0.abs() * 0.2, clamp)
+                self.insert_outside_clamped(p.0 + p.0.abs() * 0.2, clamp)
             } else {
                 panic!("EditableFunc has no point")
             }
         } else if let (Some(p0), Some(p1)) = (self.points.get(index - 1), self.points.get(index)) {
-            self.split_segment_clamped((p0.0 + p1.0) / 2.0, clamp)
+            self.insert_on_curve_clamped((p0.0 + p1.0) / 2.0, clamp)
         } else {
             panic!("EditableFunc has no point")
         }
     }
 
-    pub(super) fn split_segment_clamped(&mut self, x: f64, clamp: FnClamp) -> (usize, (f64, f64)) {
-        self.insert_point_clamped((x, self.value_at_clamped(x, clamp)), clamp)
+    /// 端の外側に点を追加する (直線で伸ばすとき以外は端の値を使う)
+    fn insert_outside_clamped(&mut self, x: f64, clamp: FnClamp) -> (usize, (f64, f64)) {
+        if self.extrapolation == Extrapolation::Linear {
+            return self.insert_on_curve_clamped(x, clamp);
+        }
+        let end = if x < self.points[0].0 {
+            self.points.first()
+        } else {
+            self.points.last()
+        };
+        let y = end.map_or(0.0, |p| p.1);
+        self.insert_point_clamped((x, y), clamp)
+    }
+
+    pub(super) fn split_segment_clamped(
+        &mut self,
+        x: f64,
+        clamp: FnClamp,
+    ) -> Option<(usize, (f64, f64))> {
+        if self.mode == EditableFuncMode::Piecewise && !self.piecewise.is_empty() {
+            // 点を追加するときは x 以外の変数を使わない
+            let y = self.value_at_clamped(x, &ExprContext::default(), clamp);
+            // クリックした区間を編集対象にし、点の区間なら線の上に点を追加する
+            self.piecewise.active = self.piecewise.piece_index(x);
+            let piece = self.active_piece_mut().filter(|p| p.is_mode_points())?;
+            return Some(if y.is_finite() {
+                piece.insert_point_clamped((x, y), clamp)
+            } else {
+                piece.insert_on_curve_clamped(x, clamp)
+            });
+        }
+        Some(self.insert_on_curve_clamped(x, clamp))
+    }
+
+    /// 今の曲線の上に点を追加する
+    fn insert_on_curve_clamped(&mut self, x: f64, clamp: FnClamp) -> (usize, (f64, f64)) {
+        // 点を追加するときは x 以外の変数を使わない
+        let y = self.value_at_clamped(x, &ExprContext::default(), clamp);
+        self.insert_point_clamped((x, y), clamp)
     }
 
     pub(super) fn move_point_to_clamped(
@@ -222,6 +592,9 @@ impl EditableFunc {
         mut pos: (f64, f64),
         clamp: FnClamp,
     ) -> Option<(f64, f64)> {
+        if let Some(piece) = self.active_piece_mut() {
+            return piece.move_point_to_clamped(index, pos, clamp);
+        }
         if let Some(left) = index.checked_sub(1).and_then(|l| self.points.get(l)) {
             pos.0 = pos.0.max(left.0);
         }
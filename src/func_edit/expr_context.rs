@@ -0,0 +1,397 @@
+use super::FuncEdit;
+use meval::{ContextProvider, FuncEvalError};
+use std::sync::Arc;
+
+/// 式で使える補助関数の書き方と説明
+pub const EXPR_HELPERS: [(&str, &str); 5] = [
+    ("clamp(v, lo, hi)", "v limited to lo..hi"),
+    ("lerp(a, b, t)", "a + (b - a) * t"),
+    (
+        "smoothstep(e0, e1, v)",
+        "Smooth 0 to 1 as v goes from e0 to e1",
+    ),
+    ("step(edge, v)", "0 below edge, 1 from edge"),
+    (
+        "piecewise(v, x0, y0, x1, y1, ...)",
+        "Straight lines through the points (x, y)",
+    ),
+];
+
+/// 式から `名前(x)` で呼べる曲線
+struct Curve {
+    name: String,
+    /// この曲線を持つ音声の番号 (曲線の式の中の `pitch` などはこの音声のものになる)
+    owner: Option<usize>,
+    func: Box<dyn FuncEdit + Send + Sync>,
+}
+
+/// 式から参照できる曲線の一覧
+#[derive(Default)]
+pub struct CurveTable {
+    curves: Vec<Curve>,
+}
+
+impl std::fmt::Debug for CurveTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.curves.iter().map(|c| &c.name))
+            .finish()
+    }
+}
+
+impl CurveTable {
+    pub fn push(
+        &mut self,
+        name: String,
+        owner: Option<usize>,
+        func: impl FuncEdit + Send + Sync + 'static,
+    ) {
+        self.curves.push(Curve {
+            name,
+            owner,
+            func: Box::new(func),
+        });
+    }
+}
+
+/// 式で `x` の他に使える変数・定数・曲線
+#[derive(Debug, Default, Clone)]
+pub struct ExprContext {
+    /// `x` に比例する変数 (名前, 係数)
+    proportional: Vec<(&'static str, f64)>,
+    /// プロジェクトで決めた定数 (名前, 値)
+    constants: Arc<Vec<(String, f64)>>,
+    curves: Arc<CurveTable>,
+    /// 式を持つ音声の番号 (`pitch` などで自分の曲線を参照するのに使う)
+    owner: Option<usize>,
+    /// 値を求めている途中の曲線 (循環参照で止まらなくならないように)
+    path: Vec<String>,
+    /// 式の x が速度でないので、x に比例する変数を使わない (参照した曲線の中では使える)
+    hide_proportional: bool,
+}
+
+impl ExprContext {
+    pub fn with_proportional(mut self, name: &'static str, coefficient: f64) -> Self {
+        self.proportional.push((name, coefficient));
+        self
+    }
+
+    pub fn with_constants(mut self, constants: Vec<(String, f64)>) -> Self {
+        self.constants = Arc::new(constants);
+        self
+    }
+
+    pub fn with_curves(mut self, curves: CurveTable) -> Self {
+        self.curves = Arc::new(curves);
+        self
+    }
+
+    /// `index` 番目の音声の式を計算するときの文脈
+    pub fn with_owner(&self, index: usize) -> Self {
+        Self {
+            owner: Some(index),
+            ..self.clone()
+        }
+    }
+
+    /// x が速度でない式 (負荷に対する倍率など) のための文脈
+    pub fn without_proportional(&self) -> Self {
+        Self {
+            hide_proportional: true,
+            ..self.clone()
+        }
+    }
+
+    pub fn constants(&self) -> &[(String, f64)] {
+        &self.constants
+    }
+
+    /// 速度 `x` のときの変数・定数の値
+    pub fn variable(&self, name: &str, x: f64) -> Option<f64> {
+        Scope { context: self, x }.get_var(name)
+    }
+
+    pub fn curve_names(&self) -> impl Iterator<Item = &str> {
+        self.curves.curves.iter().map(|c| c.name.as_str())
+    }
+
+    pub(super) fn eval(&self, expr: &meval::Expr, x: f64) -> Result<f64, meval::Error> {
+        expr.eval_with_context((Scope { context: self, x }, meval::Context::new()))
+    }
+
+    /// 名前の曲線 (無ければ持ち主の音声の `名前_番号` の曲線)
+    fn curve(&self, name: &str, owner: Option<usize>) -> Option<&Curve> {
+        let curves = &self.curves.curves;
+        curves.iter().find(|c| c.name == name).or_else(|| {
+            let name = format!("{name}_{}", owner?);
+            curves.iter().find(|c| c.name == name)
+        })
+    }
+
+    /// 式が参照している曲線をたどり、循環していれば (式の中の名前, 循環する経路) を返す
+    pub(super) fn find_cycle(&self, references: &[String]) -> Option<(String, Vec<String>)> {
+        references.iter().find_map(|reference| {
+            let mut path = vec![];
+            self.visit(reference, self.owner, &mut path)
+                .then(|| (reference.clone(), path))
+        })
+    }
+
+    fn visit(&self, name: &str, owner: Option<usize>, path: &mut Vec<String>) -> bool {
+        let Some(curve) = self.curve(name, owner) else {
+            return false;
+        };
+        let found = path.contains(&curve.name);
+        path.push(curve.name.clone());
+        if found {
+            return true;
+        }
+        for reference in curve.func.references() {
+            if self.visit(&reference, curve.owner, path) {
+                return true;
+            }
+        }
+        path.pop();
+        false
+    }
+}
+
+/// 1 回の計算で式から見える名前
+struct Scope<'a> {
+    context: &'a ExprContext,
+    x: f64,
+}
+
+impl ContextProvider for Scope<'_> {
+    fn get_var(&self, name: &str) -> Option<f64> {
+        if name == "x" {
+            return Some(self.x);
+        }
+        let context = self.context;
+        context
+            .proportional
+            .iter()
+            .filter(|_| !context.hide_proportional)
+            .find(|(n, _)| *n == name)
+            .map(|(_, coefficient)| coefficient * self.x)
+            .or_else(|| {
+                context
+                    .constants
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, v)| *v)
+            })
+    }
+
+    fn eval_func(&self, name: &str, args: &[f64]) -> Result<f64, FuncEvalError> {
+        if let Some(result) = eval_helper(name, args) {
+            return result;
+        }
+        let context = self.context;
+        let Some(curve) = context.curve(name, context.owner) else {
+            return Err(FuncEvalError::UnknownFunction);
+        };
+        let &[x] = args else {
+            return Err(FuncEvalError::NumberArgs(1));
+        };
+        // 循環しているときは値を持たない
+        if context.path.contains(&curve.name) {
+            return Ok(f64::NAN);
+        }
+        let mut path = context.path.clone();
+        path.push(curve.name.clone());
+        let nested = ExprContext {
+            owner: curve.owner,
+            path,
+            hide_proportional: false,
+            ..context.clone()
+        };
+        Ok(curve.func.value_at(x, &nested))
+    }
+}
+
+/// 式の中の `名前(...)` の名前を書き換える (`rename` が `None` ならそのまま)
+pub(super) fn rename_calls(expression: &str, rename: &dyn Fn(&str) -> Option<String>) -> String {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut renamed = String::with_capacity(expression.len());
+    let mut rest = expression;
+    // 数の中の文字 (1e5 の e など) は名前の途中として読み飛ばす
+    while let Some(start) = rest.find(is_name_char) {
+        let (before, tail) = rest.split_at(start);
+        let end = tail.find(|c| !is_name_char(c)).unwrap_or(tail.len());
+        let (name, after) = tail.split_at(end);
+        renamed.push_str(before);
+        let new_name = after
+            .trim_start()
+            .starts_with('(')
+            .then(|| rename(name))
+            .flatten();
+        renamed.push_str(new_name.as_deref().unwrap_or(name));
+        rest = after;
+    }
+    renamed.push_str(rest);
+    renamed
+}
+
+fn eval_helper(name: &str, args: &[f64]) -> Option<Result<f64, FuncEvalError>> {
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(FuncEvalError::NumberArgs(n))
+        }
+    };
+    let result = match name {
+        "clamp" => arity(3).map(|()| {
+            let (lo, hi) = (args[1].min(args[2]), args[1].max(args[2]));
+            args[0].max(lo).min(hi)
+        }),
+        "lerp" => arity(3).map(|()| args[0] + (args[1] - args[0]) * args[2]),
+        "smoothstep" => arity(3).map(|()| {
+            let (e0, e1, v) = (args[0], args[1], args[2]);
+            let t = if e1 == e0 {
+                if v < e0 { 0.0 } else { 1.0 }
+            } else {
+                ((v - e0) / (e1 - e0)).clamp(0.0, 1.0)
+            };
+            t * t * (3.0 - 2.0 * t)
+        }),
+        "step" => arity(2).map(|()| if args[1] < args[0] { 0.0 } else { 1.0 }),
+        "piecewise" => piecewise(args),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// `piecewise(v, x0, y0, x1, y1, ...)` (範囲外は端の値)
+fn piecewise(args: &[f64]) -> Result<f64, FuncEvalError> {
+    let Some((&v, points)) = args.split_first() else {
+        return Err(FuncEvalError::TooFewArguments);
+    };
+    // 最後の点の y が無いときも足りない
+    if points.is_empty() || points.len() % 2 != 0 {
+        return Err(FuncEvalError::TooFewArguments);
+    }
+    let points: Vec<(f64, f64)> = points.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+    let first = points[0];
+    let last = points[points.len() - 1];
+    if v <= first.0 {
+        return Ok(first.1);
+    }
+    let value = points.windows(2).find(|w| v < w[1].0).map_or(last.1, |w| {
+        let (p0, p1) = (w[0], w[1]);
+        if p1.0 > p0.0 {
+            p0.1 + (p1.1 - p0.1) * (v - p0.0) / (p1.0 - p0.0)
+        } else {
+            p1.1
+        }
+    });
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func_edit::EditableFunc;
+
+    fn context_with(curves: &[(&str, Option<usize>, &str)]) -> ExprContext {
+        let mut table = CurveTable::default();
+        for (name, owner, expression) in curves {
+            table.push(
+                (*name).to_owned(),
+                *owner,
+                EditableFunc::with_expression(expression),
+            );
+        }
+        ExprContext::default().with_curves(table)
+    }
+
+    fn eval(expression: &str, x: f64, context: &ExprContext) -> f64 {
+        EditableFunc::with_expression(expression).value_at(x, context)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn finds_cycle_through_curves() {
+        let context = context_with(&[("a", None, "b(x) + 1"), ("b", None, "a(x) * 2")]);
+        let (reference, path) = context.find_cycle(&["a".to_owned()]).expect("cycle");
+        assert_eq!(reference, "a");
+        assert_eq!(path, ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn no_cycle_in_chain() {
+        let context = context_with(&[("c", None, "x * 2"), ("d", None, "c(x) + 1")]);
+        assert!(context.find_cycle(&["d".to_owned()]).is_none());
+        assert_close(eval("d(x)", 3.0, &context), 7.0);
+    }
+
+    #[test]
+    fn cycle_evaluates_to_nan() {
+        let context = context_with(&[("a", None, "b(x) + 1"), ("b", None, "a(x) * 2")]);
+        assert!(eval("a(x)", 1.0, &context).is_nan());
+        let context = context_with(&[("self_ref", None, "self_ref(x)")]);
+        assert!(eval("self_ref(x)", 1.0, &context).is_nan());
+    }
+
+    #[test]
+    fn owner_curve_by_short_name() {
+        let context = context_with(&[("pitch_0", Some(0), "x * 2"), ("pitch_1", Some(1), "x")]);
+        assert_close(eval("pitch(x)", 3.0, &context.with_owner(0)), 6.0);
+        assert_close(eval("pitch(x)", 3.0, &context.with_owner(1)), 3.0);
+        assert!(eval("pitch(x)", 3.0, &context).is_nan());
+    }
+
+    #[test]
+    fn variables_and_constants() {
+        let context = ExprContext::default()
+            .with_proportional("wheel_hz", 0.5)
+            .with_constants(vec![("gain".to_owned(), 3.0)]);
+        assert_close(eval("wheel_hz * gain", 4.0, &context), 6.0);
+        assert_eq!(context.variable("wheel_hz", 4.0), Some(2.0));
+        assert_eq!(context.variable("unknown", 4.0), None);
+    }
+
+    #[test]
+    fn hidden_proportional_only_at_top_level() {
+        let context = context_with(&[("wheel", None, "wheel_hz")])
+            .with_proportional("wheel_hz", 0.5)
+            .without_proportional();
+        assert!(eval("wheel_hz", 4.0, &context).is_nan());
+        assert_close(eval("wheel(10)", 4.0, &context), 5.0);
+    }
+
+    #[test]
+    fn renames_only_calls() {
+        let rename = |name: &str| (name == "pitch_1").then(|| "pitch_0".to_owned());
+        assert_eq!(
+            rename_calls(
+                "pitch_1(x) * pitch_1 + pitch_10(x) + pitch_1 (2e1)",
+                &rename
+            ),
+            "pitch_0(x) * pitch_1 + pitch_10(x) + pitch_0 (2e1)"
+        );
+    }
+
+    #[test]
+    fn helpers() {
+        let context = ExprContext::default();
+        assert_close(eval("clamp(x, 1, 0)", 2.0, &context), 1.0);
+        assert_close(eval("lerp(10, 20, x)", 0.25, &context), 12.5);
+        assert_close(eval("smoothstep(0, 2, x)", 1.0, &context), 0.5);
+        assert_close(eval("smoothstep(1, 1, x)", 1.0, &context), 1.0);
+        assert_close(eval("step(1, x)", 0.5, &context), 0.0);
+        assert_close(eval("step(1, x)", 1.0, &context), 1.0);
+        assert_close(eval("piecewise(x, 0, 0, 10, 100)", 2.5, &context), 25.0);
+        assert_close(eval("piecewise(x, 0, 0, 10, 100)", -1.0, &context), 0.0);
+        assert_close(eval("piecewise(x, 0, 0, 10, 100)", 11.0, &context), 100.0);
+        assert!(eval("piecewise(x, 0, 0, 10)", 1.0, &context).is_nan());
+        assert!(eval("clamp(x, 0)", 1.0, &context).is_nan());
+    }
+}
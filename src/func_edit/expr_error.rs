@@ -0,0 +1,66 @@
+use std::ops::Range;
+
+/// 式の誤りと、その場所 (式の文字列のバイト範囲)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    pub message: String,
+    pub span: Option<Range<usize>>,
+}
+
+impl std::fmt::Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ExprError {
+    pub(super) fn new(error: &meval::Error, expression: &str) -> Self {
+        use meval::{Error, ParseError};
+
+        let end = expression.len();
+        let span = match error {
+            Error::ParseError(ParseError::UnexpectedToken(i)) => {
+                // 1 文字分を指す
+                let len = expression[*i..].chars().next().map_or(0, char::len_utf8);
+                Some(*i..*i + len)
+            }
+            Error::ParseError(ParseError::MissingRParen(_) | ParseError::MissingArgument) => {
+                Some(end..end)
+            }
+            Error::UnknownVariable(name) | Error::Function(name, _) => {
+                find_identifier(expression, name)
+            }
+            // 字句の番号しか分からない
+            Error::RPNError(_) => None,
+        };
+        let message = match error {
+            Error::UnknownVariable(name) => format!("Unknown variable or constant `{name}`"),
+            Error::Function(name, meval::FuncEvalError::UnknownFunction) => {
+                format!("Unknown function or curve `{name}`")
+            }
+            Error::Function(name, e) => format!("`{name}`: {e}"),
+            Error::ParseError(e) => e.to_string(),
+            Error::RPNError(e) => e.to_string(),
+        };
+        Self { message, span }
+    }
+
+    /// 式の中の `reference` から曲線の参照をたどると `path` のように循環している
+    pub(super) fn cycle(reference: &str, path: &[String], expression: &str) -> Self {
+        Self {
+            message: format!("Circular reference: {}", path.join(" -> ")),
+            span: find_identifier(expression, reference),
+        }
+    }
+}
+
+/// 識別子として現れる最初の `name` の範囲
+fn find_identifier(text: &str, name: &str) -> Option<Range<usize>> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(name)
+        .map(|(i, _)| i..i + name.len())
+        .find(|r| {
+            !text[..r.start].chars().next_back().is_some_and(is_ident)
+                && !text[r.end..].chars().next().is_some_and(is_ident)
+        })
+}
@@ -0,0 +1,192 @@
+/// 点と点の間の補間方法
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// 次の点まで前の点の値を保つ
+    Step,
+    /// 点の間で値が行き過ぎない 3 次補間
+    MonotoneCubic,
+    CatmullRom,
+    /// 点ごとの接線をハンドルで決める
+    Bezier,
+}
+
+impl Interpolation {
+    pub const ALL: [Self; 5] = [
+        Self::Linear,
+        Self::Step,
+        Self::MonotoneCubic,
+        Self::CatmullRom,
+        Self::Bezier,
+    ];
+
+    pub fn is_linear(&self) -> bool {
+        matches!(self, Self::Linear)
+    }
+
+    pub fn label_text(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Step => "Step",
+            Self::MonotoneCubic => "Monotone cubic",
+            Self::CatmullRom => "Catmull-Rom",
+            Self::Bezier => "Bezier",
+        }
+    }
+}
+
+/// 最初の点より前、最後の点より後の値の決め方
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Extrapolation {
+    /// 端の値のまま
+    Hold,
+    /// 端の 2 点を通る直線で伸ばす
+    #[default]
+    Linear,
+    /// 最初の点から最後の点までを繰り返す
+    Repeat,
+    /// 値を持たない (NaN)
+    Nan,
+}
+
+impl Extrapolation {
+    pub const ALL: [Self; 4] = [Self::Hold, Self::Linear, Self::Repeat, Self::Nan];
+
+    pub fn is_linear(&self) -> bool {
+        matches!(self, Self::Linear)
+    }
+
+    pub fn label_text(self) -> &'static str {
+        match self {
+            Self::Hold => "Hold",
+            Self::Linear => "Linear",
+            Self::Repeat => "Repeat",
+            Self::Nan => "None (NaN)",
+        }
+    }
+}
+
+/// 両端の傾きを決めた 3 次エルミート補間
+pub(super) fn hermite(p0: (f64, f64), p1: (f64, f64), m0: f64, m1: f64, x: f64) -> f64 {
+    let h = p1.0 - p0.0;
+    let t = (x - p0.0) / h;
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0) * p0.1
+        + (t3 - 2.0 * t2 + t) * h * m0
+        + (-2.0 * t3 + 3.0 * t2) * p1.1
+        + (t3 - t2) * h * m1
+}
+
+/// 2 点を結ぶ直線の傾き (x が同じなら 0)
+pub(super) fn secant(p0: (f64, f64), p1: (f64, f64)) -> f64 {
+    let h = p1.0 - p0.0;
+    if h > 0.0 { (p1.1 - p0.1) / h } else { 0.0 }
+}
+
+/// `index` 番目の点での Catmull-Rom の接線
+pub(super) fn catmull_rom_tangent(points: &[(f64, f64)], index: usize) -> f64 {
+    let last = points.len().saturating_sub(1);
+    let prev = points[index.saturating_sub(1)];
+    let next = points[(index + 1).min(last)];
+    secant(prev, next)
+}
+
+/// `index` 番目の点での単調な 3 次補間の接線 (Fritsch-Butland)
+pub(super) fn monotone_tangent(points: &[(f64, f64)], index: usize) -> f64 {
+    let prev = index.checked_sub(1).map(|i| (points[i], points[index]));
+    let next = points.get(index + 1).map(|p| (points[index], *p));
+    match (prev, next) {
+        (Some((a, b)), Some((c, d))) => {
+            let (d0, d1) = (secant(a, b), secant(c, d));
+            if d0 * d1 <= 0.0 {
+                // 山・谷では平らにして行き過ぎないようにする
+                return 0.0;
+            }
+            let (h0, h1) = (b.0 - a.0, d.0 - c.0);
+            let w0 = 2.0 * h1 + h0;
+            let w1 = h1 + 2.0 * h0;
+            (w0 + w1) / (w0 / d0 + w1 / d1)
+        }
+        (Some((a, b)), None) | (None, Some((a, b))) => secant(a, b),
+        (None, None) => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func_edit::{EditableFunc, ExprContext, FuncEdit as _};
+
+    fn func_with(points: &[(f64, f64)], interpolation: Interpolation) -> EditableFunc {
+        let mut func = EditableFunc::with_points(points.to_vec());
+        func.set_interpolation(interpolation);
+        func
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    /// 平らな所と急な所が混ざった点
+    const POINTS: [(f64, f64); 6] = [
+        (0.0, 0.0),
+        (1.0, 0.0),
+        (2.0, 1.0),
+        (3.0, 1.0),
+        (4.0, 5.0),
+        (10.0, 6.0),
+    ];
+
+    #[test]
+    fn monotone_cubic_does_not_overshoot() {
+        let func = func_with(&POINTS, Interpolation::MonotoneCubic);
+        let context = ExprContext::default();
+        for pair in POINTS.windows(2) {
+            let (p0, p1) = (pair[0], pair[1]);
+            let (low, high) = (p0.1.min(p1.1), p0.1.max(p1.1));
+            for k in 0..=100 {
+                let x = p0.0 + (p1.0 - p0.0) * f64::from(k) / 100.0;
+                let y = func.value_at(x, &context);
+                assert!(
+                    (low - 1e-12..=high + 1e-12).contains(&y),
+                    "{y} at {x} is outside {low}..={high}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_points() {
+        let func = func_with(&POINTS, Interpolation::CatmullRom);
+        let context = ExprContext::default();
+        for p in POINTS {
+            assert_close(func.value_at(p.0, &context), p.1);
+        }
+    }
+
+    #[test]
+    fn bezier_passes_through_points() {
+        let mut func = func_with(&POINTS, Interpolation::Bezier);
+        // ハンドルを動かしても点は通る
+        func.move_tangent_handle(2, (2.5, 3.0));
+        let context = ExprContext::default();
+        for p in POINTS {
+            assert_close(func.value_at(p.0, &context), p.1);
+        }
+    }
+
+    #[test]
+    fn vertical_pair_extrapolates_flat() {
+        // 縦に並んだ 2 点は傾き無し (無限大にしない) とわざと決めている
+        assert_close(secant((1.0, 0.0), (1.0, 5.0)), 0.0);
+        let func = EditableFunc::with_points(vec![(1.0, 0.0), (1.0, 5.0)]);
+        let context = ExprContext::default();
+        assert_close(func.value_at(-1.0, &context), 0.0);
+        assert_close(func.value_at(3.0, &context), 5.0);
+    }
+}
@@ -1,6 +1,16 @@
+mod convert;
 mod editable_func;
+mod expr_context;
+mod expr_error;
+mod interpolation;
+mod piecewise;
 mod traits;
 mod wrappers;
+pub use convert::{Fit, FitFamily};
 pub use editable_func::{EditableFunc, EditableFuncMode};
+pub use expr_context::{CurveTable, EXPR_HELPERS, ExprContext};
+pub use expr_error::ExprError;
+pub use interpolation::{Extrapolation, Interpolation};
+pub use piecewise::Piecewise;
 pub use traits::FuncEdit;
 pub use wrappers::{EditablePositiveFunc, EditableZeroOneFunc};
@@ -0,0 +1,140 @@
+use super::{EditableFunc, EditableFuncMode, ExprContext, FuncEdit as _};
+
+/// 新しい区間を足すときの、前の区間からの間隔
+const NEW_PIECE_SPAN: f64 = 10.0;
+
+/// x で区切った区間の一つ
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FuncPiece {
+    /// この区間が始まる x (最初の区間では使わない)
+    pub start: f64,
+    /// 前の区間の終わりの値から続ける
+    #[serde(default)]
+    pub continuous: bool,
+    pub func: EditableFunc,
+}
+
+/// 区間ごとに点と式を使い分ける関数
+///
+/// 最初の区間はそれより前も、最後の区間はそれより後も受け持つ。
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Piecewise {
+    pub pieces: Vec<FuncPiece>,
+    /// 点や式を編集している区間
+    #[serde(skip)]
+    pub active: usize,
+}
+
+impl Piecewise {
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// 点の関数一つだけから始める
+    pub(super) fn with_func(func: EditableFunc) -> Self {
+        Self {
+            pieces: vec![FuncPiece {
+                start: 0.0,
+                continuous: false,
+                func,
+            }],
+            active: 0,
+        }
+    }
+
+    /// 編集している区間 (区間を消したり読み込んだりした後でも範囲内にする)
+    pub fn active_index(&self) -> Option<usize> {
+        let last = self.pieces.len().checked_sub(1)?;
+        Some(self.active.min(last))
+    }
+
+    /// x を受け持つ区間
+    pub fn piece_index(&self, x: f64) -> usize {
+        self.pieces.iter().rposition(|p| p.start <= x).unwrap_or(0)
+    }
+
+    pub fn value_at(&self, x: f64, context: &ExprContext) -> f64 {
+        if self.pieces.is_empty() {
+            return f64::NAN;
+        }
+        self.piece_value(self.piece_index(x), x, context)
+    }
+
+    fn piece_value(&self, index: usize, x: f64, context: &ExprContext) -> f64 {
+        let piece = &self.pieces[index];
+        let value = piece.func.value_at(x, context);
+        if index == 0 || !piece.continuous {
+            return value;
+        }
+        let joined = self.piece_value(index - 1, piece.start, context);
+        match piece.func.mode {
+            // 区間の始まりから区間内の最初の点までを直線でつなぐ
+            EditableFuncMode::Points => {
+                let first = piece
+                    .func
+                    .points()
+                    .and_then(|points| points.iter().find(|p| p.0 > piece.start));
+                match first {
+                    Some(p) if x < p.0 => {
+                        joined + (p.1 - joined) * (x - piece.start) / (p.0 - piece.start)
+                    }
+                    Some(_) => value,
+                    None => joined,
+                }
+            }
+            // 式は全体をずらしてつなぐ
+            EditableFuncMode::Expression | EditableFuncMode::Piecewise => {
+                value + joined - piece.func.value_at(piece.start, context)
+            }
+        }
+    }
+
+    /// 最後に式の区間を足して編集対象にする
+    pub fn push_expression(&mut self) {
+        let start = self.pieces.last().map_or(0.0, |p| {
+            // 点の区間なら最後の点から始める
+            let end = p
+                .func
+                .points()
+                .and_then(|points| points.last())
+                .filter(|_| p.func.mode == EditableFuncMode::Points)
+                .map_or(p.start, |q| q.0);
+            if end > p.start {
+                end
+            } else {
+                p.start + NEW_PIECE_SPAN
+            }
+        });
+        self.pieces.push(FuncPiece {
+            start,
+            continuous: false,
+            func: EditableFunc::with_expression(""),
+        });
+        self.active = self.pieces.len() - 1;
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if self.pieces.len() >= 2 && index < self.pieces.len() {
+            self.pieces.remove(index);
+            if self.active >= index {
+                self.active = self.active.saturating_sub(1);
+            }
+        }
+    }
+
+    /// 区間の始まりを動かす (前後の区間を越えないようにする)
+    pub fn move_start(&mut self, index: usize, mut x: f64) {
+        if index == 0 {
+            return;
+        }
+        if let Some(prev) = self.pieces.get(index - 1) {
+            x = x.max(prev.start);
+        }
+        if let Some(next) = self.pieces.get(index + 1) {
+            x = x.min(next.start);
+        }
+        if let Some(piece) = self.pieces.get_mut(index) {
+            piece.start = x;
+        }
+    }
+}
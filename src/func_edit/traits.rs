@@ -1,34 +1,75 @@
-use super::EditableFuncMode;
+use super::{
+    EditableFuncMode, ExprContext, ExprError, Extrapolation, Fit, FitFamily, Interpolation,
+    Piecewise,
+};
 
 pub trait FuncEdit {
     fn mode(&self) -> &EditableFuncMode;
 
     fn mode_mut(&mut self) -> &mut EditableFuncMode;
 
+    /// 点を編集できるか (区分けしているときは編集している区間が点か)
     fn is_mode_points(&self) -> bool {
         matches!(self.mode(), EditableFuncMode::Points)
     }
 
     fn points(&self) -> Option<&Vec<(f64, f64)>>;
 
-    fn value_at(&self, x: f64) -> f64;
+    fn value_at(&self, x: f64, context: &ExprContext) -> f64;
 
-    fn checked_value_at(&self, x: f64) -> Option<f64>;
+    fn checked_value_at(&self, x: f64, context: &ExprContext) -> Option<f64>;
 
     #[expect(dead_code)]
     fn insert_point(&mut self, pos: (f64, f64)) -> (usize, (f64, f64));
 
     fn insert_point_by_index(&mut self, index: usize) -> (usize, (f64, f64));
 
-    fn split_segment(&mut self, x: f64) -> (usize, (f64, f64));
+    /// 線の上に点を追加する (式の区間では追加できず `None`)
+    fn split_segment(&mut self, x: f64) -> Option<(usize, (f64, f64))>;
 
     fn move_point_to(&mut self, index: usize, pos: (f64, f64)) -> Option<(f64, f64)>;
 
     fn remove_point(&mut self, index: usize) -> Option<(f64, f64)>;
 
+    fn interpolation(&self) -> Interpolation;
+
+    fn set_interpolation(&mut self, interpolation: Interpolation);
+
+    fn extrapolation_mut(&mut self) -> &mut Extrapolation;
+
+    /// 値を持つ x の範囲 (範囲外を NaN にしているときだけ)
+    fn defined_range(&self) -> Option<(f64, f64)>;
+
+    /// ベジェ補間で傾きを決めるハンドル (点の番号, 位置)
+    fn tangent_handles(&self) -> Vec<(usize, (f64, f64))>;
+
+    /// ハンドルを動かして `index` 番目の点の傾きを変える
+    fn move_tangent_handle(&mut self, index: usize, pos: (f64, f64));
+
+    /// 区分けしているときの区間の一覧
+    fn piecewise_mut(&mut self) -> Option<&mut Piecewise>;
+
+    /// 区間の境目 (区間の番号, x)
+    fn piece_starts(&self) -> Vec<(usize, f64)>;
+
+    fn move_piece_start(&mut self, index: usize, x: f64);
+
     fn expression_mut(&mut self) -> Option<&mut String>;
 
-    fn expression_err(&self) -> Option<&meval::Error>;
+    /// 式の誤り (読めない・知らない名前・曲線の循環参照)
+    fn expression_err(&self, context: &ExprContext) -> Option<ExprError>;
+
+    /// 式から参照している曲線の名前
+    fn references(&self) -> Vec<String>;
 
     fn update_expression(&mut self);
+
+    /// 式の中で呼んでいる曲線の名前を書き換える (`rename` が `None` ならそのまま)
+    fn rename_references(&mut self, rename: &dyn Fn(&str) -> Option<String>);
+
+    /// `range` の中の値を、直線でつないだときのずれが `tolerance` 以内の点にして点の関数にする
+    fn bake_to_points(&mut self, range: (f64, f64), tolerance: f64, context: &ExprContext) -> bool;
+
+    /// 点に `family` の形の式を当てはめて式の関数にする
+    fn fit_expression(&mut self, family: FitFamily) -> anyhow::Result<Fit>;
 }
@@ -1,4 +1,7 @@
-use super::{EditableFunc, EditableFuncMode, FuncEdit};
+use super::{
+    EditableFunc, EditableFuncMode, ExprContext, ExprError, Extrapolation, Fit, FitFamily,
+    FuncEdit, Interpolation, Piecewise,
+};
 
 #[derive(Debug, Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct EditableZeroOneFunc {
@@ -21,12 +24,16 @@ impl FuncEdit for EditableZeroOneFunc {
         self.inner.mode_mut()
     }
 
-    fn value_at(&self, x: f64) -> f64 {
-        self.inner.value_at_clamped(x, Self::clamp)
+    fn is_mode_points(&self) -> bool {
+        self.inner.is_mode_points()
     }
 
-    fn checked_value_at(&self, x: f64) -> Option<f64> {
-        self.inner.checked_value_at_clamped(x, Self::clamp)
+    fn value_at(&self, x: f64, context: &ExprContext) -> f64 {
+        self.inner.value_at_clamped(x, context, Self::clamp)
+    }
+
+    fn checked_value_at(&self, x: f64, context: &ExprContext) -> Option<f64> {
+        self.inner.checked_value_at_clamped(x, context, Self::clamp)
     }
 
     fn points(&self) -> Option<&Vec<(f64, f64)>> {
@@ -41,7 +48,7 @@ impl FuncEdit for EditableZeroOneFunc {
         self.inner.insert_point_by_index_clamped(index, Self::clamp)
     }
 
-    fn split_segment(&mut self, x: f64) -> (usize, (f64, f64)) {
+    fn split_segment(&mut self, x: f64) -> Option<(usize, (f64, f64))> {
         self.inner.split_segment_clamped(x, Self::clamp)
     }
 
@@ -53,17 +60,70 @@ impl FuncEdit for EditableZeroOneFunc {
         self.inner.remove_point(index)
     }
 
+    fn interpolation(&self) -> Interpolation {
+        self.inner.interpolation()
+    }
+
+    fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.inner.set_interpolation(interpolation);
+    }
+
+    fn extrapolation_mut(&mut self) -> &mut Extrapolation {
+        self.inner.extrapolation_mut()
+    }
+
+    fn defined_range(&self) -> Option<(f64, f64)> {
+        self.inner.defined_range()
+    }
+
+    fn tangent_handles(&self) -> Vec<(usize, (f64, f64))> {
+        self.inner.tangent_handles()
+    }
+
+    fn move_tangent_handle(&mut self, index: usize, pos: (f64, f64)) {
+        self.inner.move_tangent_handle(index, pos);
+    }
+
+    fn piecewise_mut(&mut self) -> Option<&mut Piecewise> {
+        self.inner.piecewise_mut()
+    }
+
+    fn piece_starts(&self) -> Vec<(usize, f64)> {
+        self.inner.piece_starts()
+    }
+
+    fn move_piece_start(&mut self, index: usize, x: f64) {
+        self.inner.move_piece_start(index, Self::clamp((x, 0.0)).0);
+    }
+
     fn expression_mut(&mut self) -> Option<&mut String> {
         self.inner.expression_mut()
     }
 
-    fn expression_err(&self) -> Option<&meval::Error> {
-        self.inner.expression_err()
+    fn expression_err(&self, context: &ExprContext) -> Option<ExprError> {
+        self.inner.expression_err(context)
+    }
+
+    fn references(&self) -> Vec<String> {
+        self.inner.references()
     }
 
     fn update_expression(&mut self) {
         self.inner.update_expression();
     }
+
+    fn rename_references(&mut self, rename: &dyn Fn(&str) -> Option<String>) {
+        self.inner.rename_references(rename);
+    }
+
+    fn bake_to_points(&mut self, range: (f64, f64), tolerance: f64, context: &ExprContext) -> bool {
+        self.inner
+            .bake_to_points_clamped(range, tolerance, context, Self::clamp)
+    }
+
+    fn fit_expression(&mut self, family: FitFamily) -> anyhow::Result<Fit> {
+        self.inner.fit_expression(family)
+    }
 }
 
 impl EditableZeroOneFunc {
@@ -97,12 +157,16 @@ impl FuncEdit for EditablePositiveFunc {
         self.inner.mode_mut()
     }
 
-    fn value_at(&self, x: f64) -> f64 {
-        self.inner.value_at_clamped(x, Self::clamp)
+    fn is_mode_points(&self) -> bool {
+        self.inner.is_mode_points()
     }
 
-    fn checked_value_at(&self, x: f64) -> Option<f64> {
-        self.inner.checked_value_at_clamped(x, Self::clamp)
+    fn value_at(&self, x: f64, context: &ExprContext) -> f64 {
+        self.inner.value_at_clamped(x, context, Self::clamp)
+    }
+
+    fn checked_value_at(&self, x: f64, context: &ExprContext) -> Option<f64> {
+        self.inner.checked_value_at_clamped(x, context, Self::clamp)
     }
 
     fn points(&self) -> Option<&Vec<(f64, f64)>> {
@@ -117,7 +181,7 @@ impl FuncEdit for EditablePositiveFunc {
         self.inner.insert_point_by_index_clamped(index, Self::clamp)
     }
 
-    fn split_segment(&mut self, x: f64) -> (usize, (f64, f64)) {
+    fn split_segment(&mut self, x: f64) -> Option<(usize, (f64, f64))> {
         self.inner.split_segment_clamped(x, Self::clamp)
     }
 
@@ -129,17 +193,70 @@ impl FuncEdit for EditablePositiveFunc {
         self.inner.remove_point(index)
     }
 
+    fn interpolation(&self) -> Interpolation {
+        self.inner.interpolation()
+    }
+
+    fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.inner.set_interpolation(interpolation);
+    }
+
+    fn extrapolation_mut(&mut self) -> &mut Extrapolation {
+        self.inner.extrapolation_mut()
+    }
+
+    fn defined_range(&self) -> Option<(f64, f64)> {
+        self.inner.defined_range()
+    }
+
+    fn tangent_handles(&self) -> Vec<(usize, (f64, f64))> {
+        self.inner.tangent_handles()
+    }
+
+    fn move_tangent_handle(&mut self, index: usize, pos: (f64, f64)) {
+        self.inner.move_tangent_handle(index, pos);
+    }
+
+    fn piecewise_mut(&mut self) -> Option<&mut Piecewise> {
+        self.inner.piecewise_mut()
+    }
+
+    fn piece_starts(&self) -> Vec<(usize, f64)> {
+        self.inner.piece_starts()
+    }
+
+    fn move_piece_start(&mut self, index: usize, x: f64) {
+        self.inner.move_piece_start(index, Self::clamp((x, 0.0)).0);
+    }
+
     fn expression_mut(&mut self) -> Option<&mut String> {
         self.inner.expression_mut()
     }
 
-    fn expression_err(&self) -> Option<&meval::Error> {
-        self.inner.expression_err()
+    fn expression_err(&self, context: &ExprContext) -> Option<ExprError> {
+        self.inner.expression_err(context)
+    }
+
+    fn references(&self) -> Vec<String> {
+        self.inner.references()
     }
 
     fn update_expression(&mut self) {
         self.inner.update_expression();
     }
+
+    fn rename_references(&mut self, rename: &dyn Fn(&str) -> Option<String>) {
+        self.inner.rename_references(rename);
+    }
+
+    fn bake_to_points(&mut self, range: (f64, f64), tolerance: f64, context: &ExprContext) -> bool {
+        self.inner
+            .bake_to_points_clamped(range, tolerance, context, Self::clamp)
+    }
+
+    fn fit_expression(&mut self, family: FitFamily) -> anyhow::Result<Fit> {
+        self.inner.fit_expression(family)
+    }
 }
 
 impl EditablePositiveFunc {
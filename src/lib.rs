@@ -6,10 +6,13 @@ mod audio_player;
 #[cfg(not(target_arch = "wasm32"))]
 mod file_dialog;
 mod func_edit;
+#[cfg(not(target_arch = "wasm32"))]
+mod offline_render;
 mod player_state;
 mod preference;
 #[cfg(not(target_arch = "wasm32"))]
 mod save_load;
+mod simulation;
 mod state;
 mod ui;
 pub use app::MainApp;
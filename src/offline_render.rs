@@ -0,0 +1,308 @@
+use crate::{
+    audio_player::{AudioSource, OutputConfig, Voice},
+    player_state::PlayerState,
+    preference::RenderSettings,
+    simulation::{entry_voice_params, expr_contexts, one_shot_params},
+    state::{AudioEntry, AudioEntryId, FileRegistory, State},
+};
+use anyhow::{Context as _, anyhow, bail};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+};
+
+const BLOCK_SIZE: usize = 256;
+const MAX_RENDER_SECONDS: f64 = 600.0;
+/// 力行中にこの時間速度が上がらなければ目標速度に届かないとみなす
+const STALL_SECONDS: f64 = 5.0;
+/// 速度が上がったとみなす幅 (km/h)
+const STALL_SPEED_STEP: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RenderPhase {
+    Power,
+    Coast,
+    Brake,
+    Tail,
+}
+
+/// 書き出しの進み具合
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderProgress {
+    /// 書き出した長さ (秒)
+    pub seconds: f64,
+    /// 速度 (km/h)
+    pub speed: f64,
+}
+
+/// 別スレッドで WAV を書き出す
+///
+/// 一時ファイルに書き、うまくいったときだけ `path` へ名前を変える。
+#[derive(Debug)]
+pub struct RenderJob {
+    path: PathBuf,
+    progress: Arc<Mutex<RenderProgress>>,
+    cancel: Arc<AtomicBool>,
+    handle: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl RenderJob {
+    pub fn start(
+        path: PathBuf,
+        registory: &FileRegistory,
+        state: &State,
+        settings: &RenderSettings,
+    ) -> Self {
+        // 書き出し中に編集されても影響しないように写しを渡す
+        let raw_data: HashMap<AudioEntryId, Vec<u8>> = state
+            .audio_entries
+            .iter()
+            .filter_map(|e| Some((*e.id(), registory.raw_data_by_id(e.id())?.clone())))
+            .collect();
+        let state = state.clone();
+        let settings = settings.clone();
+        let progress = Arc::new(Mutex::new(RenderProgress::default()));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (path, progress, cancel) = (path.clone(), progress.clone(), cancel.clone());
+            std::thread::spawn(move || {
+                render_to_file(&path, &raw_data, &state, &settings, &progress, &cancel)
+            })
+        };
+        Self {
+            path,
+            progress,
+            cancel,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn progress(&self) -> RenderProgress {
+        self.progress.lock().map(|p| *p).unwrap_or_default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// 終わっていれば結果を返す
+    pub fn try_finish(&mut self) -> Option<anyhow::Result<()>> {
+        if !self.handle.as_ref()?.is_finished() {
+            return None;
+        }
+        let handle = self.handle.take()?;
+        Some(
+            handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("The render thread panicked."))),
+        )
+    }
+}
+
+fn render_to_file(
+    path: &Path,
+    raw_data: &HashMap<AudioEntryId, Vec<u8>>,
+    state: &State,
+    settings: &RenderSettings,
+    progress: &Mutex<RenderProgress>,
+    cancel: &AtomicBool,
+) -> anyhow::Result<()> {
+    // 途中で止まっても書きかけのファイルを残さない
+    let temp_path = path.with_extension("wav.part");
+    let result =
+        write_wav(&temp_path, raw_data, state, settings, progress, cancel).and_then(|()| {
+            std::fs::rename(&temp_path, path)
+                .with_context(|| format!("Unable to write {}", path.display()))
+        });
+    if result.is_err() {
+        std::fs::remove_file(&temp_path).ok();
+    }
+    result
+}
+
+fn write_wav(
+    path: &Path,
+    raw_data: &HashMap<AudioEntryId, Vec<u8>>,
+    state: &State,
+    settings: &RenderSettings,
+    progress: &Mutex<RenderProgress>,
+    cancel: &AtomicBool,
+) -> anyhow::Result<()> {
+    let spec = hound::WavSpec {
+        channels: settings.channels,
+        sample_rate: settings.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    render(raw_data, state, settings, |block, current| {
+        if cancel.load(Ordering::Relaxed) {
+            bail!("Render cancelled.");
+        }
+        if let Ok(mut p) = progress.lock() {
+            *p = current;
+        }
+        for sample in block {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        Ok(())
+    })?;
+    writer.finalize()?;
+    Ok(())
+}
+
+fn render(
+    raw_data: &HashMap<AudioEntryId, Vec<u8>>,
+    state: &State,
+    settings: &RenderSettings,
+    mut write_block: impl FnMut(&[f32], RenderProgress) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let channels = settings.channels as usize;
+    if channels == 0 || settings.sample_rate == 0 {
+        bail!("Invalid render settings.");
+    }
+
+    let mut voices = decode_voices(raw_data, state, settings)?;
+
+    let performance = &state.train_performance;
+    let contexts = expr_contexts(state);
+    let mut player_state = PlayerState::default();
+    let dt = BLOCK_SIZE as f64 / settings.sample_rate as f64;
+    let mut block = vec![0.0f32; BLOCK_SIZE * channels];
+
+    let mut phase = RenderPhase::Power;
+    let mut phase_time = 0.0;
+    let mut elapsed = 0.0;
+    // 力行中に最後に速度が上がったときの (速度, 時刻)
+    let mut last_rise = (0.0, 0.0);
+
+    loop {
+        // 運転操作
+        let next_phase = match phase {
+            RenderPhase::Power if player_state.speed >= settings.target_speed => {
+                Some(RenderPhase::Coast)
+            }
+            RenderPhase::Coast if phase_time >= settings.coast_seconds => Some(RenderPhase::Brake),
+            RenderPhase::Brake if player_state.speed <= 0.0 => Some(RenderPhase::Tail),
+            RenderPhase::Tail if phase_time >= settings.tail_seconds => break,
+            _ => None,
+        };
+        if let Some(next_phase) = next_phase {
+            phase = next_phase;
+            phase_time = 0.0;
+        }
+        player_state.master_controller = match phase {
+            RenderPhase::Power => settings.power_notch as i32,
+            RenderPhase::Coast | RenderPhase::Tail => 0,
+            RenderPhase::Brake => -(settings.brake_notch as i32),
+        };
+        player_state.check(performance);
+        let context = &contexts[&player_state.active_sound_type()];
+        player_state.step(dt, performance, context);
+
+        // 固定長ブロックごとにミックス
+        block.fill(0.0);
+        let sounding = &contexts[&player_state.sounding_sound_type().0];
+        for (i, (entry, voice)) in voices.iter_mut().enumerate() {
+            // モニター音量は書き出しに反映しない
+            if entry.one_shot().is_none() {
+                voice.apply_params(&entry_voice_params(
+                    entry,
+                    &player_state,
+                    performance,
+                    &sounding.with_owner(i),
+                    &state.track_geometry,
+                    1.0,
+                ));
+            }
+            for params in one_shot_params(
+                entry,
+                &state.audio_entries,
+                &player_state,
+                &state.track_geometry,
+                1.0,
+            ) {
+                voice.apply_params(&params);
+            }
+            voice.write_data_additive(&mut block);
+        }
+        player_state.reset_event_origin();
+
+        phase_time += dt;
+        elapsed += dt;
+        write_block(
+            &block,
+            RenderProgress {
+                seconds: elapsed,
+                speed: player_state.speed,
+            },
+        )?;
+        if phase == RenderPhase::Power {
+            if player_state.speed > last_rise.0 + STALL_SPEED_STEP {
+                last_rise = (player_state.speed, elapsed);
+            } else if elapsed - last_rise.1 > STALL_SECONDS {
+                bail!(
+                    "The train stops accelerating at {:.1} km/h and cannot reach the target speed of {:.1} km/h.",
+                    player_state.speed,
+                    settings.target_speed
+                );
+            }
+        }
+        if elapsed > MAX_RENDER_SECONDS {
+            bail!(
+                "Render exceeded {MAX_RENDER_SECONDS} seconds. Check the train performance and target speed."
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// 出力のサンプルレート・チャンネル数でデコードし直す
+fn decode_voices<'a>(
+    raw_data: &HashMap<AudioEntryId, Vec<u8>>,
+    state: &'a State,
+    settings: &RenderSettings,
+) -> anyhow::Result<Vec<(&'a AudioEntry, Voice)>> {
+    state
+        .audio_entries
+        .iter()
+        .map(|entry| {
+            if let Some(synth) = entry.synth() {
+                let config = OutputConfig {
+                    sample_rate: settings.sample_rate,
+                    channels: settings.channels,
+                };
+                return Ok((entry, Voice::synth(synth.kind(), config)));
+            }
+            let raw = raw_data
+                .get(entry.id())
+                .with_context(|| format!("Unable to get audio data of {}", entry.name()))?;
+            let source = Arc::new(AudioSource::decode(raw, settings.channels as usize)?);
+            let voice = if entry.one_shot().is_some() {
+                Voice::one_shot(source, settings.sample_rate)
+            } else {
+                Voice::sample(
+                    source,
+                    settings.sample_rate,
+                    BLOCK_SIZE,
+                    settings.resampler_quality,
+                )?
+            };
+            Ok((entry, voice))
+        })
+        .collect()
+}
@@ -1,17 +1,51 @@
 use crate::{
-    func_edit::FuncEdit as _,
+    func_edit::{ExprContext, FuncEdit as _},
     preference::Preference,
-    state::{AudioEntry, SoundType, State, TrainPerformance},
+    state::{
+        AudioEntry, CarrierSchedule, SoundType, State, TrackGeometry, TrainPerformance,
+        TriggerEvent,
+    },
 };
 use egui::{Context, Key, Modifiers};
-use std::time::Instant;
+
+/// これより遅ければ停車中とみなす (km/h)
+const STOP_SPEED: f64 = 0.1;
+
+/// 走行状態の切り替え中 (前の状態の音を消してから次の状態の音を鳴らす)
+#[derive(Debug, Clone, Copy)]
+struct Transition {
+    from: SoundType,
+    /// 切り替え始めたときの前の状態の音量
+    from_gain: f64,
+    elapsed: f64,
+    fade_out: f64,
+    gap: f64,
+    fade_in: f64,
+}
+
+/// きっかけを最後に調べたときの運転状態
+#[derive(Debug, Clone, Copy)]
+struct EventOrigin {
+    master_controller: i32,
+    speed: f64,
+    distance: f64,
+}
 
 pub struct PlayerState {
     pub master_controller: i32,
     pub speed: f64,
+    /// 走行距離 (m、継ぎ目を踏む位置を決める)
+    distance: f64,
+    /// グラフで表示・編集する状態 (走行状態が変わると追従する)
     pub sound_type: SoundType,
+    last_active_sound_type: SoundType,
     smoothed_acceleration: f64,
-    last_frame_time: Option<Instant>,
+    /// 引張力・ブレーキ力の最大値に対する割合
+    effort_ratio: f64,
+    /// 切り替えが終わった後に鳴る状態
+    audible_sound_type: SoundType,
+    transition: Option<Transition>,
+    event_origin: Option<EventOrigin>,
 }
 
 impl std::fmt::Debug for PlayerState {
@@ -36,9 +70,15 @@ impl Default for PlayerState {
         Self {
             master_controller: i32::MIN,
             speed: 0.0,
+            distance: 0.0,
             sound_type: SoundType::Accel,
+            // 起動直後は非常ブレーキで停車中
+            last_active_sound_type: SoundType::Idle,
             smoothed_acceleration: 0.0,
-            last_frame_time: None,
+            effort_ratio: 0.0,
+            audible_sound_type: SoundType::Idle,
+            transition: None,
+            event_origin: None,
         }
     }
 }
@@ -49,22 +89,142 @@ impl PlayerState {
             -(train_performance.brake_steps as i32),
             train_performance.power_steps as i32,
         );
+        let active = self.active_sound_type();
+        if active != self.last_active_sound_type {
+            self.sound_type = active;
+            self.last_active_sound_type = active;
+        }
+    }
+
+    /// 運転操作と速度から決まる今の走行状態
+    pub fn active_sound_type(&self) -> SoundType {
+        let stopped = self.speed < STOP_SPEED;
         match self.master_controller.cmp(&0) {
-            std::cmp::Ordering::Greater => self.sound_type = SoundType::Accel,
-            std::cmp::Ordering::Less => self.sound_type = SoundType::Brake,
-            std::cmp::Ordering::Equal => {}
+            std::cmp::Ordering::Greater => SoundType::Accel,
+            _ if stopped => SoundType::Idle,
+            std::cmp::Ordering::Less => SoundType::Brake,
+            std::cmp::Ordering::Equal => SoundType::Coast,
         }
     }
 
-    pub fn get_volume_pitch(&self, entry: &AudioEntry, preference: &Preference) -> (f32, f32) {
-        if let Some(funcs) = entry.funcs_by_type(self.sound_type) {
-            let volume = if self.master_controller != 0 && self.speed > 1e-6 {
-                funcs.volume.value_at(self.speed)
-            } else {
-                0.0
-            };
-            let pitch = funcs.pitch.value_at(self.speed);
-            (preference.global_volume * volume as f32, pitch as f32)
+    /// 今鳴らす状態とその音量にかける倍率
+    pub fn sounding_sound_type(&self) -> (SoundType, f64) {
+        let Some(t) = &self.transition else {
+            return (self.audible_sound_type, 1.0);
+        };
+        if t.elapsed < t.fade_out {
+            (t.from, t.from_gain * (1.0 - t.elapsed / t.fade_out))
+        } else if t.elapsed < t.fade_out + t.gap {
+            (t.from, 0.0)
+        } else if t.elapsed < t.fade_out + t.gap + t.fade_in {
+            let gain = (t.elapsed - t.fade_out - t.gap) / t.fade_in;
+            (self.audible_sound_type, gain)
+        } else {
+            (self.audible_sound_type, 1.0)
+        }
+    }
+
+    fn update_transition(
+        &mut self,
+        dt: f64,
+        performance: &TrainPerformance,
+        context: &ExprContext,
+    ) {
+        let active = self.active_sound_type();
+        if active != self.audible_sound_type {
+            // 切り替え中でも今鳴っている音から消していく
+            let (from, from_gain) = self.sounding_sound_type();
+            let (fade_out, gap, fade_in) = performance.transition.timings_at(self.speed, context);
+            self.transition = Some(Transition {
+                from,
+                from_gain,
+                elapsed: 0.0,
+                fade_out,
+                gap,
+                fade_in,
+            });
+            self.audible_sound_type = active;
+        } else if let Some(t) = &mut self.transition {
+            t.elapsed += dt;
+            if t.elapsed >= t.fade_out + t.gap + t.fade_in {
+                self.transition = None;
+            }
+        }
+    }
+
+    /// 前回 `reset_event_origin` を呼んでから `event` が起きたか
+    pub fn event_fired(
+        &self,
+        event: TriggerEvent,
+        schedule: &CarrierSchedule,
+        track: &TrackGeometry,
+    ) -> bool {
+        let Some(origin) = self.event_origin else {
+            return false;
+        };
+        let (m0, m1) = (origin.master_controller, self.master_controller);
+        let (v0, v1) = (origin.speed, self.speed);
+        match event {
+            TriggerEvent::NotchUp => m1 > m0,
+            TriggerEvent::NotchDown => m1 < m0,
+            TriggerEvent::PowerOn => m0 <= 0 && m1 > 0,
+            TriggerEvent::PowerOff => m0 > 0 && m1 <= 0,
+            TriggerEvent::BrakeOn => m0 >= 0 && m1 < 0,
+            TriggerEvent::BrakeOff => m0 < 0 && m1 >= 0,
+            TriggerEvent::SpeedRising(speed) => v0 < speed && v1 >= speed,
+            TriggerEvent::SpeedFalling(speed) => v0 > speed && v1 <= speed,
+            TriggerEvent::ModeChange => {
+                !schedule.is_empty() && schedule.region_index_at(v0) != schedule.region_index_at(v1)
+            }
+            TriggerEvent::Stop => v0 >= STOP_SPEED && v1 < STOP_SPEED,
+            TriggerEvent::RailJoint => track.joints_between(origin.distance, self.distance) > 0,
+        }
+    }
+
+    /// 全ての音声についてきっかけを調べ終えたら呼ぶ
+    pub fn reset_event_origin(&mut self) {
+        self.event_origin = Some(EventOrigin {
+            master_controller: self.master_controller,
+            speed: self.speed,
+            distance: self.distance,
+        });
+    }
+
+    /// 最低ノッチを 0、最高ノッチを 1 とした今のノッチの位置 (ノッチオフでは 1)
+    pub fn notch_position(&self, performance: &TrainPerformance) -> f64 {
+        let (notch, steps) = match self.master_controller.cmp(&0) {
+            std::cmp::Ordering::Greater => (self.master_controller, performance.power_steps),
+            std::cmp::Ordering::Less => (-self.master_controller, performance.brake_steps),
+            std::cmp::Ordering::Equal => return 1.0,
+        };
+        notch_position(notch as u8, steps)
+    }
+
+    pub fn get_volume_pitch(
+        &self,
+        entry: &AudioEntry,
+        performance: &TrainPerformance,
+        global_volume: f32,
+        context: &ExprContext,
+    ) -> (f32, f32) {
+        let (sound_type, gain) = self.sounding_sound_type();
+        if let Some(funcs) = entry.funcs_by_type(sound_type) {
+            let notch = self.notch_position(performance);
+            let mut volume = gain * funcs.volume_at(self.speed, notch, context);
+            if let Some(modulation) = entry.load_modulation() {
+                volume *=
+                    modulation.gain_at(self.effort_ratio, self.smoothed_acceleration, context);
+            }
+            if sound_type == SoundType::Brake {
+                // 低速では空気ブレーキに切り替わって消えていく
+                volume *= performance.regen_fade.factor(self.speed);
+            }
+            // 点の範囲外で値を持たないときは鳴らさない
+            if volume.is_nan() {
+                volume = 0.0;
+            }
+            let pitch = funcs.pitch_at(self.speed, notch, context);
+            (global_volume * volume as f32, pitch as f32)
         } else {
             (0.0, 1.0)
         }
@@ -102,35 +262,83 @@ impl PlayerState {
 
         self.check(performance);
 
-        // 速度更新
-        if let Some(dt) = self.last_frame_time.map(|i| i.elapsed().as_secs_f64()) {
-            let mut acceleration = 0.0;
+        // 速度は Simulation で更新されるので、表示のために描画し続ける
+        ctx.request_repaint();
+    }
 
-            match self.master_controller.cmp(&0) {
-                std::cmp::Ordering::Greater => {
-                    let f = self.master_controller as f64 / performance.power_steps as f64;
-                    if let Some(a) = performance.acceleration.checked_value_at(self.speed) {
-                        acceleration += f * a;
-                    }
-                }
-                std::cmp::Ordering::Less => {
-                    let f = -self.master_controller as f64 / performance.brake_steps as f64;
-                    acceleration -= f * performance.brake_acceleration;
+    /// 操作を `simulated` へ渡し、走行状態を受け取る
+    pub fn sync_with(&mut self, simulated: &mut Self) {
+        simulated.master_controller = self.master_controller;
+        self.speed = simulated.speed;
+        self.distance = simulated.distance;
+        self.smoothed_acceleration = simulated.smoothed_acceleration;
+        self.effort_ratio = simulated.effort_ratio;
+    }
+
+    /// 加速度 (km/h/s、減速は負)
+    pub fn acceleration(&self) -> f64 {
+        self.smoothed_acceleration
+    }
+
+    pub fn effort_ratio(&self) -> f64 {
+        self.effort_ratio
+    }
+
+    pub fn step(&mut self, dt: f64, performance: &TrainPerformance, context: &ExprContext) {
+        let mut acceleration = 0.0;
+
+        match self.master_controller.cmp(&0) {
+            std::cmp::Ordering::Greater => {
+                let f = self.master_controller as f64 / performance.power_steps as f64;
+                if let Some(a) = performance
+                    .acceleration
+                    .checked_value_at(self.speed, context)
+                {
+                    acceleration += f * a;
                 }
-                std::cmp::Ordering::Equal => {}
             }
-
-            if let Some(drag) = performance.drag.checked_value_at(self.speed) {
-                acceleration -= drag;
+            std::cmp::Ordering::Less => {
+                let f = -self.master_controller as f64 / performance.brake_steps as f64;
+                acceleration -= f * performance.brake_acceleration;
             }
-
-            let sa = &mut self.smoothed_acceleration;
-            *sa = acceleration.clamp(*sa - 4.0 * dt, *sa + 4.0 * dt);
-            self.speed += *sa * dt;
+            std::cmp::Ordering::Equal => {}
         }
+
+        let drag = performance
+            .drag
+            .checked_value_at(self.speed, context)
+            .unwrap_or(0.0);
+        acceleration -= drag;
+
+        let sa = &mut self.smoothed_acceleration;
+        *sa = acceleration.clamp(*sa - 4.0 * dt, *sa + 4.0 * dt);
+
+        // 走行抵抗を除いた分が引張力・ブレーキ力
+        let effort = *sa + drag;
+        let max_effort = if effort >= 0.0 {
+            performance
+                .acceleration
+                .checked_value_at(self.speed, context)
+        } else {
+            Some(performance.brake_acceleration)
+        };
+        self.effort_ratio = max_effort
+            .filter(|m| *m > 0.0)
+            .map_or(0.0, |m| (effort.abs() / m).min(1.0));
+
+        self.speed += self.smoothed_acceleration * dt;
         self.speed = self.speed.max(0.0);
+        self.distance += self.speed / 3.6 * dt;
 
-        self.last_frame_time = Some(std::time::Instant::now());
-        ctx.request_repaint();
+        self.update_transition(dt, performance, context);
+    }
+}
+
+/// `notch` 段目 (1 から `steps` まで) の位置
+pub fn notch_position(notch: u8, steps: u8) -> f64 {
+    if steps <= 1 {
+        1.0
+    } else {
+        (notch.clamp(1, steps) - 1) as f64 / (steps - 1) as f64
     }
 }
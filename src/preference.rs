@@ -1,10 +1,68 @@
+use crate::audio_player::{OutputConfig, ResamplerQuality};
+
 #[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Preference {
     pub global_volume: f32,
+    #[serde(default)]
+    pub render: RenderSettings,
+    #[serde(default)]
+    pub null_output: OutputConfig,
+    #[serde(default)]
+    pub audio_device: DeviceSettings,
+    #[serde(default)]
+    pub resampler_quality: ResamplerQuality,
 }
 
 impl Default for Preference {
     fn default() -> Self {
-        Self { global_volume: 0.5 }
+        Self {
+            global_volume: 0.5,
+            render: RenderSettings::default(),
+            null_output: OutputConfig::default(),
+            audio_device: DeviceSettings::default(),
+            resampler_quality: ResamplerQuality::default(),
+        }
+    }
+}
+
+/// 出力デバイスの設定 (None はデバイスの既定値)
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct DeviceSettings {
+    pub name: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub buffer_size: Option<u32>,
+    pub use_null: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RenderSettings {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub power_notch: u8,
+    pub target_speed: f64,
+    pub coast_seconds: f64,
+    pub brake_notch: u8,
+    pub tail_seconds: f64,
+    #[serde(default = "render_resampler_quality")]
+    pub resampler_quality: ResamplerQuality,
+}
+
+// 書き出しは時間をかけてよいので高品質を既定に
+fn render_resampler_quality() -> ResamplerQuality {
+    ResamplerQuality::Sinc
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            channels: 2,
+            power_notch: 5,
+            target_speed: 100.0,
+            coast_seconds: 2.0,
+            brake_notch: 8,
+            tail_seconds: 1.0,
+            resampler_quality: render_resampler_quality(),
+        }
     }
 }
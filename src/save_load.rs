@@ -1,5 +1,10 @@
-use crate::state::{
-    AudioEntry, AudioEntryId, AudioFunctionMode, FileRegistory, State, TrainPerformance,
+use crate::{
+    audio_player::{AudioFormat, LoopRegion, Smoothing},
+    state::{
+        AudioEntry, AudioEntryId, AudioFunctionMode, CarrierSchedule, DriveTrain, ExprConstant,
+        FileRegistory, LoadModulation, OneShotSettings, State, SynthSource, TrackGeometry,
+        TrainPerformance,
+    },
 };
 use anyhow::bail;
 use std::{
@@ -9,17 +14,31 @@ use std::{
 };
 use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
 
+/// ファイルフォーマットのバージョン
+const FILE_VERSION: &str = "1";
+
 #[derive(serde::Serialize)]
 struct SerializeState<'a> {
     audio_entries: Vec<SerializeAudioEntry<'a>>,
     train_performance: &'a TrainPerformance,
+    drive_train: &'a DriveTrain,
+    track_geometry: &'a TrackGeometry,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    constants: &'a [ExprConstant],
 }
 
-impl<'a> From<&'a State> for SerializeState<'a> {
-    fn from(value: &'a State) -> Self {
+impl<'a> SerializeState<'a> {
+    fn new(state: &'a State, registory: &FileRegistory) -> Self {
         Self {
-            audio_entries: value.audio_entries.iter().map(|e| e.into()).collect(),
-            train_performance: &value.train_performance,
+            audio_entries: state
+                .audio_entries
+                .iter()
+                .map(|e| SerializeAudioEntry::new(e, registory))
+                .collect(),
+            train_performance: &state.train_performance,
+            drive_train: &state.drive_train,
+            track_geometry: &state.track_geometry,
+            constants: &state.constants,
         }
     }
 }
@@ -28,19 +47,47 @@ impl<'a> From<&'a State> for SerializeState<'a> {
 struct SerializeAudioEntry<'a> {
     #[serde(skip)]
     id: AudioEntryId,
-    audio: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<String>,
     name: &'a str,
     mode: &'a AudioFunctionMode,
+    #[serde(skip_serializing_if = "LoopRegion::is_default")]
+    loop_region: &'a LoopRegion,
+    #[serde(skip_serializing_if = "Smoothing::is_default")]
+    smoothing: &'a Smoothing,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    synth: Option<&'a SynthSource>,
+    #[serde(skip_serializing_if = "CarrierSchedule::is_empty")]
+    carrier_schedule: &'a CarrierSchedule,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    load_modulation: Option<&'a LoadModulation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    one_shot: Option<&'a OneShotSettings>,
 }
 
-impl<'a> From<&'a AudioEntry> for SerializeAudioEntry<'a> {
-    fn from(value: &'a AudioEntry) -> Self {
-        let id = *value.id();
+impl<'a> SerializeAudioEntry<'a> {
+    fn new(entry: &'a AudioEntry, registory: &FileRegistory) -> Self {
+        let id = *entry.id();
+        // 元のファイル形式のまま埋め込む (合成音は音声ファイルを持たない)
+        let audio = entry.synth().is_none().then(|| {
+            let extension = registory
+                .raw_data_by_id(&id)
+                .and_then(|buf| AudioFormat::detect(buf))
+                .unwrap_or(AudioFormat::OggVorbis)
+                .extension();
+            format!("{id}.{extension}")
+        });
         Self {
             id,
-            audio: format!("{id}.ogg"),
-            name: value.name(),
-            mode: value.mode(),
+            audio,
+            name: entry.name(),
+            mode: entry.mode(),
+            loop_region: entry.loop_region(),
+            smoothing: entry.smoothing(),
+            synth: entry.synth(),
+            carrier_schedule: entry.carrier_schedule(),
+            load_modulation: entry.load_modulation(),
+            one_shot: entry.one_shot(),
         }
     }
 }
@@ -49,18 +96,37 @@ impl<'a> From<&'a AudioEntry> for SerializeAudioEntry<'a> {
 struct DeserializeState {
     audio_entries: Vec<DeserializeAudioEntry>,
     train_performance: TrainPerformance,
+    #[serde(default)]
+    drive_train: DriveTrain,
+    #[serde(default)]
+    track_geometry: TrackGeometry,
+    #[serde(default)]
+    constants: Vec<ExprConstant>,
 }
 
 #[derive(serde::Deserialize)]
 struct DeserializeAudioEntry {
-    audio: String,
+    #[serde(default)]
+    audio: Option<String>,
     name: String,
     mode: AudioFunctionMode,
+    #[serde(default)]
+    loop_region: LoopRegion,
+    #[serde(default)]
+    smoothing: Smoothing,
+    #[serde(default)]
+    synth: Option<SynthSource>,
+    #[serde(default)]
+    carrier_schedule: CarrierSchedule,
+    #[serde(default)]
+    load_modulation: Option<LoadModulation>,
+    #[serde(default)]
+    one_shot: Option<OneShotSettings>,
 }
 
 pub fn save_file(path: &PathBuf, registory: &FileRegistory, state: &State) -> anyhow::Result<()> {
-    // jsonとoggをまとめてzipにして保存、拡張子だけswvf
-    let saved_state: SerializeState<'_> = state.into();
+    // jsonと音声ファイルをまとめてzipにして保存、拡張子だけswvf
+    let saved_state = SerializeState::new(state, registory);
     let json = serde_json::to_string(&saved_state)?;
     let file = File::create(path)?;
     let mut zip = ZipWriter::new(file);
@@ -68,7 +134,7 @@ pub fn save_file(path: &PathBuf, registory: &FileRegistory, state: &State) -> an
 
     // ファイルフォーマットのバージョン
     zip.start_file("VERSION", zip_options)?;
-    zip.write_all(b"0")?;
+    zip.write_all(FILE_VERSION.as_bytes())?;
 
     // JSON
     zip.start_file("state.json", zip_options)?;
@@ -76,8 +142,11 @@ pub fn save_file(path: &PathBuf, registory: &FileRegistory, state: &State) -> an
 
     // 音声ファイル
     for entry in &saved_state.audio_entries {
+        let Some(audio) = &entry.audio else {
+            continue;
+        };
         if let Some(bytes) = registory.raw_data_by_id(&entry.id) {
-            zip.start_file(&entry.audio, zip_options)?;
+            zip.start_file(audio, zip_options)?;
             zip.write_all(bytes)?;
         } else {
             bail!("Unexpected error on saving file");
@@ -99,7 +168,10 @@ pub fn load_file(
 
     let mut version = String::new();
     zip.by_name("VERSION")?.read_to_string(&mut version)?;
-    assert_eq!(version.trim(), "0", "Unsupported file version: {version}");
+    // 0: 走行状態が力行・ブレーキだけ (読み込み時に変換する)
+    if !matches!(version.trim(), "0" | FILE_VERSION) {
+        bail!("Unsupported file version: {version}");
+    }
 
     let mut json = String::new();
     zip.by_name("state.json")?.read_to_string(&mut json)?;
@@ -107,18 +179,38 @@ pub fn load_file(
 
     let mut new_state = State {
         train_performance: saved_state.train_performance,
+        drive_train: saved_state.drive_train,
+        track_geometry: saved_state.track_geometry,
+        constants: saved_state.constants,
         ..Default::default()
     };
 
-    let mut new_registory = FileRegistory::default();
+    let mut new_registory = FileRegistory::new(registory.output_config());
     for entry in &saved_state.audio_entries {
-        let mut buf = Vec::new();
-        zip.by_name(&entry.audio)?.read_to_end(&mut buf)?;
-        let id = new_registory.add_buffered_file(buf, &entry.name, &mut new_state)?;
+        let id = match (&entry.synth, &entry.audio) {
+            (Some(synth), _) => {
+                new_registory.add_synth(&entry.name, synth.clone(), &mut new_state)?
+            }
+            (None, Some(audio)) => {
+                let mut buf = Vec::new();
+                zip.by_name(audio)?.read_to_end(&mut buf)?;
+                new_registory.add_buffered_file(buf, &entry.name, &mut new_state)?
+            }
+            (None, None) => bail!("Audio file of {} is missing", entry.name),
+        };
         let new_entry = new_state
             .get_audio_entry_mut(&id)
             .expect("Unexpected error on loading file");
         *new_entry.mode_mut() = entry.mode.clone();
+        *new_entry.loop_region_mut() = entry.loop_region;
+        *new_entry.smoothing_mut() = entry.smoothing;
+        new_entry
+            .carrier_schedule_mut()
+            .clone_from(&entry.carrier_schedule);
+        new_entry
+            .load_modulation_mut()
+            .clone_from(&entry.load_modulation);
+        new_entry.one_shot_mut().clone_from(&entry.one_shot);
     }
 
     registory.patch_keep_output(new_registory);
@@ -0,0 +1,336 @@
+use crate::{
+    audio_player::{VoiceParams, is_pitch_in_range},
+    func_edit::ExprContext,
+    player_state::PlayerState,
+    state::{
+        AudioEntry, AudioEntryId, DriveTrain, ExprConstant, SoundType, State, SynthSource,
+        TrackGeometry, TrainPerformance, TriggerEvent,
+    },
+};
+use rtrb::PushError;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// シミュレーションを進める間隔 (秒)
+const TICK_SECONDS: f64 = 0.005;
+/// 一度に進める時間の上限 (スリープ復帰直後などに速度が飛ばないように)
+const MAX_TICK_SECONDS: f64 = 0.1;
+/// 送り直しを待つワンショットの合図の上限 (Mixer が止まったままでも溜まり続けないように)
+const MAX_PENDING_TRIGGERS: usize = 64;
+
+/// シミュレーションに使う State の写し
+#[derive(Debug, Clone, Default)]
+pub struct SimulationModel {
+    pub performance: TrainPerformance,
+    pub drive_train: DriveTrain,
+    pub track_geometry: TrackGeometry,
+    pub constants: Vec<ExprConstant>,
+    pub entries: Vec<AudioEntry>,
+    pub global_volume: f32,
+    /// 走行状態ごとの式の文脈 (曲線の写しを持つのでモデルを作るときに一度だけ作る)
+    contexts: HashMap<SoundType, ExprContext>,
+}
+
+impl SimulationModel {
+    pub fn new(state: &State, global_volume: f32) -> Self {
+        Self {
+            performance: state.train_performance.clone(),
+            drive_train: state.drive_train.clone(),
+            track_geometry: state.track_geometry.clone(),
+            constants: state.constants.clone(),
+            entries: state.audio_entries.clone(),
+            global_volume,
+            contexts: expr_contexts(state),
+        }
+    }
+
+    pub fn expr_context(&self, sound_type: SoundType) -> ExprContext {
+        self.contexts.get(&sound_type).cloned().unwrap_or_default()
+    }
+
+    pub fn voice_params<'a>(
+        &'a self,
+        player_state: &'a PlayerState,
+    ) -> impl Iterator<Item = VoiceParams> + 'a {
+        let sound_type = player_state.sounding_sound_type().0;
+        let context = self.contexts.get(&sound_type);
+        self.entries.iter().enumerate().flat_map(move |(i, entry)| {
+            let looped = entry.one_shot().is_none().then(|| {
+                entry_voice_params(
+                    entry,
+                    player_state,
+                    &self.performance,
+                    &context.map(|c| c.with_owner(i)).unwrap_or_default(),
+                    &self.track_geometry,
+                    self.global_volume,
+                )
+            });
+            looped.into_iter().chain(one_shot_params(
+                entry,
+                &self.entries,
+                player_state,
+                &self.track_geometry,
+                self.global_volume,
+            ))
+        })
+    }
+}
+
+/// 走行状態ごとの式の文脈
+pub fn expr_contexts(state: &State) -> HashMap<SoundType, ExprContext> {
+    SoundType::ALL
+        .into_iter()
+        .map(|t| (t, state.expr_context(t)))
+        .collect()
+}
+
+/// 走行状態から音声ごとのパラメータを求める
+///
+/// `context` は鳴っている走行状態の、この音声のための文脈。
+pub fn entry_voice_params(
+    entry: &AudioEntry,
+    player_state: &PlayerState,
+    performance: &TrainPerformance,
+    context: &ExprContext,
+    track: &TrackGeometry,
+    global_volume: f32,
+) -> VoiceParams {
+    let (volume, pitch) = player_state.get_volume_pitch(entry, performance, global_volume, context);
+    VoiceParams {
+        id: *entry.id(),
+        volume,
+        pitch,
+        loop_region: *entry.loop_region(),
+        smoothing: *entry.smoothing(),
+        synth: entry
+            .synth()
+            .map(|s| s.params_at(player_state.speed, entry.carrier_schedule(), context)),
+        // 転動音は継ぎ目を踏むたびに鳴らす
+        trigger: matches!(entry.synth(), Some(SynthSource::Noise(_)))
+            && player_state.event_fired(TriggerEvent::RailJoint, entry.carrier_schedule(), track),
+    }
+}
+
+/// ワンショット音声のうち、きっかけが起きて条件を満たすものを鳴らす合図
+///
+/// `entries` は搬送波モードの変化を調べる音声を探すのに使う。
+pub fn one_shot_params<'a>(
+    entry: &'a AudioEntry,
+    entries: &'a [AudioEntry],
+    player_state: &'a PlayerState,
+    track: &'a TrackGeometry,
+    global_volume: f32,
+) -> impl Iterator<Item = VoiceParams> + 'a {
+    let sound_type = player_state.active_sound_type();
+    entry
+        .one_shot()
+        .into_iter()
+        .flat_map(|settings| &settings.triggers)
+        .filter(move |t| {
+            let watched = t
+                .schedule_entry
+                .and_then(|i| entries.get(i))
+                .unwrap_or(entry);
+            player_state.event_fired(t.event, watched.carrier_schedule(), track)
+                && t.conditions_met(player_state.speed, sound_type)
+        })
+        .map(move |t| VoiceParams {
+            id: *entry.id(),
+            volume: global_volume * t.volume as f32,
+            pitch: t.pitch as f32,
+            loop_region: *entry.loop_region(),
+            smoothing: *entry.smoothing(),
+            synth: None,
+            trigger: true,
+        })
+}
+
+struct Shared {
+    model: SimulationModel,
+    player_state: PlayerState,
+    params: Option<rtrb::Producer<VoiceParams>>,
+    /// 鳴っているのに扱えない範囲のピッチになっている音声
+    pitch_warnings: Vec<(AudioEntryId, f32)>,
+    /// キューが一杯で送れなかったワンショットの合図
+    pending_triggers: Vec<VoiceParams>,
+}
+
+impl Shared {
+    fn new() -> Self {
+        Self {
+            model: SimulationModel::default(),
+            player_state: PlayerState::default(),
+            params: None,
+            pitch_warnings: vec![],
+            pending_triggers: vec![],
+        }
+    }
+
+    fn tick(&mut self, dt: f64) {
+        let performance = &self.model.performance;
+        self.player_state.check(performance);
+        let context = self
+            .model
+            .expr_context(self.player_state.active_sound_type());
+        self.player_state
+            .step(dt.min(MAX_TICK_SECONDS), performance, &context);
+
+        self.pitch_warnings.clear();
+        // 前の tick で送れなかった合図を先に送る
+        let mut unsent = vec![];
+        for p in std::mem::take(&mut self.pending_triggers) {
+            push_params(self.params.as_mut(), p, &mut unsent);
+        }
+        for p in self.model.voice_params(&self.player_state) {
+            if p.volume > 0.0 && !is_pitch_in_range(p.pitch) {
+                self.pitch_warnings.push((p.id, p.pitch));
+            }
+            push_params(self.params.as_mut(), p, &mut unsent);
+        }
+        unsent.truncate(MAX_PENDING_TRIGGERS);
+        self.pending_triggers = unsent;
+        self.player_state.reset_event_origin();
+    }
+}
+
+/// Mixer が止まっていてキューが一杯なら、音量・ピッチは次の tick で求め直し、
+/// 一度きりの合図は `unsent` に取っておいて送り直す
+fn push_params(
+    params: Option<&mut rtrb::Producer<VoiceParams>>,
+    p: VoiceParams,
+    unsent: &mut Vec<VoiceParams>,
+) {
+    if let Some(params) = params {
+        match params.push(p) {
+            Err(PushError::Full(p)) if p.trigger => unsent.push(p),
+            _ => {}
+        }
+    }
+}
+
+/// UI のフレームとは独立した時計で列車を走らせ、Mixer に音量・ピッチを送る
+pub struct Simulation {
+    shared: Arc<Mutex<Shared>>,
+    /// 最後に送ったモデル (変わったときだけ送る)
+    model: SimulationModel,
+    pitch_warnings: Vec<(AudioEntryId, f32)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    running: Arc<std::sync::atomic::AtomicBool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    handle: Option<std::thread::JoinHandle<()>>,
+    #[cfg(target_arch = "wasm32")]
+    last_tick: Option<std::time::Instant>,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulation {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> Self {
+        use std::{
+            sync::atomic::{AtomicBool, Ordering},
+            time::{Duration, Instant},
+        };
+
+        let shared = Arc::new(Mutex::new(Shared::new()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_shared = shared.clone();
+        let thread_running = running.clone();
+        let handle = std::thread::spawn(move || {
+            let period = Duration::from_secs_f64(TICK_SECONDS);
+            let mut last = Instant::now();
+            while thread_running.load(Ordering::Relaxed) {
+                std::thread::sleep(period.saturating_sub(last.elapsed()));
+                let now = Instant::now();
+                let dt = now.duration_since(last).as_secs_f64();
+                last = now;
+                if let Ok(mut shared) = thread_shared.lock() {
+                    shared.tick(dt);
+                }
+            }
+        });
+
+        Self {
+            shared,
+            model: SimulationModel::default(),
+            pitch_warnings: vec![],
+            running,
+            handle: Some(handle),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared::new())),
+            model: SimulationModel::default(),
+            pitch_warnings: vec![],
+            last_tick: None,
+        }
+    }
+
+    /// スレッドが使えない環境では UI のフレームごとに進める
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) {
+        let now = std::time::Instant::now();
+        if let Some(last) = self.last_tick {
+            if let Ok(mut shared) = self.shared.lock() {
+                shared.tick(now.duration_since(last).as_secs_f64());
+            }
+        }
+        self.last_tick = Some(now);
+    }
+
+    pub fn set_param_sink(&self, params: rtrb::Producer<VoiceParams>) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.params = Some(params);
+        }
+    }
+
+    pub fn update_model(&mut self, state: &State, global_volume: f32) {
+        let model = &self.model;
+        if model.performance == state.train_performance
+            && model.drive_train == state.drive_train
+            && model.track_geometry == state.track_geometry
+            && model.constants == state.constants
+            && model.entries == state.audio_entries
+            && model.global_volume == global_volume
+        {
+            return;
+        }
+        self.model = SimulationModel::new(state, global_volume);
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.model = self.model.clone();
+        }
+    }
+
+    /// UI の操作をシミュレーションへ渡し、走行状態を UI へ写す
+    pub fn sync(&mut self, player_state: &mut PlayerState) {
+        if let Ok(mut shared) = self.shared.lock() {
+            player_state.sync_with(&mut shared.player_state);
+            self.pitch_warnings.clone_from(&shared.pitch_warnings);
+        }
+    }
+
+    pub fn pitch_warnings(&self) -> &[(AudioEntryId, f32)] {
+        &self.pitch_warnings
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.running
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
@@ -1,5 +1,8 @@
-use super::AudioEntryId;
-use crate::func_edit::{EditableFunc, EditablePositiveFunc, EditableZeroOneFunc};
+use super::{AudioEntryId, CarrierSchedule, LoadModulation, OneShotSettings, SynthSource};
+use crate::{
+    audio_player::{LoopRegion, Smoothing},
+    func_edit::{EditableFunc, EditablePositiveFunc, EditableZeroOneFunc, ExprContext, FuncEdit},
+};
 use anyhow::Context as _;
 use std::path::PathBuf;
 
@@ -7,6 +10,9 @@ use std::path::PathBuf;
 pub struct AudioFunctions {
     pub volume: EditableZeroOneFunc,
     pub pitch: EditablePositiveFunc,
+    /// 最低ノッチでの曲線 (あれば `volume`, `pitch` は最高ノッチでの曲線になる)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_notch: Option<Box<NotchLayer>>,
 }
 
 impl Default for AudioFunctions {
@@ -14,143 +20,234 @@ impl Default for AudioFunctions {
         Self {
             volume: EditableFunc::with_points(vec![(40.0, 0.5)]).into(),
             pitch: EditableFunc::with_points(vec![(40.0, 1.0)]).into(),
+            low_notch: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+impl AudioFunctions {
+    /// `notch` は最低ノッチを 0、最高ノッチを 1 とした位置
+    pub fn volume_at(&self, x: f64, notch: f64, context: &ExprContext) -> f64 {
+        let high = self.volume.value_at(x, context);
+        match &self.low_notch {
+            Some(layer) => lerp(layer.volume.value_at(x, context), high, notch),
+            None => high,
+        }
+    }
+
+    pub fn pitch_at(&self, x: f64, notch: f64, context: &ExprContext) -> f64 {
+        let high = self.pitch.value_at(x, context);
+        match self.low_notch.as_ref().and_then(|l| l.pitch.as_ref()) {
+            Some(pitch) => lerp(pitch.value_at(x, context), high, notch),
+            None => high,
+        }
+    }
+
+    pub fn for_each_func_mut(&mut self, f: &mut dyn FnMut(&mut dyn FuncEdit)) {
+        f(&mut self.volume);
+        f(&mut self.pitch);
+        if let Some(layer) = self.low_notch.as_mut() {
+            f(&mut layer.volume);
+            if let Some(pitch) = layer.pitch.as_mut() {
+                f(pitch);
+            }
+        }
+    }
+
+    /// 最低ノッチの曲線を今の曲線から作る
+    pub fn set_notch_dependent(&mut self, enabled: bool) {
+        if !enabled {
+            self.low_notch = None;
+        } else if self.low_notch.is_none() {
+            self.low_notch = Some(Box::new(NotchLayer {
+                volume: self.volume.clone(),
+                pitch: None,
+            }));
+        }
+    }
+}
+
+/// 最低ノッチでの曲線 (間のノッチは最高ノッチとの直線補間)
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct NotchLayer {
+    pub volume: EditableZeroOneFunc,
+    /// 無ければピッチはノッチによらない
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<EditablePositiveFunc>,
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// 走行状態ごとの音の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
 pub enum SoundType {
+    /// 力行
     Accel,
+    /// 回生ブレーキ
     Brake,
+    /// 惰行 (ノッチオフで走行中)
+    Coast,
+    /// 停車中
+    Idle,
 }
 
-#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
-pub enum AudioFunctionMode {
-    Common(AudioFunctions),
-    Separate {
-        accel: AudioFunctions,
-        brake: Box<AudioFunctions>,
-    },
-    AccelOnly(AudioFunctions),
-    BrakeOnly(AudioFunctions),
+impl SoundType {
+    pub const ALL: [Self; 4] = [Self::Accel, Self::Brake, Self::Coast, Self::Idle];
+
+    pub fn label_text(self) -> &'static str {
+        match self {
+            Self::Accel => "Accel",
+            Self::Brake => "Brake",
+            Self::Coast => "Coast",
+            Self::Idle => "Idle",
+        }
+    }
 }
 
-impl AudioFunctionMode {
-    pub const TEXT_COMMON: &str = "Common";
-    pub const TEXT_SEPARATE: &str = "Separate";
-    pub const TEXT_ACCEL_ONLY: &str = "Accel Only";
-    pub const TEXT_BRAKE_ONLY: &str = "Brake Only";
+/// 走行状態ごとの曲線 (無い状態では鳴らさない)
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct StateFunctions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accel: Option<AudioFunctions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brake: Option<AudioFunctions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coast: Option<AudioFunctions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle: Option<AudioFunctions>,
+}
 
-    pub fn is_common(&self) -> bool {
-        matches!(self, Self::Common(_))
+impl StateFunctions {
+    pub fn get(&self, sound_type: SoundType) -> Option<&AudioFunctions> {
+        match sound_type {
+            SoundType::Accel => self.accel.as_ref(),
+            SoundType::Brake => self.brake.as_ref(),
+            SoundType::Coast => self.coast.as_ref(),
+            SoundType::Idle => self.idle.as_ref(),
+        }
     }
 
-    pub fn is_separate(&self) -> bool {
-        matches!(self, Self::Separate { .. })
+    pub fn get_mut(&mut self, sound_type: SoundType) -> Option<&mut AudioFunctions> {
+        self.slot_mut(sound_type).as_mut()
     }
 
-    pub fn is_accel_only(&self) -> bool {
-        matches!(self, Self::AccelOnly(_))
+    fn slot_mut(&mut self, sound_type: SoundType) -> &mut Option<AudioFunctions> {
+        match sound_type {
+            SoundType::Accel => &mut self.accel,
+            SoundType::Brake => &mut self.brake,
+            SoundType::Coast => &mut self.coast,
+            SoundType::Idle => &mut self.idle,
+        }
     }
 
-    pub fn is_brake_only(&self) -> bool {
-        matches!(self, Self::BrakeOnly(_))
+    /// 最初に見つかった曲線
+    fn first(&self) -> Option<&AudioFunctions> {
+        SoundType::ALL.into_iter().find_map(|t| self.get(t))
     }
 
-    pub fn get_by_type(&self, audio_type: SoundType) -> Option<&AudioFunctions> {
-        match audio_type {
-            SoundType::Accel => self.accel(),
-            SoundType::Brake => self.brake(),
+    /// 曲線を追加するときは他の状態の曲線を写す
+    pub fn set_enabled(&mut self, sound_type: SoundType, enabled: bool) {
+        if enabled {
+            if self.get(sound_type).is_none() {
+                let funcs = self.first().cloned().unwrap_or_default();
+                *self.slot_mut(sound_type) = Some(funcs);
+            }
+        } else {
+            *self.slot_mut(sound_type) = None;
         }
     }
+}
 
-    pub fn get_by_type_mut(&mut self, audio_type: SoundType) -> Option<&mut AudioFunctions> {
-        match audio_type {
-            SoundType::Accel => self.accel_mut(),
-            SoundType::Brake => self.brake_mut(),
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(from = "AudioFunctionModeFormat")]
+pub enum AudioFunctionMode {
+    /// 力行とブレーキで同じ曲線を使い、惰行・停車中は鳴らさない
+    Common(Box<AudioFunctions>),
+    Separate(Box<StateFunctions>),
+}
+
+/// 読み込み用 (以前の `AccelOnly`, `BrakeOnly` を `Separate` に変換する)
+#[derive(serde::Deserialize)]
+enum AudioFunctionModeFormat {
+    Common(Box<AudioFunctions>),
+    Separate(Box<StateFunctions>),
+    AccelOnly(AudioFunctions),
+    BrakeOnly(AudioFunctions),
+}
+
+impl From<AudioFunctionModeFormat> for AudioFunctionMode {
+    fn from(value: AudioFunctionModeFormat) -> Self {
+        match value {
+            AudioFunctionModeFormat::Common(funcs) => Self::Common(funcs),
+            AudioFunctionModeFormat::Separate(funcs) => Self::Separate(funcs),
+            AudioFunctionModeFormat::AccelOnly(funcs) => Self::Separate(Box::new(StateFunctions {
+                accel: Some(funcs),
+                ..Default::default()
+            })),
+            AudioFunctionModeFormat::BrakeOnly(funcs) => Self::Separate(Box::new(StateFunctions {
+                brake: Some(funcs),
+                ..Default::default()
+            })),
         }
     }
+}
 
-    pub fn accel(&self) -> Option<&AudioFunctions> {
-        match self {
-            Self::Separate { accel, .. } => Some(accel),
-            Self::AccelOnly(funcs) | Self::Common(funcs) => Some(funcs),
-            Self::BrakeOnly(_) => None,
-        }
+impl AudioFunctionMode {
+    pub const TEXT_COMMON: &str = "Common";
+    pub const TEXT_SEPARATE: &str = "Separate";
+
+    pub fn is_common(&self) -> bool {
+        matches!(self, Self::Common(_))
     }
 
-    pub fn accel_mut(&mut self) -> Option<&mut AudioFunctions> {
-        match self {
-            Self::Separate { accel, .. } => Some(accel),
-            Self::AccelOnly(funcs) | Self::Common(funcs) => Some(funcs),
-            Self::BrakeOnly(_) => None,
-        }
+    pub fn is_separate(&self) -> bool {
+        matches!(self, Self::Separate(_))
     }
 
-    pub fn brake(&self) -> Option<&AudioFunctions> {
+    pub fn get_by_type(&self, audio_type: SoundType) -> Option<&AudioFunctions> {
         match self {
-            Self::Separate { brake, .. } => Some(brake),
-            Self::AccelOnly(_) => None,
-            Self::BrakeOnly(funcs) | Self::Common(funcs) => Some(funcs),
+            Self::Common(funcs) => {
+                matches!(audio_type, SoundType::Accel | SoundType::Brake).then_some(funcs)
+            }
+            Self::Separate(funcs) => funcs.get(audio_type),
         }
     }
 
-    pub fn brake_mut(&mut self) -> Option<&mut AudioFunctions> {
+    pub fn get_by_type_mut(&mut self, audio_type: SoundType) -> Option<&mut AudioFunctions> {
         match self {
-            Self::Separate { brake, .. } => Some(brake),
-            Self::AccelOnly(_) => None,
-            Self::BrakeOnly(funcs) | Self::Common(funcs) => Some(funcs),
+            Self::Common(funcs) => {
+                matches!(audio_type, SoundType::Accel | SoundType::Brake).then_some(funcs)
+            }
+            Self::Separate(funcs) => funcs.get_mut(audio_type),
         }
     }
 
     pub fn label_text(&self) -> &str {
         match self {
             Self::Common(_) => Self::TEXT_COMMON,
-            Self::Separate { .. } => Self::TEXT_SEPARATE,
-            Self::AccelOnly(_) => Self::TEXT_ACCEL_ONLY,
-            Self::BrakeOnly(_) => Self::TEXT_BRAKE_ONLY,
+            Self::Separate(_) => Self::TEXT_SEPARATE,
         }
     }
 
     pub fn to_common(&self) -> Self {
         match self {
-            Self::Separate { accel, .. } => Self::Common(accel.clone()),
-            Self::AccelOnly(funcs) | Self::BrakeOnly(funcs) | Self::Common(funcs) => {
-                Self::Common(funcs.clone())
+            Self::Common(funcs) => Self::Common(funcs.clone()),
+            Self::Separate(funcs) => {
+                Self::Common(Box::new(funcs.first().cloned().unwrap_or_default()))
             }
         }
     }
 
     pub fn to_separate(&self) -> Self {
         match self {
-            Self::Separate { accel, brake } => Self::Separate {
-                accel: accel.clone(),
-                brake: brake.clone(),
-            },
-            Self::AccelOnly(funcs) | Self::Common(funcs) | Self::BrakeOnly(funcs) => {
-                Self::Separate {
-                    accel: funcs.clone(),
-                    brake: Box::new(funcs.clone()),
-                }
-            }
-        }
-    }
-
-    pub fn to_accel_only(&self) -> Self {
-        match self {
-            Self::Separate { accel, .. } => Self::AccelOnly(accel.clone()),
-            Self::AccelOnly(funcs) | Self::BrakeOnly(funcs) | Self::Common(funcs) => {
-                Self::AccelOnly(funcs.clone())
-            }
-        }
-    }
-
-    pub fn to_brake_only(&self) -> Self {
-        match self {
-            Self::Separate { brake, .. } => Self::BrakeOnly(*brake.clone()),
-            Self::AccelOnly(funcs) | Self::Common(funcs) | Self::BrakeOnly(funcs) => {
-                Self::BrakeOnly(funcs.clone())
-            }
+            Self::Common(funcs) => Self::Separate(Box::new(StateFunctions {
+                accel: Some(*funcs.clone()),
+                brake: Some(*funcs.clone()),
+                ..Default::default()
+            })),
+            Self::Separate(funcs) => Self::Separate(funcs.clone()),
         }
     }
 }
@@ -162,6 +259,19 @@ pub struct AudioEntry {
     pub(super) path: Option<PathBuf>,
     name: String,
     mode: AudioFunctionMode,
+    #[serde(default)]
+    loop_region: LoopRegion,
+    #[serde(default)]
+    smoothing: Smoothing,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    synth: Option<SynthSource>,
+    #[serde(default, skip_serializing_if = "CarrierSchedule::is_empty")]
+    carrier_schedule: CarrierSchedule,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    load_modulation: Option<LoadModulation>,
+    /// あればループせず、きっかけがあるたびに一度だけ鳴らす
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    one_shot: Option<OneShotSettings>,
 }
 
 impl AudioEntry {
@@ -170,7 +280,20 @@ impl AudioEntry {
             id,
             path: None,
             name: name.to_owned(),
-            mode: AudioFunctionMode::Common(AudioFunctions::default()),
+            mode: AudioFunctionMode::Common(Box::default()),
+            loop_region: LoopRegion::default(),
+            smoothing: Smoothing::default(),
+            synth: None,
+            carrier_schedule: CarrierSchedule::default(),
+            load_modulation: None,
+            one_shot: None,
+        }
+    }
+
+    pub fn with_synth(id: u32, name: &str, synth: SynthSource) -> Self {
+        Self {
+            synth: Some(synth),
+            ..Self::new(id, name)
         }
     }
 
@@ -184,7 +307,13 @@ impl AudioEntry {
             id,
             path: Some(path),
             name: name.to_owned(),
-            mode: AudioFunctionMode::Common(AudioFunctions::default()),
+            mode: AudioFunctionMode::Common(Box::default()),
+            loop_region: LoopRegion::default(),
+            smoothing: Smoothing::default(),
+            synth: None,
+            carrier_schedule: CarrierSchedule::default(),
+            load_modulation: None,
+            one_shot: None,
         })
     }
 
@@ -204,11 +333,87 @@ impl AudioEntry {
         &mut self.mode
     }
 
+    pub fn loop_region(&self) -> &LoopRegion {
+        &self.loop_region
+    }
+
+    pub fn loop_region_mut(&mut self) -> &mut LoopRegion {
+        &mut self.loop_region
+    }
+
+    pub fn smoothing(&self) -> &Smoothing {
+        &self.smoothing
+    }
+
+    pub fn smoothing_mut(&mut self) -> &mut Smoothing {
+        &mut self.smoothing
+    }
+
+    pub fn synth(&self) -> Option<&SynthSource> {
+        self.synth.as_ref()
+    }
+
+    pub fn synth_mut(&mut self) -> Option<&mut SynthSource> {
+        self.synth.as_mut()
+    }
+
+    pub fn carrier_schedule(&self) -> &CarrierSchedule {
+        &self.carrier_schedule
+    }
+
+    pub fn carrier_schedule_mut(&mut self) -> &mut CarrierSchedule {
+        &mut self.carrier_schedule
+    }
+
+    pub fn load_modulation(&self) -> Option<&LoadModulation> {
+        self.load_modulation.as_ref()
+    }
+
+    pub fn load_modulation_mut(&mut self) -> &mut Option<LoadModulation> {
+        &mut self.load_modulation
+    }
+
+    pub fn one_shot(&self) -> Option<&OneShotSettings> {
+        self.one_shot.as_ref()
+    }
+
+    pub fn one_shot_mut(&mut self) -> &mut Option<OneShotSettings> {
+        &mut self.one_shot
+    }
+
+    /// ワンショット音声では曲線を使わない (切り替えても残しておく)
     pub fn funcs_by_type(&self, sound_type: SoundType) -> Option<&AudioFunctions> {
-        self.mode.get_by_type(sound_type)
+        self.one_shot
+            .is_none()
+            .then(|| self.mode.get_by_type(sound_type))
+            .flatten()
+    }
+
+    /// 表示していないものも含めた全ての曲線
+    pub fn for_each_func_mut(&mut self, f: &mut dyn FnMut(&mut dyn FuncEdit)) {
+        match &mut self.mode {
+            // 力行とブレーキで同じ曲線なので一度だけ
+            AudioFunctionMode::Common(funcs) => funcs.for_each_func_mut(f),
+            AudioFunctionMode::Separate(funcs) => {
+                for sound_type in SoundType::ALL {
+                    if let Some(funcs) = funcs.get_mut(sound_type) {
+                        funcs.for_each_func_mut(f);
+                    }
+                }
+            }
+        }
+        if let Some(synth) = &mut self.synth {
+            synth.for_each_func_mut(f);
+        }
+        if let Some(modulation) = &mut self.load_modulation {
+            f(&mut modulation.gain);
+        }
     }
 
     pub fn funcs_by_type_mut(&mut self, sound_type: SoundType) -> Option<&mut AudioFunctions> {
+        if self.one_shot.is_some() {
+            return None;
+        }
         self.mode.get_by_type_mut(sound_type)
     }
 }
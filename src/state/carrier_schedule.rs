@@ -0,0 +1,149 @@
+use crate::audio_player::CarrierMode;
+
+/// `start` の速度から次の区間の手前までの搬送波モード
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CarrierRegion {
+    pub start: f64,
+    pub mode: CarrierMode,
+}
+
+/// 速度ごとの搬送波モードの切り替え
+///
+/// 区間は開始速度の順に並び、最初の区間は速度 0 から始まる。
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(from = "RawCarrierSchedule")]
+pub struct CarrierSchedule {
+    regions: Vec<CarrierRegion>,
+}
+
+/// ファイルから読んだままの区間 (手で編集されて並びが崩れていることがある)
+#[derive(serde::Deserialize)]
+struct RawCarrierSchedule {
+    #[serde(default)]
+    regions: Vec<CarrierRegion>,
+}
+
+impl From<RawCarrierSchedule> for CarrierSchedule {
+    fn from(raw: RawCarrierSchedule) -> Self {
+        let mut sorted: Vec<CarrierRegion> = raw
+            .regions
+            .into_iter()
+            .filter(|r| r.start.is_finite())
+            .map(|r| CarrierRegion {
+                start: r.start.max(0.0),
+                ..r
+            })
+            .collect();
+        sorted.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        // 幅のない区間は後の区間に置き換える
+        let mut regions: Vec<CarrierRegion> = Vec::with_capacity(sorted.len());
+        for region in sorted {
+            match regions.last_mut() {
+                Some(last) if last.start == region.start => *last = region,
+                _ => regions.push(region),
+            }
+        }
+        if let Some(first) = regions.first_mut() {
+            first.start = 0.0;
+        }
+        Self { regions }
+    }
+}
+
+impl CarrierSchedule {
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn regions(&self) -> &[CarrierRegion] {
+        &self.regions
+    }
+
+    /// 区間ごとの (開始速度, 終了速度, モード)、最後の区間の終了速度は無限大
+    pub fn ranges(&self) -> impl Iterator<Item = (f64, f64, CarrierMode)> + '_ {
+        self.regions.iter().enumerate().map(|(i, r)| {
+            let end = self.regions.get(i + 1).map_or(f64::INFINITY, |n| n.start);
+            (r.start, end, r.mode)
+        })
+    }
+
+    pub fn region_index_at(&self, speed: f64) -> Option<usize> {
+        if self.regions.is_empty() {
+            return None;
+        }
+        Some(
+            self.regions
+                .iter()
+                .rposition(|r| r.start <= speed)
+                .unwrap_or(0),
+        )
+    }
+
+    pub fn mode_at(&self, speed: f64) -> Option<CarrierMode> {
+        self.region_index_at(speed).map(|i| self.regions[i].mode)
+    }
+
+    /// 空なら全体を 1 つの区間にする
+    pub fn init(&mut self, mode: CarrierMode) {
+        if self.regions.is_empty() {
+            self.regions.push(CarrierRegion { start: 0.0, mode });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    /// `speed` で区間を分け、新しい区間の番号を返す
+    pub fn split_at(&mut self, speed: f64) -> Option<usize> {
+        let index = self.region_index_at(speed)?;
+        let region = self.regions[index];
+        if speed <= region.start {
+            return None;
+        }
+        self.regions.insert(
+            index + 1,
+            CarrierRegion {
+                start: speed,
+                mode: region.mode,
+            },
+        );
+        Some(index + 1)
+    }
+
+    /// 区間を消して前の区間に含める (最初の区間は次の区間に含める)
+    pub fn remove(&mut self, index: usize) {
+        if index >= self.regions.len() {
+            return;
+        }
+        self.regions.remove(index);
+        if index == 0 {
+            if let Some(first) = self.regions.first_mut() {
+                first.start = 0.0;
+            }
+        }
+    }
+
+    /// 区間の開始速度を隣の区間を越えない範囲で動かす
+    pub fn move_start(&mut self, index: usize, speed: f64) -> Option<f64> {
+        if index == 0 || index >= self.regions.len() {
+            return None;
+        }
+        let min = self.regions[index - 1].start;
+        let max = self
+            .regions
+            .get(index + 1)
+            .map_or(f64::INFINITY, |r| r.start);
+        // 読み込んだ区間が崩れていても止まらないように clamp は使わない
+        let speed = speed.max(min).min(max);
+        self.regions[index].start = speed;
+        Some(speed)
+    }
+
+    pub fn set_mode(&mut self, index: usize, mode: CarrierMode) {
+        if let Some(region) = self.regions.get_mut(index) {
+            region.mode = mode;
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use super::SoundType;
+use crate::func_edit::ExprContext;
+
+/// 車両の駆動系 (式の変数に使う)
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct DriveTrain {
+    /// 車輪径 (m)
+    pub wheel_diameter: f64,
+    /// 歯車比 (モーター回転数 / 車輪回転数)
+    pub gear_ratio: f64,
+    /// モーターの極数
+    pub pole_count: u32,
+    /// すべり (回転子の遅れ、インバーター周波数に対する割合)
+    pub slip: f64,
+}
+
+impl Default for DriveTrain {
+    fn default() -> Self {
+        Self {
+            wheel_diameter: 0.86,
+            gear_ratio: 6.0,
+            pole_count: 4,
+            slip: 0.02,
+        }
+    }
+}
+
+impl DriveTrain {
+    /// 式で使える変数の名前と説明
+    pub const VARIABLES: [(&str, &str); 4] = [
+        ("x", "Speed (km/h)"),
+        ("wheel_hz", "Wheel rotational frequency (Hz)"),
+        ("motor_hz", "Motor rotational frequency (Hz)"),
+        ("inverter_hz", "Inverter output frequency (Hz)"),
+    ];
+
+    /// 速度 1 km/h あたりの車輪の回転数 (Hz)
+    fn wheel_coefficient(&self) -> f64 {
+        if self.wheel_diameter > 0.0 {
+            1.0 / 3.6 / (std::f64::consts::PI * self.wheel_diameter)
+        } else {
+            0.0
+        }
+    }
+
+    /// 速度 1 km/h あたりのインバーター周波数 (Hz)
+    ///
+    /// 力行では回転より速く、回生では遅く回転磁界を回す。
+    fn inverter_coefficient(&self, sound_type: SoundType) -> f64 {
+        let slip = match sound_type {
+            SoundType::Accel => self.slip,
+            SoundType::Brake => -self.slip,
+            SoundType::Coast | SoundType::Idle => 0.0,
+        };
+        self.wheel_coefficient() * self.gear_ratio * self.pole_count as f64 / 2.0 * (1.0 + slip)
+    }
+
+    pub fn inverter_frequency(&self, speed: f64, sound_type: SoundType) -> f64 {
+        speed * self.inverter_coefficient(sound_type)
+    }
+
+    pub fn expr_context(&self, sound_type: SoundType) -> ExprContext {
+        let wheel = self.wheel_coefficient();
+        ExprContext::default()
+            .with_proportional("wheel_hz", wheel)
+            .with_proportional("motor_hz", wheel * self.gear_ratio)
+            .with_proportional("inverter_hz", self.inverter_coefficient(sound_type))
+    }
+}
@@ -0,0 +1,115 @@
+use super::SoundType;
+
+/// ワンショット音声を鳴らすきっかけ
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum TriggerEvent {
+    /// マスコンを力行側へ動かした (ブレーキを緩めたときも含む)
+    NotchUp,
+    /// マスコンをブレーキ側へ動かした
+    NotchDown,
+    PowerOn,
+    PowerOff,
+    BrakeOn,
+    BrakeOff,
+    /// 加速中にこの速度 (km/h) を超えた
+    SpeedRising(f64),
+    /// 減速中にこの速度 (km/h) を下回った
+    SpeedFalling(f64),
+    /// 搬送波モードの区間が変わった ([`EventTrigger::schedule_entry`] の音声で調べる)
+    ModeChange,
+    /// 停車した
+    Stop,
+    /// 車軸がレールの継ぎ目を踏んだ
+    RailJoint,
+}
+
+impl TriggerEvent {
+    pub const ALL: [Self; 11] = [
+        Self::NotchUp,
+        Self::NotchDown,
+        Self::PowerOn,
+        Self::PowerOff,
+        Self::BrakeOn,
+        Self::BrakeOff,
+        Self::SpeedRising(30.0),
+        Self::SpeedFalling(30.0),
+        Self::ModeChange,
+        Self::Stop,
+        Self::RailJoint,
+    ];
+
+    pub fn label_text(self) -> &'static str {
+        match self {
+            Self::NotchUp => "Notch up",
+            Self::NotchDown => "Notch down",
+            Self::PowerOn => "Power on",
+            Self::PowerOff => "Power off",
+            Self::BrakeOn => "Brake on",
+            Self::BrakeOff => "Brake off",
+            Self::SpeedRising(_) => "Speed rising past",
+            Self::SpeedFalling(_) => "Speed falling past",
+            Self::ModeChange => "Mode change",
+            Self::Stop => "Stop",
+            Self::RailJoint => "Rail joint",
+        }
+    }
+
+    /// 速度のしきい値を持つものは値によらず同じ種類とみなす
+    pub fn same_kind(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    pub fn threshold_mut(&mut self) -> Option<&mut f64> {
+        match self {
+            Self::SpeedRising(speed) | Self::SpeedFalling(speed) => Some(speed),
+            _ => None,
+        }
+    }
+}
+
+/// きっかけと、鳴らす条件・音量・ピッチ
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EventTrigger {
+    pub event: TriggerEvent,
+    pub volume: f64,
+    pub pitch: f64,
+    /// この速度範囲 (km/h) のときだけ鳴らす
+    #[serde(default)]
+    pub min_speed: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_speed: Option<f64>,
+    /// きっかけの後の走行状態がこれのときだけ鳴らす
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound_type: Option<SoundType>,
+    /// 搬送波モードの変化を調べる音声の番号 (無ければこの音声)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule_entry: Option<usize>,
+}
+
+impl Default for EventTrigger {
+    fn default() -> Self {
+        Self {
+            event: TriggerEvent::NotchUp,
+            volume: 0.5,
+            pitch: 1.0,
+            min_speed: 0.0,
+            max_speed: None,
+            sound_type: None,
+            schedule_entry: None,
+        }
+    }
+}
+
+impl EventTrigger {
+    pub fn conditions_met(&self, speed: f64, sound_type: SoundType) -> bool {
+        speed >= self.min_speed
+            && self.max_speed.is_none_or(|max| speed <= max)
+            && self.sound_type.is_none_or(|t| t == sound_type)
+    }
+}
+
+/// ループせず、きっかけがあるたびに一度だけ鳴らす音声の設定
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct OneShotSettings {
+    pub triggers: Vec<EventTrigger>,
+}
@@ -0,0 +1,26 @@
+/// 式で名前を書いて使えるプロジェクトの定数
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ExprConstant {
+    pub name: String,
+    pub value: f64,
+}
+
+impl Default for ExprConstant {
+    fn default() -> Self {
+        Self {
+            name: "k".to_owned(),
+            value: 1.0,
+        }
+    }
+}
+
+impl ExprConstant {
+    /// 式の中で変数として読める名前か
+    pub fn is_valid_name(&self) -> bool {
+        let mut chars = self.name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+}
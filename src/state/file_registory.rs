@@ -1,16 +1,16 @@
-use super::State;
+use super::{State, SynthSource};
 use crate::{
-    audio_player::{AudioOutput, AudioSource, ResampledLoopAudio},
-    player_state::PlayerState,
-    preference::Preference,
+    audio_player::{
+        AudioOutput, AudioSource, Mixer, MixerCommand, MixerHandle, OutputConfig, ResamplerQuality,
+        SynthKind, Voice, VoiceParams,
+    },
     state::AudioEntry,
 };
-use anyhow::{anyhow, bail};
-use lewton::inside_ogg::OggStreamReader;
+use anyhow::anyhow;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::Arc,
 };
 
 pub type AudioEntryId = u32;
@@ -18,30 +18,102 @@ pub type AudioEntryId = u32;
 #[derive(Default)]
 pub struct FileRegistory {
     raw_data: HashMap<AudioEntryId, Vec<u8>>,
-    pub audio_sources: Arc<Mutex<HashMap<AudioEntryId, anyhow::Result<ResampledLoopAudio>>>>,
+    decoded: HashMap<AudioEntryId, Arc<AudioSource>>,
+    /// 音声ファイルを持たない合成音
+    synths: HashMap<AudioEntryId, SynthKind>,
+    /// ループせずにワンショットで鳴らす音声
+    one_shots: HashSet<AudioEntryId>,
+    mixer: Option<MixerHandle>,
     next_id: AudioEntryId,
-    audio_output: AudioOutput,
+    output_config: OutputConfig,
+    resampler_quality: ResamplerQuality,
 }
 
 impl FileRegistory {
+    pub fn new(output_config: OutputConfig) -> Self {
+        Self {
+            output_config,
+            ..Default::default()
+        }
+    }
+
+    pub fn output_config(&self) -> OutputConfig {
+        self.output_config
+    }
+
+    /// 出力設定に合わせて全ての音声をデコードし直す
+    pub fn set_output_config(&mut self, output_config: OutputConfig) -> anyhow::Result<()> {
+        if self.output_config == output_config {
+            return Ok(());
+        }
+        self.output_config = output_config;
+
+        let decoded = self
+            .raw_data
+            .iter()
+            .map(|(id, raw)| Ok((*id, self.read_buffer(raw)?)))
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        self.decoded = decoded;
+        Ok(())
+    }
+
+    /// リサンプラーを変えて音声を作り直す
+    pub fn set_resampler_quality(&mut self, quality: ResamplerQuality) -> anyhow::Result<()> {
+        if self.resampler_quality == quality {
+            return Ok(());
+        }
+        self.resampler_quality = quality;
+        self.rebuild_voices()
+    }
+
+    /// 新しい出力に合わせて音声を作り直し、再生先を切り替える
+    pub fn switch_output(
+        &mut self,
+        audio_output: &mut AudioOutput,
+        mut new_output: AudioOutput,
+    ) -> anyhow::Result<()> {
+        self.set_output_config(new_output.config())?;
+
+        let (mut mixer, handle) = Mixer::new();
+        let old_handle = self.mixer.replace(handle);
+        if let Err(err) = self.rebuild_voices() {
+            self.mixer = old_handle;
+            return Err(err);
+        }
+        // 再生前にコマンドを反映しておく
+        mixer.process(&mut []);
+        if let Err(err) = new_output.play(move |data| mixer.process(data)) {
+            self.mixer = old_handle;
+            return Err(err);
+        }
+        *audio_output = new_output;
+        Ok(())
+    }
+
+    /// 出力を切り替えたあと Simulation に渡す
+    pub fn take_param_sink(&mut self) -> Option<rtrb::Producer<VoiceParams>> {
+        self.mixer.as_mut().and_then(|m| m.take_param_sink())
+    }
+
     pub fn patch_keep_output(&mut self, mut other: Self) {
         self.raw_data = std::mem::take(&mut other.raw_data);
-        let mut a = self
-            .audio_sources
-            .lock()
-            .expect("Failed to lock audio_sources in self");
-        let mut b = other
-            .audio_sources
-            .lock()
-            .expect("Failed to lock audio_sources in other");
-        *a = std::mem::take(&mut b);
+        self.decoded = std::mem::take(&mut other.decoded);
+        self.synths = std::mem::take(&mut other.synths);
+        self.one_shots = std::mem::take(&mut other.one_shots);
         self.next_id = other.next_id;
+        if let Err(err) = self.rebuild_voices() {
+            log::warn!("Failed to rebuild voices: {err:?}");
+        }
     }
 
     pub fn raw_data_by_id(&self, id: &AudioEntryId) -> Option<&Vec<u8>> {
         self.raw_data.get(id)
     }
 
+    pub fn audio_source_by_id(&self, id: &AudioEntryId) -> Option<&Arc<AudioSource>> {
+        self.decoded.get(id)
+    }
+
     pub fn add_buffered_file(
         &mut self,
         buf: Vec<u8>,
@@ -50,29 +122,31 @@ impl FileRegistory {
     ) -> anyhow::Result<AudioEntryId> {
         // バイト列からファイルを追加
         let id = self.generate_id();
-        let result = self.read_buffer(&buf)?;
-        if let Ok(mut sources) = self.audio_sources.lock() {
-            self.raw_data.insert(id, buf);
-            sources.insert(id, result);
-            state.add_audio_entry(id, name);
-            Ok(id)
-        } else {
-            bail!("Failed to get access to mutex audio_sources");
-        }
+        let source = self.read_buffer(&buf)?;
+        self.insert(id, buf, source)?;
+        state.add_audio_entry(id, name);
+        Ok(id)
     }
 
     pub fn add_file(&mut self, path: PathBuf, state: &mut State) -> anyhow::Result<AudioEntryId> {
         // registoryとstateの両方に同時に追加
-        let (raw, result) = self.read_file(&path)?;
+        let (raw, source) = self.read_file(&path)?;
         let id = self.generate_id();
-        if let Ok(mut sources) = self.audio_sources.lock() {
-            self.raw_data.insert(id, raw);
-            sources.insert(id, result);
-            state.add_audio_entry_with_path(id, path)?;
-            Ok(id)
-        } else {
-            bail!("Failed to get access to mutex audio_sources");
-        }
+        self.insert(id, raw, source)?;
+        state.add_audio_entry_with_path(id, path)?;
+        Ok(id)
+    }
+
+    pub fn add_synth(
+        &mut self,
+        name: &str,
+        synth: SynthSource,
+        state: &mut State,
+    ) -> anyhow::Result<AudioEntryId> {
+        let id = self.generate_id();
+        self.insert_synth(id, synth.kind())?;
+        state.add_synth_entry(id, name, synth);
+        Ok(id)
     }
 
     fn add_existing(
@@ -81,80 +155,83 @@ impl FileRegistory {
         entry: &mut AudioEntry,
     ) -> anyhow::Result<AudioEntryId> {
         // stateにすでにあるものをregistoryに追加
-        let (raw, result) = self.read_file(path)?;
+        let (raw, source) = self.read_file(path)?;
         let new_id = self.generate_id();
-        if let Ok(mut sources) = self.audio_sources.lock() {
-            self.raw_data.insert(new_id, raw);
-            sources.insert(new_id, result);
-            entry.id = new_id;
-            Ok(new_id)
-        } else {
-            bail!("Failed to get access to mutex audio_sources");
-        }
+        self.insert(new_id, raw, source)?;
+        entry.id = new_id;
+        Ok(new_id)
     }
 
-    pub fn remove_file(&mut self, id: &AudioEntryId, state: &mut State) -> anyhow::Result<()> {
-        if let Ok(mut sources) = self.audio_sources.lock() {
-            self.raw_data.remove(id);
-            sources.remove(id);
-            state.audio_entries.retain(|e| e.id() != id);
-            Ok(())
-        } else {
-            bail!("Failed to get access to mutex audio_sources");
-        }
+    fn add_existing_synth(
+        &mut self,
+        kind: SynthKind,
+        entry: &mut AudioEntry,
+    ) -> anyhow::Result<()> {
+        let new_id = self.generate_id();
+        self.insert_synth(new_id, kind)?;
+        entry.id = new_id;
+        Ok(())
     }
 
-    pub fn clear(&mut self, state: &mut State) {
-        if let Ok(mut sources) = self.audio_sources.lock() {
-            self.raw_data.clear();
-            sources.clear();
-            state.clear_audio_entries();
-        }
+    pub fn remove_file(&mut self, id: &AudioEntryId, state: &mut State) -> anyhow::Result<()> {
+        self.send(MixerCommand::RemoveVoice(*id))?;
+        self.raw_data.remove(id);
+        self.decoded.remove(id);
+        self.synths.remove(id);
+        self.one_shots.remove(id);
+        state.audio_entries.retain(|e| e.id() != id);
+        Ok(())
     }
 
-    pub fn play_audio(&self, audio_output: &mut AudioOutput) -> anyhow::Result<()> {
-        let sources = self.audio_sources.clone();
-
-        audio_output.play(move |data| {
-            if let Ok(mut sources) = sources.lock() {
-                for entry in sources.values_mut().flatten() {
-                    entry.write_data_additive(data);
-                }
-            }
-        })
+    pub fn clear(&mut self, state: &mut State) -> anyhow::Result<()> {
+        self.send(MixerCommand::Clear)?;
+        self.raw_data.clear();
+        self.decoded.clear();
+        self.synths.clear();
+        self.one_shots.clear();
+        state.clear_audio_entries();
+        Ok(())
     }
 
-    pub fn update(
-        &mut self,
-        state: &mut State,
-        player_state: &PlayerState,
-        preference: &Preference,
-        action: &mut crate::app_action::AppAction,
-    ) {
+    pub fn update(&mut self, state: &mut State, action: &mut crate::app_action::AppAction) {
         let mut to_load = vec![];
+        let mut synths_to_add = vec![];
         let mut indices_to_remove = vec![];
 
-        if let Ok(mut sources) = self.audio_sources.lock() {
-            for (index, entry) in state.audio_entries.iter().enumerate() {
-                let id = entry.id;
-                if let Some(Ok(source)) = sources.get_mut(&id) {
-                    let (volume, pitch) = player_state.get_volume_pitch(entry, preference);
-                    source.set_volume_pitch(volume, pitch);
-                } else if !sources.contains_key(&id) {
-                    // 未ロードの音声あり
-                    if let Some(path) = entry.path.as_ref() {
-                        to_load.push((index, path.clone()));
-                    } else {
-                        action.add_error_modal(anyhow!(
-                            "Unable to get audio data of {}",
-                            entry.name()
-                        ));
-                        indices_to_remove.push(index);
-                    }
+        if let Some(mixer) = self.mixer.as_mut() {
+            mixer.collect_garbage();
+        }
+
+        for (index, entry) in state.audio_entries.iter().enumerate() {
+            if !self.decoded.contains_key(&entry.id) && !self.synths.contains_key(&entry.id) {
+                // 未ロードの音声あり
+                if let Some(synth) = entry.synth() {
+                    synths_to_add.push((index, synth.kind()));
+                } else if let Some(path) = entry.path.as_ref() {
+                    to_load.push((index, path.clone()));
+                } else {
+                    action.add_error_modal(anyhow!("Unable to get audio data of {}", entry.name()));
+                    indices_to_remove.push(index);
                 }
             }
         }
 
+        // 再生の仕方が変わった音声は作り直す
+        let playback_changed: Vec<_> = state
+            .audio_entries
+            .iter()
+            .filter(|e| {
+                self.decoded.contains_key(&e.id)
+                    && e.one_shot().is_some() != self.one_shots.contains(&e.id)
+            })
+            .map(|e| (e.id, e.one_shot().is_some()))
+            .collect();
+        for (id, one_shot) in playback_changed {
+            if let Err(err) = self.set_one_shot(id, one_shot) {
+                action.add_error_modal(err);
+            }
+        }
+
         indices_to_remove.sort();
         indices_to_remove.reverse();
         for index in indices_to_remove {
@@ -168,6 +245,14 @@ impl FileRegistory {
                 }
             }
         }
+
+        for (index, kind) in synths_to_add {
+            if let Some(entry) = state.audio_entries.get_mut(index) {
+                if let Err(err) = self.add_existing_synth(kind, entry) {
+                    action.add_error_modal(err);
+                }
+            }
+        }
     }
 
     fn generate_id(&mut self) -> AudioEntryId {
@@ -176,20 +261,97 @@ impl FileRegistory {
         id
     }
 
-    fn read_file(
-        &self,
-        path: &PathBuf,
-    ) -> anyhow::Result<(Vec<u8>, anyhow::Result<ResampledLoopAudio>)> {
+    fn insert(
+        &mut self,
+        id: AudioEntryId,
+        raw: Vec<u8>,
+        source: Arc<AudioSource>,
+    ) -> anyhow::Result<()> {
+        self.add_voice(id, &source)?;
+        self.raw_data.insert(id, raw);
+        self.decoded.insert(id, source);
+        Ok(())
+    }
+
+    fn insert_synth(&mut self, id: AudioEntryId, kind: SynthKind) -> anyhow::Result<()> {
+        self.add_synth_voice(id, kind)?;
+        self.synths.insert(id, kind);
+        Ok(())
+    }
+
+    /// 出力中なら音声を Mixer に追加する
+    fn add_voice(&mut self, id: AudioEntryId, source: &Arc<AudioSource>) -> anyhow::Result<()> {
+        if self.mixer.is_none() {
+            return Ok(());
+        }
+        let voice = if self.one_shots.contains(&id) {
+            Voice::one_shot(source.clone(), self.output_config.sample_rate)
+        } else {
+            Voice::sample(
+                source.clone(),
+                self.output_config.sample_rate,
+                256,
+                self.resampler_quality,
+            )?
+        };
+        self.send(MixerCommand::AddVoice(id, Box::new(voice)))
+    }
+
+    fn set_one_shot(&mut self, id: AudioEntryId, one_shot: bool) -> anyhow::Result<()> {
+        if one_shot {
+            self.one_shots.insert(id);
+        } else {
+            self.one_shots.remove(&id);
+        }
+        match self.decoded.get(&id).cloned() {
+            Some(source) => self.add_voice(id, &source),
+            None => Ok(()),
+        }
+    }
+
+    fn add_synth_voice(&mut self, id: AudioEntryId, kind: SynthKind) -> anyhow::Result<()> {
+        if self.mixer.is_none() {
+            return Ok(());
+        }
+        let voice = Voice::synth(kind, self.output_config);
+        self.send(MixerCommand::AddVoice(id, Box::new(voice)))
+    }
+
+    fn rebuild_voices(&mut self) -> anyhow::Result<()> {
+        self.send(MixerCommand::Clear)?;
+        let decoded: Vec<_> = self
+            .decoded
+            .iter()
+            .map(|(id, s)| (*id, s.clone()))
+            .collect();
+        for (id, source) in decoded {
+            self.add_voice(id, &source)?;
+        }
+        let synths: Vec<_> = self.synths.iter().map(|(id, k)| (*id, *k)).collect();
+        for (id, kind) in synths {
+            self.add_synth_voice(id, kind)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, command: MixerCommand) -> anyhow::Result<()> {
+        match self.mixer.as_mut() {
+            Some(mixer) => mixer.send(command),
+            None => Ok(()),
+        }
+    }
+
+    fn read_file(&self, path: &PathBuf) -> anyhow::Result<(Vec<u8>, Arc<AudioSource>)> {
         let raw = std::fs::read(path)?;
-        let result = self.read_buffer(&raw)?;
-        Ok((raw, result))
+        let source = self.read_buffer(&raw)?;
+        Ok((raw, source))
     }
 
-    fn read_buffer(&self, buf: &[u8]) -> anyhow::Result<anyhow::Result<ResampledLoopAudio>> {
-        let config = self.audio_output.config()?;
-        let mut ogg = OggStreamReader::new(std::io::Cursor::new(buf))?;
-        let source = AudioSource::from_ogg(&mut ogg, config.channels() as usize)?;
-        let result = ResampledLoopAudio::new(source, config.sample_rate().0, 256);
-        Ok(result)
+    fn read_buffer(&self, buf: &[u8]) -> anyhow::Result<Arc<AudioSource>> {
+        let config = self.output_config;
+        Ok(Arc::new(AudioSource::decode(
+            buf,
+            config.channels as usize,
+        )?))
     }
 }
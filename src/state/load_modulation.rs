@@ -0,0 +1,56 @@
+use crate::func_edit::{EditableFunc, EditableZeroOneFunc, ExprContext, FuncEdit as _};
+
+/// 負荷の大きさとして使う値
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum LoadInput {
+    /// 引張力・ブレーキ力の最大値に対する割合 (0 から 1)
+    #[default]
+    Effort,
+    /// 加速度の大きさ (km/h/s、減速も正)
+    Acceleration,
+}
+
+impl LoadInput {
+    pub fn label_text(self) -> &'static str {
+        match self {
+            Self::Effort => "Effort",
+            Self::Acceleration => "Acceleration",
+        }
+    }
+
+    pub fn axis_label(self) -> &'static str {
+        match self {
+            Self::Effort => "Effort",
+            Self::Acceleration => "|km/h/s|",
+        }
+    }
+}
+
+/// 負荷に応じて音量にかける倍率
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct LoadModulation {
+    pub input: LoadInput,
+    pub gain: EditableZeroOneFunc,
+}
+
+impl Default for LoadModulation {
+    fn default() -> Self {
+        Self {
+            input: LoadInput::Effort,
+            gain: EditableFunc::with_points(vec![(0.0, 0.3), (1.0, 1.0)]).into(),
+        }
+    }
+}
+
+impl LoadModulation {
+    /// `context` はこの音声の式の文脈 (定数・曲線を使える)
+    pub fn gain_at(&self, effort: f64, acceleration: f64, context: &ExprContext) -> f64 {
+        let x = match self.input {
+            LoadInput::Effort => effort,
+            // 倍率の曲線は x < 0 を持たないので、減速も大きさで扱う
+            LoadInput::Acceleration => acceleration.abs(),
+        };
+        // x は速度ではないので速度に比例する変数は使えない
+        self.gain.value_at(x, &context.without_proportional())
+    }
+}
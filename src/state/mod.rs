@@ -1,10 +1,24 @@
 mod audio_entry;
+mod carrier_schedule;
+mod drive_train;
+mod event_trigger;
+mod expr_constant;
 mod file_registory;
+mod load_modulation;
 mod project_state;
 mod selection_cursor;
+mod synth_source;
+mod track_geometry;
 mod train_performance;
-pub use audio_entry::{AudioEntry, AudioFunctionMode, AudioFunctions, SoundType};
+pub use audio_entry::{AudioEntry, AudioFunctionMode, AudioFunctions, SoundType, StateFunctions};
+pub use carrier_schedule::CarrierSchedule;
+pub use drive_train::DriveTrain;
+pub use event_trigger::{EventTrigger, OneShotSettings, TriggerEvent};
+pub use expr_constant::ExprConstant;
 pub use file_registory::{AudioEntryId, FileRegistory};
+pub use load_modulation::{LoadInput, LoadModulation};
 pub use project_state::State;
 pub use selection_cursor::SelectionCursor;
-pub use train_performance::TrainPerformance;
+pub use synth_source::{Harmonic, HarmonicSettings, NoiseSettings, PwmSettings, SynthSource};
+pub use track_geometry::TrackGeometry;
+pub use train_performance::{StateTransition, TrainPerformance};
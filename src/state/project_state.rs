@@ -1,4 +1,8 @@
-use super::{AudioEntry, AudioEntryId, TrainPerformance};
+use super::{
+    AudioEntry, AudioEntryId, DriveTrain, ExprConstant, SoundType, SynthSource, TrackGeometry,
+    TrainPerformance,
+};
+use crate::func_edit::{CurveTable, ExprContext, FuncEdit};
 use std::path::PathBuf;
 
 #[derive(Debug, Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
@@ -6,13 +10,67 @@ pub struct State {
     pub audio_entries: Vec<AudioEntry>,
     pub selection: Option<AudioEntryId>,
     pub train_performance: TrainPerformance,
+    #[serde(default)]
+    pub drive_train: DriveTrain,
+    #[serde(default)]
+    pub track_geometry: TrackGeometry,
+    #[serde(default)]
+    pub constants: Vec<ExprConstant>,
     //pub speed_cursor: Cursor, // 今のところ不使用
 }
 
 impl State {
+    /// 式で使える変数・定数・曲線
+    ///
+    /// `pitch_0`, `volume_0` のように番号で音声の曲線を、`pitch`, `volume` で自分の曲線を参照できる。
+    pub fn expr_context(&self, sound_type: SoundType) -> ExprContext {
+        let constants = self
+            .constants
+            .iter()
+            .filter(|c| c.is_valid_name())
+            .map(|c| (c.name.clone(), c.value))
+            .collect();
+        let mut curves = CurveTable::default();
+        for (i, entry) in self.audio_entries.iter().enumerate() {
+            if let Some(funcs) = entry.funcs_by_type(sound_type) {
+                curves.push(format!("pitch_{i}"), Some(i), funcs.pitch.clone());
+                curves.push(format!("volume_{i}"), Some(i), funcs.volume.clone());
+            }
+        }
+        let performance = &self.train_performance;
+        curves.push(
+            "acceleration".to_owned(),
+            None,
+            performance.acceleration.clone(),
+        );
+        curves.push("drag".to_owned(), None, performance.drag.clone());
+        self.drive_train
+            .expr_context(sound_type)
+            .with_constants(constants)
+            .with_curves(curves)
+    }
+
+    fn for_each_func_mut(&mut self, f: &mut dyn FnMut(&mut dyn FuncEdit)) {
+        self.train_performance.for_each_func_mut(f);
+        for entry in &mut self.audio_entries {
+            entry.for_each_func_mut(f);
+        }
+    }
+
+    /// 読み込んだ式を計算できるようにする
+    ///
+    /// 他の曲線から参照されることがあるので、表示していないものも読んでおく。
+    pub fn update_expressions(&mut self) {
+        self.for_each_func_mut(&mut |func| func.update_expression());
+    }
+
     pub(super) fn add_audio_entry(&mut self, id: AudioEntryId, name: &str) {
         self.audio_entries.push(AudioEntry::new(id, name));
     }
+    pub(super) fn add_synth_entry(&mut self, id: AudioEntryId, name: &str, synth: SynthSource) {
+        self.audio_entries
+            .push(AudioEntry::with_synth(id, name, synth));
+    }
     pub(super) fn add_audio_entry_with_path(
         &mut self,
         id: AudioEntryId,
@@ -27,13 +85,55 @@ impl State {
     }
 
     pub fn move_audio_entry(&mut self, from_idx: usize, to_idx: usize) {
-        let item = self.remove_audio_entry(from_idx);
-        self.audio_entries
-            .insert(to_idx.min(self.audio_entries.len()), item);
+        let item = self.audio_entries.remove(from_idx);
+        let to_idx = to_idx.min(self.audio_entries.len());
+        self.audio_entries.insert(to_idx, item);
+        self.renumber_entries(|i| {
+            if i == from_idx {
+                return Some(to_idx);
+            }
+            let i = if i > from_idx { i - 1 } else { i };
+            Some(if i >= to_idx { i + 1 } else { i })
+        });
     }
 
     pub(super) fn remove_audio_entry(&mut self, index: usize) -> AudioEntry {
-        self.audio_entries.remove(index)
+        let entry = self.audio_entries.remove(index);
+        self.renumber_entries(|i| match i.cmp(&index) {
+            std::cmp::Ordering::Less => Some(i),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(i - 1),
+        });
+        entry
+    }
+
+    /// 音声の並びが変わったとき、番号で音声を指しているものを付け直す (`None` は消した音声)
+    ///
+    /// 消した音声の曲線を参照している式は `pitch_0_removed` のように書き換えて誤りとして見せる。
+    fn renumber_entries(&mut self, new_index: impl Fn(usize) -> Option<usize>) {
+        let rename = |name: &str| {
+            let (kind, number) = name.rsplit_once('_')?;
+            if !matches!(kind, "pitch" | "volume") {
+                return None;
+            }
+            let index = number.parse().ok()?;
+            Some(match new_index(index) {
+                Some(new) => format!("{kind}_{new}"),
+                None => format!("{kind}_{index}_removed"),
+            })
+        };
+        self.for_each_func_mut(&mut |func| func.rename_references(&rename));
+
+        for entry in &mut self.audio_entries {
+            let triggers = entry
+                .one_shot_mut()
+                .iter_mut()
+                .flat_map(|settings| &mut settings.triggers);
+            for trigger in triggers {
+                // 消した音声を見ていたきっかけは、この音声の区間を見るように戻す
+                trigger.schedule_entry = trigger.schedule_entry.and_then(&new_index);
+            }
+        }
     }
 
     pub(super) fn clear_audio_entries(&mut self) {
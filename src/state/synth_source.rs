@@ -0,0 +1,203 @@
+use super::CarrierSchedule;
+use crate::{
+    audio_player::{HarmonicParams, MAX_HARMONICS, NoiseParams, PwmParams, SynthKind, SynthParams},
+    func_edit::{EditableFunc, EditablePositiveFunc, EditableZeroOneFunc, ExprContext, FuncEdit},
+};
+
+/// 音声ファイルの代わりに合成する音
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum SynthSource {
+    Pwm(Box<PwmSettings>),
+    Harmonic(HarmonicSettings),
+    Noise(Box<NoiseSettings>),
+}
+
+impl SynthSource {
+    pub const TEXT_PWM: &str = "PWM Inverter";
+    pub const TEXT_HARMONIC: &str = "Motor Harmonics";
+    pub const TEXT_NOISE: &str = "Rolling Noise";
+
+    pub fn kind(&self) -> SynthKind {
+        match self {
+            Self::Pwm(_) => SynthKind::Pwm,
+            Self::Harmonic(_) => SynthKind::Harmonic,
+            Self::Noise(_) => SynthKind::Noise,
+        }
+    }
+
+    pub fn label_text(&self) -> &str {
+        match self {
+            Self::Pwm(_) => Self::TEXT_PWM,
+            Self::Harmonic(_) => Self::TEXT_HARMONIC,
+            Self::Noise(_) => Self::TEXT_NOISE,
+        }
+    }
+
+    pub fn for_each_func_mut(&mut self, f: &mut dyn FnMut(&mut dyn FuncEdit)) {
+        match self {
+            Self::Pwm(settings) => {
+                f(&mut settings.carrier_frequency);
+                f(&mut settings.signal_frequency);
+                f(&mut settings.modulation_index);
+            }
+            Self::Harmonic(settings) => {
+                for harmonic in &mut settings.harmonics {
+                    f(&mut harmonic.amplitude);
+                }
+            }
+            Self::Noise(settings) => {
+                f(&mut settings.rolling_cutoff);
+                f(&mut settings.rolling_level);
+                f(&mut settings.joint_cutoff);
+                f(&mut settings.joint_level);
+            }
+        }
+    }
+
+    pub fn params_at(
+        &self,
+        speed: f64,
+        schedule: &CarrierSchedule,
+        context: &ExprContext,
+    ) -> SynthParams {
+        match self {
+            Self::Pwm(settings) => SynthParams::Pwm(settings.params_at(speed, schedule, context)),
+            Self::Harmonic(settings) => SynthParams::Harmonic(settings.params_at(speed, context)),
+            Self::Noise(settings) => SynthParams::Noise(settings.params_at(speed, context)),
+        }
+    }
+}
+
+/// 速度に対する搬送波・信号波の周波数
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct PwmSettings {
+    pub carrier_frequency: EditablePositiveFunc,
+    pub signal_frequency: EditablePositiveFunc,
+    pub modulation_index: EditablePositiveFunc,
+}
+
+impl Default for PwmSettings {
+    fn default() -> Self {
+        Self {
+            carrier_frequency: EditableFunc::with_points(vec![(0.0, 1000.0)]).into(),
+            signal_frequency: EditableFunc::with_points(vec![(0.0, 0.0), (100.0, 150.0)]).into(),
+            modulation_index: EditableFunc::with_points(vec![
+                (0.0, 0.0),
+                (40.0, 1.0),
+                (100.0, 1.0),
+            ])
+            .into(),
+        }
+    }
+}
+
+impl PwmSettings {
+    /// 搬送波モードの区間が無ければ非同期として扱う
+    pub fn params_at(
+        &self,
+        speed: f64,
+        schedule: &CarrierSchedule,
+        context: &ExprContext,
+    ) -> PwmParams {
+        PwmParams {
+            carrier_frequency: self.carrier_frequency.value_at(speed, context) as f32,
+            signal_frequency: self.signal_frequency.value_at(speed, context) as f32,
+            modulation_index: self.modulation_index.value_at(speed, context) as f32,
+            mode: schedule.mode_at(speed).unwrap_or_default(),
+        }
+    }
+}
+
+/// 倍音 1 つ分
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Harmonic {
+    /// 基本周波数に対する倍率
+    pub order: f64,
+    pub amplitude: EditableZeroOneFunc,
+}
+
+/// 回転子の回転に合わせて鳴る倍音
+///
+/// 基本周波数は駆動系 ([`super::DriveTrain`]) から求めたモーターの回転数。
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct HarmonicSettings {
+    pub harmonics: Vec<Harmonic>,
+}
+
+impl Default for HarmonicSettings {
+    fn default() -> Self {
+        Self {
+            harmonics: vec![
+                Harmonic {
+                    order: 1.0,
+                    amplitude: EditableFunc::with_points(vec![(0.0, 0.5)]).into(),
+                },
+                Harmonic {
+                    order: 2.0,
+                    amplitude: EditableFunc::with_points(vec![(0.0, 0.2)]).into(),
+                },
+            ],
+        }
+    }
+}
+
+impl HarmonicSettings {
+    /// 速度 (km/h) から基本周波数 (Hz) を求める
+    pub fn fundamental_at(speed: f64, context: &ExprContext) -> f64 {
+        context.variable("motor_hz", speed).unwrap_or(0.0)
+    }
+
+    pub fn can_add_harmonic(&self) -> bool {
+        self.harmonics.len() < MAX_HARMONICS
+    }
+
+    pub fn params_at(&self, speed: f64, context: &ExprContext) -> HarmonicParams {
+        let mut params = HarmonicParams {
+            fundamental: Self::fundamental_at(speed, context) as f32,
+            ..Default::default()
+        };
+        for (i, harmonic) in self.harmonics.iter().take(MAX_HARMONICS).enumerate() {
+            params.orders[i] = harmonic.order as f32;
+            params.amplitudes[i] = harmonic.amplitude.value_at(speed, context) as f32;
+        }
+        params
+    }
+}
+
+/// 速度に対する転動音と、継ぎ目を踏んだときの音
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct NoiseSettings {
+    /// 転動音の遮断周波数 (Hz)
+    pub rolling_cutoff: EditablePositiveFunc,
+    pub rolling_level: EditableZeroOneFunc,
+    /// 継ぎ目の音の中心周波数 (Hz)
+    pub joint_cutoff: EditablePositiveFunc,
+    pub joint_level: EditableZeroOneFunc,
+    /// 継ぎ目の音の減衰時間 (s)
+    pub joint_decay: f64,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            rolling_cutoff: EditableFunc::with_points(vec![(0.0, 200.0), (120.0, 1200.0)]).into(),
+            rolling_level: EditableFunc::with_points(vec![(0.0, 0.0), (20.0, 0.2), (120.0, 0.5)])
+                .into(),
+            joint_cutoff: EditableFunc::with_points(vec![(0.0, 600.0), (120.0, 1500.0)]).into(),
+            joint_level: EditableFunc::with_points(vec![(0.0, 0.15), (120.0, 0.4)]).into(),
+            joint_decay: 0.04,
+        }
+    }
+}
+
+impl NoiseSettings {
+    pub fn params_at(&self, speed: f64, context: &ExprContext) -> NoiseParams {
+        NoiseParams {
+            rolling_cutoff: self.rolling_cutoff.value_at(speed, context) as f32,
+            rolling_level: self.rolling_level.value_at(speed, context) as f32,
+            joint_cutoff: self.joint_cutoff.value_at(speed, context) as f32,
+            joint_level: self.joint_level.value_at(speed, context) as f32,
+            joint_decay: self.joint_decay as f32,
+        }
+    }
+}
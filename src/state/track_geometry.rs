@@ -0,0 +1,52 @@
+/// レールと台車の寸法 (車軸が継ぎ目を踏む間隔を決める)
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TrackGeometry {
+    /// レール 1 本の長さ (m)
+    pub rail_length: f64,
+    /// 台車中心間距離 (m)
+    pub bogie_spacing: f64,
+    /// 台車の軸距 (m)
+    pub axle_spacing: f64,
+}
+
+impl Default for TrackGeometry {
+    fn default() -> Self {
+        Self {
+            rail_length: 25.0,
+            bogie_spacing: 13.8,
+            axle_spacing: 2.1,
+        }
+    }
+}
+
+impl TrackGeometry {
+    /// 1 両分の車軸の位置 (先頭の車軸からの距離、m)
+    fn axle_offsets(&self) -> [f64; 4] {
+        [
+            0.0,
+            self.axle_spacing,
+            self.bogie_spacing,
+            self.bogie_spacing + self.axle_spacing,
+        ]
+    }
+
+    /// 走行距離が `from` から `to` (m) まで進む間に車軸が継ぎ目を踏んだ回数
+    pub fn joints_between(&self, from: f64, to: f64) -> usize {
+        if self.rail_length <= 0.0 || to <= from {
+            return 0;
+        }
+        self.axle_offsets()
+            .into_iter()
+            .map(|offset| {
+                let passed = ((to - offset) / self.rail_length).floor()
+                    - ((from - offset) / self.rail_length).floor();
+                passed.max(0.0) as usize
+            })
+            .sum()
+    }
+
+    /// 速度 (km/h) でレール 1 本分を走る時間 (s)
+    pub fn rail_period(&self, speed: f64) -> Option<f64> {
+        (speed > 0.0).then(|| self.rail_length / (speed / 3.6))
+    }
+}
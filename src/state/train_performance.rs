@@ -1,4 +1,4 @@
-use crate::func_edit::{EditableFunc, EditablePositiveFunc, FuncEdit as _};
+use crate::func_edit::{EditableFunc, EditablePositiveFunc, ExprContext, FuncEdit};
 
 #[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct TrainPerformance {
@@ -7,6 +7,10 @@ pub struct TrainPerformance {
     pub brake_acceleration: f64,
     pub brake_steps: u8,
     pub drag: EditablePositiveFunc,
+    #[serde(default)]
+    pub regen_fade: RegenFade,
+    #[serde(default)]
+    pub transition: StateTransition,
 }
 
 impl Default for TrainPerformance {
@@ -17,13 +21,70 @@ impl Default for TrainPerformance {
             brake_acceleration: 4.2,
             brake_steps: 8,
             drag: EditableFunc::with_expression("x/500").into(),
+            regen_fade: RegenFade {
+                start_speed: 8.0,
+                end_speed: 3.0,
+            },
+            transition: StateTransition {
+                fade_out: EditableFunc::with_points(vec![(0.0, 0.1)]).into(),
+                gap: EditableFunc::with_points(vec![(0.0, 0.3)]).into(),
+                fade_in: EditableFunc::with_points(vec![(0.0, 0.2)]).into(),
+            },
         }
     }
 }
 
 impl TrainPerformance {
-    pub fn update(&mut self) {
-        self.acceleration.update_expression();
-        self.drag.update_expression();
+    pub fn for_each_func_mut(&mut self, f: &mut dyn FnMut(&mut dyn FuncEdit)) {
+        f(&mut self.acceleration);
+        f(&mut self.drag);
+        f(&mut self.transition.fade_out);
+        f(&mut self.transition.gap);
+        f(&mut self.transition.fade_in);
+    }
+}
+
+/// 低速で回生ブレーキから空気ブレーキへ切り替わる速度の範囲
+///
+/// `start_speed` から `end_speed` にかけてブレーキの音量を 0 まで下げる。
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RegenFade {
+    pub start_speed: f64,
+    pub end_speed: f64,
+}
+
+impl RegenFade {
+    /// ブレーキの音量に掛ける係数
+    pub fn factor(&self, speed: f64) -> f64 {
+        if speed >= self.start_speed {
+            1.0
+        } else if speed <= self.end_speed {
+            0.0
+        } else {
+            (speed - self.end_speed) / (self.start_speed - self.end_speed)
+        }
+    }
+}
+
+/// 走行状態が変わるときに前の音を消してから次の音を鳴らすまでの時間 (秒、速度の関数)
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct StateTransition {
+    pub fade_out: EditablePositiveFunc,
+    pub gap: EditablePositiveFunc,
+    pub fade_in: EditablePositiveFunc,
+}
+
+impl StateTransition {
+    /// `speed` での (フェードアウト, 無音, フェードイン) の秒数
+    pub fn timings_at(&self, speed: f64, context: &ExprContext) -> (f64, f64, f64) {
+        let seconds = |f: &EditablePositiveFunc| {
+            f.checked_value_at(speed, context)
+                .map_or(0.0, |v| v.max(0.0))
+        };
+        (
+            seconds(&self.fade_out),
+            seconds(&self.gap),
+            seconds(&self.fade_in),
+        )
     }
 }
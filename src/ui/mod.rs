@@ -16,6 +16,8 @@ mod ui_player;
 pub use ui_player::UiPlayer;
 mod ui_performance_window;
 pub use ui_performance_window::UiPerformanceWindow;
+mod ui_carrier_schedule_window;
+pub use ui_carrier_schedule_window::{UiCarrierScheduleWindow, plot_carrier_bands};
 mod ui_setting_window;
 pub use ui_setting_window::UiSettingWindow;
 mod ui_about;
@@ -1,8 +1,8 @@
 use crate::{
     app_action::AppAction,
-    state::{AudioEntry, FileRegistory, State},
+    state::{AudioEntry, FileRegistory, HarmonicSettings, State, SynthSource},
 };
-use egui::{Button, Id, Label, ScrollArea};
+use egui::{Button, Id, Label, Popup, ScrollArea};
 use egui_extras::{Size, StripBuilder};
 
 #[derive(Debug, Default)]
@@ -29,20 +29,37 @@ impl UiAudioFiles {
             .size(Size::remainder())
             .size(Size::exact(20.0))
             .vertical(|mut strip| {
-                strip.cell(|ui| {
-                    if ui
-                        .add_sized(ui.available_size(), Button::new("Add"))
-                        .clicked()
-                    {
-                        #[cfg(not(target_arch = "wasm32"))]
-                        if let Some(paths) = crate::file_dialog::add_ogg_dialog(frame) {
-                            for path in paths {
-                                if let Err(err) = registory.add_file(path, state) {
-                                    action.add_error_modal(err);
+                strip.strip(|builder| {
+                    builder
+                        .size(Size::remainder())
+                        .size(Size::relative(0.3))
+                        .horizontal(|mut strip| {
+                            strip.cell(|ui| {
+                                if ui
+                                    .add_sized(ui.available_size(), Button::new("Add"))
+                                    .clicked()
+                                {
+                                    #[cfg(not(target_arch = "wasm32"))]
+                                    if let Some(paths) = crate::file_dialog::add_audio_dialog(frame)
+                                    {
+                                        for path in paths {
+                                            if let Err(err) = registory.add_file(path, state) {
+                                                action.add_error_modal(err);
+                                            }
+                                        }
+                                    }
                                 }
-                            }
-                        }
-                    }
+                            });
+
+                            strip.cell(|ui| {
+                                // 音声ファイルを使わない合成音
+                                let response =
+                                    ui.add_sized(ui.available_size(), Button::new("Synth"));
+                                Popup::menu(&response).show(|ui| {
+                                    ui_add_synth(ui, action, registory, state);
+                                });
+                            });
+                        });
                 });
 
                 strip.cell(|ui| {
@@ -77,7 +94,9 @@ impl UiAudioFiles {
                                     .add_sized(ui.available_size(), Button::new("All"))
                                     .clicked()
                                 {
-                                    registory.clear(state);
+                                    if let Err(err) = registory.clear(state) {
+                                        action.add_error_modal(err);
+                                    }
                                 }
                             });
                         });
@@ -105,6 +124,34 @@ impl UiAudioFiles {
     }
 }
 
+fn ui_add_synth(
+    ui: &mut egui::Ui,
+    action: &mut AppAction,
+    registory: &mut FileRegistory,
+    state: &mut State,
+) {
+    let mut synth = None;
+    if ui.button(SynthSource::TEXT_PWM).clicked() {
+        synth = Some(SynthSource::Pwm(Box::default()));
+    }
+    if ui.button(SynthSource::TEXT_HARMONIC).clicked() {
+        synth = Some(SynthSource::Harmonic(HarmonicSettings::default()));
+    }
+    if ui
+        .button(SynthSource::TEXT_NOISE)
+        .on_hover_text("Filtered noise with rail joint clatter")
+        .clicked()
+    {
+        synth = Some(SynthSource::Noise(Box::default()));
+    }
+    if let Some(synth) = synth {
+        let name = synth.label_text().to_owned();
+        if let Err(err) = registory.add_synth(&name, synth, state) {
+            action.add_error_modal(err);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum DndLocation {
     Active(usize),
@@ -0,0 +1,311 @@
+use crate::{
+    app_action::AppAction,
+    audio_player::CarrierMode,
+    state::{CarrierSchedule, State},
+};
+use egui::{Color32, ComboBox, DragValue, Grid, Response, Stroke, Window};
+use egui_plot::{AxisHints, Plot, PlotPoint, Polygon, Text, VLine};
+
+/// 境界線をつかめる距離 (px)
+const GRAB_RADIUS: f32 = 6.0;
+/// 選択肢に出す同期モードのパルス数
+const SYNCHRONOUS_PRESETS: [u32; 6] = [27, 15, 9, 7, 5, 3];
+
+#[derive(Debug, Default)]
+pub struct UiCarrierScheduleWindow {
+    dragging_boundary: Option<usize>,
+    last_pointer_button_down: bool,
+    selected_region: Option<usize>,
+}
+
+impl UiCarrierScheduleWindow {
+    pub const TITLE: &str = "Carrier Schedule";
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        open: &mut bool,
+        action: &mut AppAction,
+        state: &mut State,
+    ) {
+        Window::new(Self::TITLE)
+            .open(open)
+            .default_size([360.0, 320.0])
+            .min_size([300.0, 200.0])
+            .show(ctx, |ui| {
+                self.ui(ui, action, state);
+            });
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, action: &mut AppAction, state: &mut State) {
+        let Some(entry) = state
+            .selection
+            .and_then(|id| state.audio_entries.iter_mut().find(|e| e.id() == &id))
+        else {
+            self.dragging_boundary = None;
+            ui.weak("Select an audio entry to edit its carrier modes.");
+            return;
+        };
+
+        ui.strong(entry.name());
+        if entry.synth().is_none() {
+            ui.weak("Annotation only: shown as bands on the plots.");
+        }
+
+        let schedule = entry.carrier_schedule_mut();
+        if schedule.is_empty() {
+            self.dragging_boundary = None;
+            if ui.button("Add schedule").clicked() {
+                schedule.init(CarrierMode::Asynchronous);
+                action.add_undo();
+            }
+            return;
+        }
+
+        self.ui_plot(ui, action, schedule);
+        ui.weak(
+            "Double-click to split, drag a boundary to move it, right-click a boundary to merge.",
+        );
+        ui.separator();
+        self.ui_regions(ui, action, schedule);
+
+        ui.separator();
+        if ui.button("Remove schedule").clicked() {
+            schedule.clear();
+            self.selected_region = None;
+            action.add_undo();
+        }
+    }
+
+    fn ui_plot(
+        &mut self,
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        schedule: &mut CarrierSchedule,
+    ) {
+        let response = Plot::new("plot_carrier_schedule")
+            .show_axes([true, false])
+            .show_grid([true, false])
+            .allow_drag(false)
+            .allow_zoom([true, false])
+            .allow_scroll([true, false])
+            .allow_double_click_reset(false)
+            .default_x_bounds(0.0, 120.0)
+            .default_y_bounds(0.0, 1.0)
+            .custom_x_axes(vec![AxisHints::new_x().label("Speed (km/h)")])
+            .height(120.0)
+            .show(ui, |plot_ui| {
+                let mouse_down = self.check_mouse_down(plot_ui.response());
+                plot_carrier_bands(plot_ui, schedule);
+                for (i, (start, end, mode)) in schedule.ranges().enumerate() {
+                    // 区間の名前
+                    let bounds = plot_ui.plot_bounds();
+                    let center = (start.max(bounds.min()[0]) + end.min(bounds.max()[0])) / 2.0;
+                    let mut text = egui::RichText::new(mode.label_text());
+                    if self.selected_region == Some(i) {
+                        text = text.strong();
+                    }
+                    plot_ui
+                        .text(Text::new("", PlotPoint::new(center, 0.5), text).allow_hover(false));
+                }
+
+                // 境界線
+                let pointer = plot_ui.response().hover_pos();
+                let mut hovered = None;
+                for (i, region) in schedule.regions().iter().enumerate().skip(1) {
+                    let x = plot_ui
+                        .screen_from_plot(PlotPoint::new(region.start, 0.0))
+                        .x;
+                    let near = pointer.is_some_and(|p| (p.x - x).abs() < GRAB_RADIUS);
+                    if near {
+                        hovered = Some(i);
+                    }
+                    let width = if near || self.dragging_boundary == Some(i) {
+                        3.0
+                    } else {
+                        1.5
+                    };
+                    plot_ui.vline(
+                        VLine::new("", region.start)
+                            .width(width)
+                            .color(plot_ui.ctx().style().visuals.text_color())
+                            .allow_hover(false),
+                    );
+                }
+                if mouse_down {
+                    self.dragging_boundary = hovered;
+                }
+
+                (plot_ui.pointer_coordinate(), hovered)
+            });
+        let (pointer, hovered) = response.inner;
+        let response = response.response;
+
+        // 境界のドラッグ
+        if let Some(index) = self.dragging_boundary {
+            if response.is_pointer_button_down_on() {
+                if let Some(pointer) = pointer {
+                    schedule.move_start(index, pointer.x.max(0.0));
+                }
+            } else {
+                self.dragging_boundary = None;
+                action.add_undo();
+            }
+        }
+
+        if response.clicked_by(egui::PointerButton::Secondary) {
+            if let Some(index) = hovered {
+                schedule.remove(index);
+                self.selected_region = None;
+                action.add_undo();
+            }
+        } else if response.double_clicked() {
+            if let Some(index) = pointer.and_then(|p| schedule.split_at(p.x)) {
+                self.selected_region = Some(index);
+                action.add_undo();
+            }
+        } else if response.clicked() && hovered.is_none() {
+            self.selected_region = pointer.and_then(|p| schedule.region_index_at(p.x));
+        }
+    }
+
+    fn ui_regions(
+        &mut self,
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        schedule: &mut CarrierSchedule,
+    ) {
+        let mut update = None;
+        let mut remove = None;
+        Grid::new(ui.id().with("carrier_schedule_regions"))
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("From (km/h)");
+                ui.label("Mode");
+                ui.end_row();
+
+                let removable = schedule.regions().len() > 1;
+                for (i, region) in schedule.regions().iter().enumerate() {
+                    let mut start = region.start;
+                    let mut mode = region.mode;
+
+                    let selected = self.selected_region == Some(i);
+                    if ui.selectable_label(selected, format!("{i}")).clicked() {
+                        self.selected_region = (!selected).then_some(i);
+                    }
+                    let drag = DragValue::new(&mut start).speed(0.1).max_decimals(1);
+                    if ui.add_enabled(i > 0, drag).changed() {
+                        update = Some((i, start, mode));
+                    }
+                    if ui_carrier_mode(ui, ("carrier_schedule_mode", i), &mut mode).changed() {
+                        update = Some((i, start, mode));
+                    }
+                    if ui
+                        .add_enabled(removable, egui::Button::new("- Remove"))
+                        .clicked()
+                    {
+                        remove = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some((i, start, mode)) = update {
+            schedule.move_start(i, start.max(0.0));
+            schedule.set_mode(i, mode);
+            self.selected_region = Some(i);
+        }
+        if let Some(i) = remove {
+            schedule.remove(i);
+            self.selected_region = None;
+            action.add_undo();
+        }
+    }
+
+    fn check_mouse_down(&mut self, response: &Response) -> bool {
+        let down = response.is_pointer_button_down_on();
+        let mouse_down_now = down && !self.last_pointer_button_down;
+        self.last_pointer_button_down = down;
+        mouse_down_now
+    }
+}
+
+/// 搬送波モードの区間を色の帯で描く
+pub fn plot_carrier_bands(plot_ui: &mut egui_plot::PlotUi<'_>, schedule: &CarrierSchedule) {
+    let bounds = plot_ui.plot_bounds();
+    let (left, right) = (bounds.min()[0], bounds.max()[0]);
+    let (bottom, top) = (bounds.min()[1], bounds.max()[1]);
+    let (bottom, top) = (bottom - (top - bottom), top + (top - bottom));
+    for (start, end, mode) in schedule.ranges() {
+        if end < left || start > right {
+            continue;
+        }
+        let end = end.min(right + (right - left));
+        plot_ui.polygon(
+            Polygon::new(
+                "",
+                vec![[start, bottom], [start, top], [end, top], [end, bottom]],
+            )
+            .fill_color(carrier_mode_color(mode).linear_multiply(0.15))
+            .stroke(Stroke::NONE)
+            .allow_hover(false),
+        );
+    }
+}
+
+fn carrier_mode_color(mode: CarrierMode) -> Color32 {
+    let hsva = match mode {
+        CarrierMode::Asynchronous => egui::epaint::Hsva::new(0.6, 0.2, 0.6, 1.0),
+        // パルス数が少ないほど赤に近づける
+        CarrierMode::Synchronous(pulses) => {
+            egui::epaint::Hsva::new(0.1 + 0.5 * (pulses.min(27) as f32 / 27.0), 0.8, 0.7, 1.0)
+        }
+        CarrierMode::WidePulse => egui::epaint::Hsva::new(0.05, 0.9, 0.7, 1.0),
+        CarrierMode::OnePulse => egui::epaint::Hsva::new(0.0, 0.9, 0.6, 1.0),
+    };
+    hsva.into()
+}
+
+fn ui_carrier_mode(
+    ui: &mut egui::Ui,
+    id_salt: impl std::hash::Hash,
+    mode: &mut CarrierMode,
+) -> Response {
+    let before = *mode;
+    let mut response = ui
+        .horizontal(|ui| {
+            ComboBox::from_id_salt(id_salt)
+                .selected_text(mode.label_text())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        mode,
+                        CarrierMode::Asynchronous,
+                        CarrierMode::Asynchronous.label_text(),
+                    );
+                    for pulses in SYNCHRONOUS_PRESETS {
+                        let m = CarrierMode::Synchronous(pulses);
+                        ui.selectable_value(mode, m, m.label_text());
+                    }
+                    ui.selectable_value(
+                        mode,
+                        CarrierMode::WidePulse,
+                        CarrierMode::WidePulse.label_text(),
+                    );
+                    ui.selectable_value(
+                        mode,
+                        CarrierMode::OnePulse,
+                        CarrierMode::OnePulse.label_text(),
+                    );
+                });
+            if let CarrierMode::Synchronous(pulses) = mode {
+                ui.add(DragValue::new(pulses).range(1..=99));
+            }
+        })
+        .response;
+    if *mode != before {
+        response.mark_changed();
+    }
+    response
+}
@@ -1,12 +1,43 @@
-use crate::func_edit::{EditableFuncMode, FuncEdit};
-use egui::{Button, ComboBox, DragValue, Label, Popup, RichText, vec2};
+use crate::{
+    func_edit::{
+        EXPR_HELPERS, EditableFuncMode, ExprContext, ExprError, Extrapolation, Fit, FitFamily,
+        FuncEdit, Interpolation, Piecewise,
+    },
+    state::DriveTrain,
+};
+use egui::{
+    Button, Color32, ComboBox, DragValue, FontId, Grid, Label, Popup, RichText, TextFormat,
+    text::LayoutJob, vec2,
+};
 use egui_extras::{Column, TableBuilder};
 
+/// 点と式を変換するときの設定と結果 (関数ごとに egui のメモリに置く)
+#[derive(Debug, Clone)]
+struct ConvertSettings {
+    bake_range: (f64, f64),
+    tolerance: f64,
+    family: FitFamily,
+    /// 最後に当てはめた結果
+    fit: Option<Result<Fit, String>>,
+}
+
+impl Default for ConvertSettings {
+    fn default() -> Self {
+        Self {
+            bake_range: (0.0, 120.0),
+            tolerance: 0.01,
+            family: FitFamily::default(),
+            fit: None,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct UiFunctionEdit<'a> {
     title: &'a str,
     axis_label: (&'a str, &'a str),
     percentage: (bool, bool),
+    context: Option<&'a ExprContext>,
 }
 
 impl<'a> UiFunctionEdit<'a> {
@@ -15,9 +46,16 @@ impl<'a> UiFunctionEdit<'a> {
             title,
             axis_label,
             percentage: (false, false),
+            context: None,
         }
     }
 
+    /// 式の誤りを調べるときの文脈 (定数や参照できる曲線)
+    pub fn context(mut self, context: &'a ExprContext) -> Self {
+        self.context = Some(context);
+        self
+    }
+
     #[expect(dead_code)]
     pub fn x_percentage(mut self, value: bool) -> Self {
         self.percentage.0 = value;
@@ -41,6 +79,7 @@ impl<'a> UiFunctionEdit<'a> {
                         .show_ui(&mut uis[1], |ui| {
                             ui.selectable_value(mode, EditableFuncMode::Points, "Points");
                             ui.selectable_value(mode, EditableFuncMode::Expression, "Expression");
+                            ui.selectable_value(mode, EditableFuncMode::Piecewise, "Piecewise");
                         });
                 });
             });
@@ -52,11 +91,117 @@ impl<'a> UiFunctionEdit<'a> {
                 EditableFuncMode::Expression => {
                     self.ui_expression(ui, func);
                 }
+                EditableFuncMode::Piecewise => {
+                    self.ui_piecewise(ui, func);
+                }
             }
         });
     }
 
+    fn ui_piecewise(&self, ui: &mut egui::Ui, func: &mut impl FuncEdit) {
+        if let Some(piecewise) = func.piecewise_mut() {
+            Self::ui_pieces(ui, piecewise);
+        }
+        ui.separator();
+
+        // 選んでいる区間の中身
+        if func.is_mode_points() {
+            self.ui_points(ui, func);
+        } else {
+            self.ui_expression(ui, func);
+        }
+        // 選んでいない区間の式も使えるようにする
+        func.update_expression();
+    }
+
+    /// 区間の一覧 (どこから始まるか・点か式か・前の区間とつなぐか)
+    fn ui_pieces(ui: &mut egui::Ui, piecewise: &mut Piecewise) {
+        let mut move_start = None;
+        let mut remove = None;
+        let removable = piecewise.pieces.len() > 1;
+
+        Grid::new("ui_function_edit_pieces")
+            .num_columns(5)
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("From");
+                ui.label("Type");
+                ui.label("Join");
+                ui.end_row();
+
+                piecewise.active = piecewise.active_index().unwrap_or_default();
+                let active = &mut piecewise.active;
+                for (i, piece) in piecewise.pieces.iter_mut().enumerate() {
+                    ui.radio_value(active, i, "")
+                        .on_hover_text("Edit this piece");
+                    if i == 0 {
+                        ui.label("-");
+                    } else {
+                        let mut start = piece.start;
+                        let drag = DragValue::new(&mut start).speed(0.1).max_decimals(1);
+                        if ui.add_sized(vec2(60.0, 20.0), drag).changed() {
+                            move_start = Some((i, start));
+                        }
+                    }
+                    let mode = &mut piece.func.mode;
+                    ComboBox::new(("ui_function_edit_piece_mode", i), "")
+                        .selected_text(format!("{mode:?}"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(mode, EditableFuncMode::Points, "Points");
+                            ui.selectable_value(mode, EditableFuncMode::Expression, "Expression");
+                        });
+                    if i == 0 {
+                        ui.label("");
+                    } else {
+                        ui.checkbox(&mut piece.continuous, "")
+                            .on_hover_text("Continue from the end of the previous piece");
+                    }
+                    if ui.add_enabled(removable, Button::new("-")).clicked() {
+                        remove = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+        if ui.button("+ Add piece").clicked() {
+            piecewise.push_expression();
+        }
+
+        if let Some((i, x)) = move_start {
+            piecewise.move_start(i, x);
+        }
+        if let Some(i) = remove {
+            piecewise.remove(i);
+        }
+    }
+
+    /// 点の間と範囲外の値の決め方
+    fn ui_interpolation(ui: &mut egui::Ui, func: &mut impl FuncEdit) {
+        let interpolation = func.interpolation();
+        ComboBox::new("ui_function_edit_interpolation", "Interpolation")
+            .selected_text(interpolation.label_text())
+            .show_ui(ui, |ui| {
+                for i in Interpolation::ALL {
+                    if ui
+                        .selectable_label(interpolation == i, i.label_text())
+                        .clicked()
+                    {
+                        func.set_interpolation(i);
+                    }
+                }
+            });
+        let extrapolation = func.extrapolation_mut();
+        ComboBox::new("ui_function_edit_extrapolation", "Outside points")
+            .selected_text(extrapolation.label_text())
+            .show_ui(ui, |ui| {
+                for e in Extrapolation::ALL {
+                    ui.selectable_value(extrapolation, e, e.label_text());
+                }
+            });
+    }
+
     fn ui_points(&self, ui: &mut egui::Ui, func: &mut impl FuncEdit) {
+        Self::ui_interpolation(ui, func);
+
         if let Some(points) = func.points() {
             let mut update_point = None;
             let mut remove_point = None;
@@ -154,25 +299,160 @@ impl<'a> UiFunctionEdit<'a> {
                 func.insert_point_by_index(i);
             }
         }
+
+        Self::ui_fit(ui, func);
+    }
+
+    /// 点に式を当てはめる
+    fn ui_fit(ui: &mut egui::Ui, func: &mut impl FuncEdit) {
+        let id = ui.id().with("ui_function_edit_convert");
+        let mut settings: ConvertSettings = ui.data_mut(|d| d.get_temp(id)).unwrap_or_default();
+        ui.horizontal(|ui| {
+            ComboBox::new("ui_function_edit_fit_family", "")
+                .selected_text(settings.family.label_text())
+                .show_ui(ui, |ui| {
+                    for family in FitFamily::all() {
+                        ui.selectable_value(&mut settings.family, family, family.label_text());
+                    }
+                });
+            if ui
+                .button("Fit expression")
+                .on_hover_text("Replace the points with an expression fitted to them")
+                .clicked()
+            {
+                settings.fit = Some(
+                    func.fit_expression(settings.family)
+                        .map_err(|e| e.to_string()),
+                );
+            }
+        });
+        if let Some(Err(err)) = &settings.fit {
+            ui.label(RichText::new(err).color(Color32::RED).small());
+        }
+        ui.data_mut(|d| d.insert_temp(id, settings));
+    }
+
+    /// 式を点にする
+    fn ui_bake(&self, ui: &mut egui::Ui, func: &mut impl FuncEdit, context: &ExprContext) {
+        let id = ui.id().with("ui_function_edit_convert");
+        let mut settings: ConvertSettings = ui.data_mut(|d| d.get_temp(id)).unwrap_or_default();
+
+        // 当てはめた式のままならそのずれを表示する
+        if let Some(Ok(fit)) = &settings.fit {
+            if func.expression_mut().is_some_and(|e| *e == fit.expression) {
+                ui.weak(format!(
+                    "Fit error: RMS {:.4}, max {:.4}",
+                    fit.rms_error, fit.max_error
+                ));
+            }
+        }
+
+        ui.horizontal(|ui| {
+            let valid = func.expression_err(context).is_none();
+            if ui
+                .add_enabled(valid, Button::new("Bake to points"))
+                .on_hover_text(
+                    "Replace the expression with points that follow it within the tolerance",
+                )
+                .clicked()
+            {
+                let (x0, x1) = settings.bake_range;
+                func.bake_to_points((x0, x1), settings.tolerance, context);
+                settings.fit = None;
+            }
+            let (x0, x1) = &mut settings.bake_range;
+            ui.add(DragValue::new(x0).speed(0.1).max_decimals(1));
+            ui.label("to");
+            ui.add(DragValue::new(x1).speed(0.1).max_decimals(1));
+            let mut tolerance = DragValue::new(&mut settings.tolerance)
+                .range(1e-6..=f64::INFINITY)
+                .speed(0.001)
+                .max_decimals(4)
+                .prefix("\u{b1}");
+            if self.percentage.1 {
+                tolerance = tolerance
+                    .custom_formatter(|x, _| format!("{:.2}", 100.0 * x))
+                    .custom_parser(|s| s.parse().ok().map(|v: f64| v / 100.0))
+                    .suffix("%");
+            }
+            ui.add(tolerance).on_hover_text("Tolerance");
+        });
+        ui.data_mut(|d| d.insert_temp(id, settings));
     }
 
-    #[expect(clippy::unused_self)]
     fn ui_expression(&self, ui: &mut egui::Ui, func: &mut impl FuncEdit) {
+        let default_context = ExprContext::default();
+        let context = self.context.unwrap_or(&default_context);
+
         if let Some(expression) = func.expression_mut() {
             ui.horizontal(|ui| {
-                ui.label("f(x)=");
+                ui.label("f(x)=")
+                    .on_hover_ui(|ui| Self::ui_expression_help(ui, context));
                 ui.text_edit_singleline(expression);
             });
+            let expression = expression.clone();
 
-            if let Some(err) = func.expression_err() {
-                ui.add(Label::new(
-                    RichText::new(format!("{err}"))
-                        .color(egui::Color32::RED)
-                        .small(),
-                ));
+            func.update_expression();
+            if let Some(err) = func.expression_err(context) {
+                ui.add(Label::new(Self::error_layout(ui, &expression, &err)));
             }
+            self.ui_bake(ui, func, context);
+        }
+    }
 
-            func.update_expression();
+    /// 式で使える名前の一覧
+    fn ui_expression_help(ui: &mut egui::Ui, context: &ExprContext) {
+        // 式で使える変数
+        for (name, description) in DriveTrain::VARIABLES {
+            ui.label(format!("{name}: {description}"));
+        }
+        for (name, value) in context.constants() {
+            ui.label(format!("{name}: {value}"));
+        }
+        let curves: Vec<&str> = context.curve_names().collect();
+        if !curves.is_empty() {
+            ui.separator();
+            ui.label(format!("Curves: {}(v)", curves.join("(v), ")));
+            ui.label("pitch(v), volume(v): this entry's own curves");
+        }
+        ui.separator();
+        for (usage, description) in EXPR_HELPERS {
+            ui.label(format!("{usage}: {description}"));
+        }
+    }
+
+    /// 誤りのある場所に印を付けた式と、誤りの内容
+    fn error_layout(ui: &egui::Ui, expression: &str, err: &ExprError) -> LayoutJob {
+        let mono = TextFormat::simple(
+            FontId::monospace(ui.style().text_styles[&egui::TextStyle::Body].size),
+            ui.visuals().text_color(),
+        );
+        let marked = TextFormat {
+            color: Color32::RED,
+            underline: egui::Stroke::new(1.5, Color32::RED),
+            ..mono.clone()
+        };
+
+        let mut job = LayoutJob::default();
+        if let Some(span) = err.span.clone().filter(|s| s.end <= expression.len()) {
+            job.append(&expression[..span.start], 0.0, mono.clone());
+            if span.is_empty() {
+                // 式の終わりに足りないものがある
+                job.append(" ", 0.0, marked);
+            } else {
+                job.append(&expression[span.clone()], 0.0, marked);
+            }
+            job.append(&expression[span.end..], 0.0, mono);
+            job.append("\n", 0.0, TextFormat::default());
         }
+        job.append(
+            &err.message,
+            0.0,
+            TextFormat::simple(
+                FontId::proportional(ui.style().text_styles[&egui::TextStyle::Small].size),
+                Color32::RED,
+            ),
+        );
+        job
     }
 }
@@ -1,6 +1,9 @@
 use crate::{
     app_action::AppAction,
-    ui::{UiAudioFiles, UiPerformanceWindow, UiPitchVolumeEdit, UiSettingWindow},
+    ui::{
+        UiAudioFiles, UiCarrierScheduleWindow, UiPerformanceWindow, UiPitchVolumeEdit,
+        UiSettingWindow,
+    },
 };
 use egui::{Button, MenuBar, Sides};
 
@@ -9,6 +12,8 @@ pub struct UiMenuBar {
     pub show_audio_files_panel: bool,
     pub show_point_edit_panel: bool,
     pub show_performance_window: bool,
+    #[serde(default)]
+    pub show_carrier_schedule_window: bool,
     pub show_setting_window: bool,
 }
 
@@ -18,6 +23,7 @@ impl Default for UiMenuBar {
             show_audio_files_panel: true,
             show_point_edit_panel: true,
             show_performance_window: false,
+            show_carrier_schedule_window: false,
             show_setting_window: false,
         }
     }
@@ -47,6 +53,10 @@ impl UiMenuBar {
                                 action.save_as();
                             }
                             ui.separator();
+                            if ui.button("Render WAV...").clicked() {
+                                action.render_wav();
+                            }
+                            ui.separator();
                             if ui.button("Quit").clicked() {
                                 action.quit();
                             }
@@ -74,6 +84,10 @@ impl UiMenuBar {
                         &mut self.show_performance_window,
                         UiPerformanceWindow::TITLE,
                     );
+                    ui.toggle_value(
+                        &mut self.show_carrier_schedule_window,
+                        UiCarrierScheduleWindow::TITLE,
+                    );
                 },
                 |ui| {
                     ui.toggle_value(&mut self.show_setting_window, UiSettingWindow::TITLE);
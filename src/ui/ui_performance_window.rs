@@ -1,10 +1,17 @@
 use super::{PlotAutoColor, UiPlotEdit};
 use crate::{
     app_action::AppAction,
-    state::TrainPerformance,
+    func_edit::ExprContext,
+    state::{
+        DriveTrain, ExprConstant, SoundType, State, StateTransition, TrackGeometry,
+        TrainPerformance,
+    },
     ui::{UiFunctionEdit, ui_plot_edit::PlotEditEntry},
 };
-use egui::{Atom, Button, Grid, ScrollArea, Sides, Slider, Window};
+use egui::{
+    Atom, Button, CollapsingHeader, Color32, DragValue, Grid, ScrollArea, Sides, Slider, TextEdit,
+    Window,
+};
 use egui_plot::{AxisHints, Plot};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -27,7 +34,7 @@ impl UiPerformanceWindow {
         ctx: &egui::Context,
         open: &mut bool,
         action: &mut AppAction,
-        train_performance: &mut TrainPerformance,
+        state: &mut State,
     ) {
         Window::new(Self::TITLE)
             .open(open)
@@ -35,9 +42,18 @@ impl UiPerformanceWindow {
             .min_size([325.0, 300.0])
             .show(ctx, |ui| {
                 ScrollArea::vertical().show(ui, |ui| {
-                    self.ui_plot(ui, action, train_performance);
+                    let context = state.expr_context(SoundType::Accel);
+                    let train_performance = &mut state.train_performance;
+                    self.ui_plot(ui, action, train_performance, &context);
                     ui.separator();
                     self.ui_form(ui, action, train_performance);
+                    Self::ui_transition(ui, &mut train_performance.transition, &context);
+                    ui.separator();
+                    self.ui_drive_train(ui, &mut state.drive_train);
+                    ui.separator();
+                    Self::ui_track_geometry(ui, &mut state.track_geometry);
+                    ui.separator();
+                    Self::ui_constants(ui, action, &mut state.constants);
 
                     ui.allocate_space(ui.available_size());
                 });
@@ -49,6 +65,7 @@ impl UiPerformanceWindow {
         ui: &mut egui::Ui,
         action: &mut AppAction,
         train_performance: &mut TrainPerformance,
+        context: &ExprContext,
     ) {
         let mut reset_viewport = false;
 
@@ -77,6 +94,7 @@ impl UiPerformanceWindow {
                     PlotItem::Drag,
                 ),
             ],
+            context,
             &mut self.selection,
             &mut None,
             || {
@@ -127,18 +145,18 @@ impl UiPerformanceWindow {
 
         match self.selection {
             Some(PlotItem::Acceleration) => {
-                UiFunctionEdit::new("Accleration", ("Speed", "Acceleration")).ui(
-                    ui,
-                    ui.id().with("acceleration"),
-                    &mut train_performance.acceleration,
-                );
+                UiFunctionEdit::new("Accleration", ("Speed", "Acceleration"))
+                    .context(context)
+                    .ui(
+                        ui,
+                        ui.id().with("acceleration"),
+                        &mut train_performance.acceleration,
+                    );
             }
             Some(PlotItem::Drag) => {
-                UiFunctionEdit::new("Drag", ("Speed", "Negative acceleration")).ui(
-                    ui,
-                    ui.id().with("drag"),
-                    &mut train_performance.drag,
-                );
+                UiFunctionEdit::new("Drag", ("Speed", "Negative acceleration"))
+                    .context(context)
+                    .ui(ui, ui.id().with("drag"), &mut train_performance.drag);
             }
             None => {}
         }
@@ -173,6 +191,168 @@ impl UiPerformanceWindow {
                         .drag_value_speed(0.01),
                 );
                 ui.end_row();
+
+                // 回生ブレーキが消える速度
+                let fade = &mut train_performance.regen_fade;
+                ui.label("Regen fade (km/h)");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        DragValue::new(&mut fade.start_speed)
+                            .range(0.0..=50.0)
+                            .speed(0.1)
+                            .max_decimals(1),
+                    );
+                    // 消え終わる速度は消え始める速度より低くする
+                    fade.end_speed = fade.end_speed.min(fade.start_speed);
+                    ui.label("to");
+                    ui.add(
+                        DragValue::new(&mut fade.end_speed)
+                            .range(0.0..=fade.start_speed)
+                            .speed(0.1)
+                            .max_decimals(1),
+                    );
+                });
+                ui.end_row();
+            });
+    }
+
+    fn ui_transition(ui: &mut egui::Ui, transition: &mut StateTransition, context: &ExprContext) {
+        CollapsingHeader::new("State transition")
+            .id_salt("train_performance_transition")
+            .show(ui, |ui| {
+                ui.weak("Fade-out, gap and fade-in when switching between power, brake and coast.");
+                UiFunctionEdit::new("Fade-out", ("Speed", "Seconds"))
+                    .context(context)
+                    .ui(
+                        ui,
+                        ui.id().with("transition_fade_out"),
+                        &mut transition.fade_out,
+                    );
+                UiFunctionEdit::new("Gap", ("Speed", "Seconds"))
+                    .context(context)
+                    .ui(ui, ui.id().with("transition_gap"), &mut transition.gap);
+                UiFunctionEdit::new("Fade-in", ("Speed", "Seconds"))
+                    .context(context)
+                    .ui(
+                        ui,
+                        ui.id().with("transition_fade_in"),
+                        &mut transition.fade_in,
+                    );
+            });
+    }
+
+    #[expect(clippy::unused_self)]
+    fn ui_drive_train(&self, ui: &mut egui::Ui, drive_train: &mut DriveTrain) {
+        ui.strong("Drive train");
+        Grid::new(ui.id().with("train_performance_drive_train"))
+            .num_columns(2)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Wheel diameter (m)");
+                ui.add(
+                    DragValue::new(&mut drive_train.wheel_diameter)
+                        .range(0.1..=2.0)
+                        .speed(0.001),
+                );
+                ui.end_row();
+
+                ui.label("Gear ratio");
+                ui.add(
+                    DragValue::new(&mut drive_train.gear_ratio)
+                        .range(0.1..=20.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+
+                ui.label("Motor poles");
+                let poles = ui.add(
+                    DragValue::new(&mut drive_train.pole_count)
+                        .range(2..=16)
+                        .speed(0.05),
+                );
+                if poles.changed() {
+                    // 極数は偶数
+                    drive_train.pole_count = drive_train.pole_count / 2 * 2;
+                }
+                ui.end_row();
+
+                ui.label("Slip (%)");
+                let mut slip = drive_train.slip * 100.0;
+                if ui
+                    .add(DragValue::new(&mut slip).range(0.0..=20.0).speed(0.01))
+                    .changed()
+                {
+                    drive_train.slip = slip / 100.0;
+                }
+                ui.end_row();
             });
     }
+
+    fn ui_track_geometry(ui: &mut egui::Ui, track: &mut TrackGeometry) {
+        ui.strong("Rail joints");
+        Grid::new(ui.id().with("train_performance_track_geometry"))
+            .num_columns(2)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Rail length (m)");
+                ui.add(
+                    DragValue::new(&mut track.rail_length)
+                        .range(1.0..=200.0)
+                        .speed(0.1),
+                );
+                ui.end_row();
+
+                ui.label("Bogie spacing (m)");
+                ui.add(
+                    DragValue::new(&mut track.bogie_spacing)
+                        .range(0.0..=30.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+
+                ui.label("Axle spacing (m)");
+                ui.add(
+                    DragValue::new(&mut track.axle_spacing)
+                        .range(0.0..=5.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+            });
+        if let Some(period) = track.rail_period(100.0) {
+            ui.weak(format!("One rail every {period:.2} s at 100 km/h"));
+        }
+    }
+
+    /// 式で使える定数
+    fn ui_constants(ui: &mut egui::Ui, action: &mut AppAction, constants: &mut Vec<ExprConstant>) {
+        ui.strong("Constants");
+        let mut remove = None;
+        Grid::new(ui.id().with("train_performance_constants"))
+            .num_columns(3)
+            .spacing([8.0, 4.0])
+            .show(ui, |ui| {
+                for (i, constant) in constants.iter_mut().enumerate() {
+                    let valid = constant.is_valid_name();
+                    let mut name = TextEdit::singleline(&mut constant.name).desired_width(100.0);
+                    if !valid {
+                        name = name.text_color(Color32::RED);
+                    }
+                    ui.add(name)
+                        .on_hover_text("Letters, digits and _; usable in expressions by name");
+                    ui.add(DragValue::new(&mut constant.value).speed(0.01));
+                    if ui.button("-").clicked() {
+                        remove = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(i) = remove {
+            constants.remove(i);
+            action.add_undo();
+        }
+        if ui.button("+ Add constant").clicked() {
+            constants.push(ExprConstant::default());
+            action.add_undo();
+        }
+    }
 }
@@ -1,38 +1,84 @@
 use crate::{
     app_action::AppAction,
-    state::{AudioEntry, AudioEntryId, AudioFunctionMode, AudioFunctions},
+    audio_player::{
+        AudioSource, LoopCandidate, LoopRegion, MAX_PITCH, MIN_PITCH, SeamReport, Smoothing,
+        analyze_seam, suggest_loop_points,
+    },
+    func_edit::{EditableFunc, ExprContext},
+    state::{
+        AudioEntry, AudioEntryId, AudioFunctionMode, AudioFunctions, EventTrigger, FileRegistory,
+        Harmonic, HarmonicSettings, LoadInput, LoadModulation, NoiseSettings, OneShotSettings,
+        PwmSettings, SoundType, StateFunctions, SynthSource, TriggerEvent,
+    },
     ui::{PlotAutoColor, UiFunctionEdit},
 };
-use egui::{Button, ComboBox, Label, vec2};
+use egui::{Button, CollapsingHeader, ComboBox, DragValue, Grid, Label, RichText, vec2};
+use std::collections::HashMap;
+
+const LOOP_CANDIDATE_COUNT: usize = 5;
 
 #[derive(Debug, Default)]
-pub struct UiPitchVolumeEdit;
+pub struct UiPitchVolumeEdit {
+    seam_reports: HashMap<AudioEntryId, (LoopRegion, SeamReport)>,
+    loop_candidates: Option<(AudioEntryId, Vec<LoopCandidate>)>,
+}
 
 impl UiPitchVolumeEdit {
     pub const TITLE: &str = "Point Edit";
 
-    #[expect(clippy::unused_self)]
+    #[expect(clippy::too_many_arguments)]
     pub fn ui(
-        &self,
+        &mut self,
         ui: &mut egui::Ui,
         action: &mut AppAction,
+        registory: &FileRegistory,
         entries: &mut [AudioEntry],
         selection: &mut Option<AudioEntryId>,
+        pitch_warnings: &[(AudioEntryId, f32)],
+        context: &ExprContext,
     ) {
         ui.strong(Self::TITLE);
-        Self::ui_legend(ui, action, entries, selection);
+        self.update_seam_reports(registory, entries);
+        Self::ui_legend(
+            ui,
+            action,
+            entries,
+            selection,
+            &self.seam_reports,
+            pitch_warnings,
+        );
 
         ui.separator();
 
-        if let Some(entry) = selection
+        // 搬送波モードの変化をきっかけにできる音声
+        let schedules: Vec<_> = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| !e.carrier_schedule().is_empty())
+            .map(|(i, e)| (i, e.name().clone()))
+            .collect();
+
+        if let Some((index, entry)) = selection
             .as_ref()
-            .and_then(|id| entries.iter_mut().find(|e| e.id() == id))
+            .and_then(|id| entries.iter_mut().enumerate().find(|(_, e)| e.id() == id))
         {
+            // 式の中の pitch などはこの音声の曲線を指す
+            let context = &context.with_owner(index);
+
+            if entry.synth().is_none() {
+                Self::ui_playback(ui, action, entry.one_shot_mut());
+            }
+            if let Some(settings) = entry.one_shot_mut() {
+                Self::ui_one_shot(ui, action, settings, &schedules);
+                return;
+            }
+
             ComboBox::from_label("Mode")
                 .selected_text(entry.mode().label_text())
                 .show_ui(ui, |ui| {
                     if ui
                         .selectable_label(entry.mode().is_common(), AudioFunctionMode::TEXT_COMMON)
+                        .on_hover_text("Same curves for accel and brake, silent while coasting")
                         .clicked()
                     {
                         *entry.mode_mut() = entry.mode().to_common();
@@ -48,36 +94,30 @@ impl UiPitchVolumeEdit {
                         *entry.mode_mut() = entry.mode().to_separate();
                         action.add_undo();
                     }
-                    if ui
-                        .selectable_label(
-                            entry.mode().is_accel_only(),
-                            AudioFunctionMode::TEXT_ACCEL_ONLY,
-                        )
-                        .clicked()
-                    {
-                        *entry.mode_mut() = entry.mode().to_accel_only();
-                        action.add_undo();
-                    }
-                    if ui
-                        .selectable_label(
-                            entry.mode().is_brake_only(),
-                            AudioFunctionMode::TEXT_BRAKE_ONLY,
-                        )
-                        .clicked()
-                    {
-                        *entry.mode_mut() = entry.mode().to_brake_only();
-                        action.add_undo();
-                    }
                 });
+            if let AudioFunctionMode::Separate(funcs) = entry.mode_mut() {
+                Self::ui_state_toggles(ui, action, funcs);
+            }
 
-            if let AudioFunctionMode::Common(funcs) = entry.mode_mut() {
-                Self::ui_funcs(ui, funcs, "");
+            let id = *entry.id();
+            if let Some(synth) = entry.synth_mut() {
+                Self::ui_synth(ui, synth, context);
             } else {
-                if let Some(funcs) = entry.mode_mut().accel_mut() {
-                    Self::ui_funcs(ui, funcs, "Accel ");
-                }
-                if let Some(funcs) = entry.mode_mut().brake_mut() {
-                    Self::ui_funcs(ui, funcs, "Brake ");
+                let source = registory.audio_source_by_id(&id);
+                self.ui_loop_region(ui, id, entry.loop_region_mut(), source.map(|s| s.as_ref()));
+            }
+            Self::ui_smoothing(ui, entry.smoothing_mut());
+            Self::ui_load_modulation(ui, action, entry.load_modulation_mut(), context);
+
+            match entry.mode_mut() {
+                AudioFunctionMode::Common(funcs) => Self::ui_funcs(ui, action, funcs, "", context),
+                AudioFunctionMode::Separate(funcs) => {
+                    for sound_type in SoundType::ALL {
+                        if let Some(funcs) = funcs.get_mut(sound_type) {
+                            let prefix = format!("{} ", sound_type.label_text());
+                            Self::ui_funcs(ui, action, funcs, &prefix, context);
+                        }
+                    }
                 }
             }
         }
@@ -88,6 +128,8 @@ impl UiPitchVolumeEdit {
         action: &mut AppAction,
         entries: &[AudioEntry],
         selection: &mut Option<AudioEntryId>,
+        seam_reports: &HashMap<AudioEntryId, (LoopRegion, SeamReport)>,
+        pitch_warnings: &[(AudioEntryId, f32)],
     ) {
         for (index, entry) in entries.iter().enumerate() {
             let color = PlotAutoColor::get_color(index);
@@ -102,6 +144,21 @@ impl UiPitchVolumeEdit {
                     (4.0, color),
                 );
 
+                if let Some((_, report)) = seam_reports
+                    .get(entry.id())
+                    .filter(|(_, report)| entry.one_shot().is_none() && report.clicks())
+                {
+                    ui.label(RichText::new("\u{26a0}").color(ui.visuals().warn_fg_color))
+                        .on_hover_text(seam_report_text(report));
+                }
+
+                if let Some((_, pitch)) = pitch_warnings.iter().find(|(id, _)| id == entry.id()) {
+                    ui.label(RichText::new("\u{26a0}").color(ui.visuals().error_fg_color))
+                        .on_hover_text(format!(
+                            "Pitch \u{d7}{pitch:.3} is out of range (\u{d7}{MIN_PITCH:.3} - \u{d7}{MAX_PITCH:.0}) and is clamped"
+                        ));
+                }
+
                 let checked = selection
                     .as_ref()
                     .map(|id| id == entry.id())
@@ -126,17 +183,558 @@ impl UiPitchVolumeEdit {
         }
     }
 
-    fn ui_funcs(ui: &mut egui::Ui, funcs: &mut AudioFunctions, title_prefix: &str) {
+    fn update_seam_reports(&mut self, registory: &FileRegistory, entries: &[AudioEntry]) {
+        // ループ区間が変わったものだけ解析し直す
+        self.seam_reports
+            .retain(|id, _| entries.iter().any(|e| e.id() == id));
+        for entry in entries {
+            let loop_region = *entry.loop_region();
+            if self
+                .seam_reports
+                .get(entry.id())
+                .is_some_and(|(r, _)| r == &loop_region)
+            {
+                continue;
+            }
+            if let Some(source) = registory.audio_source_by_id(entry.id()) {
+                self.seam_reports.insert(
+                    *entry.id(),
+                    (loop_region, analyze_seam(source, &loop_region)),
+                );
+            }
+        }
+    }
+
+    fn ui_loop_region(
+        &mut self,
+        ui: &mut egui::Ui,
+        id: AudioEntryId,
+        loop_region: &mut LoopRegion,
+        source: Option<&AudioSource>,
+    ) {
+        let len = source.map(|s| s.len).unwrap_or(usize::MAX);
+        let sample_rate = source.map(|s| s.sample_rate as f64);
+
+        CollapsingHeader::new("Loop")
+            .id_salt("ui_pitch_volume_edit_loop")
+            .show(ui, |ui| {
+                Grid::new("ui_pitch_volume_edit_loop_grid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Start");
+                        ui.add(drag_samples(&mut loop_region.start, len, sample_rate));
+                        ui.end_row();
+
+                        ui.label("End");
+                        ui.horizontal(|ui| {
+                            let mut to_file_end = loop_region.end.is_none();
+                            if ui.checkbox(&mut to_file_end, "File end").changed() {
+                                loop_region.end = (!to_file_end)
+                                    .then(|| source.map(|s| s.len).unwrap_or(loop_region.start));
+                            }
+                            if let Some(end) = &mut loop_region.end {
+                                ui.add(drag_samples(end, len, sample_rate));
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Crossfade");
+                        ui.add(drag_samples(&mut loop_region.crossfade, len, sample_rate));
+                        ui.end_row();
+                    });
+
+                if let Some((_, report)) = self.seam_reports.get(&id) {
+                    let text = seam_report_text(report);
+                    if report.clicks() {
+                        ui.colored_label(ui.visuals().warn_fg_color, format!("\u{26a0} {text}"));
+                    } else {
+                        ui.weak(text);
+                    }
+                }
+
+                let Some(source) = source else {
+                    return;
+                };
+                if ui.button("Suggest loop points").clicked() {
+                    self.loop_candidates = Some((
+                        id,
+                        suggest_loop_points(source, loop_region, LOOP_CANDIDATE_COUNT),
+                    ));
+                }
+                if let Some((_, candidates)) =
+                    self.loop_candidates.as_ref().filter(|(c, _)| c == &id)
+                {
+                    if candidates.is_empty() {
+                        ui.weak("No candidates found near the current loop points.");
+                    }
+                    let mut apply = None;
+                    for candidate in candidates {
+                        ui.horizontal(|ui| {
+                            let sr = source.sample_rate as f64;
+                            ui.label(format!(
+                                "{:.3}s - {:.3}s ({:.0}%)",
+                                candidate.start as f64 / sr,
+                                candidate.end as f64 / sr,
+                                100.0 * candidate.similarity.max(0.0)
+                            ));
+                            if ui.small_button("Apply").clicked() {
+                                apply = Some(*candidate);
+                            }
+                        });
+                    }
+                    if let Some(candidate) = apply {
+                        loop_region.start = candidate.start;
+                        loop_region.end = Some(candidate.end);
+                        self.loop_candidates = None;
+                    }
+                }
+            });
+    }
+
+    fn ui_smoothing(ui: &mut egui::Ui, smoothing: &mut Smoothing) {
+        CollapsingHeader::new("Smoothing")
+            .id_salt("ui_pitch_volume_edit_smoothing")
+            .show(ui, |ui| {
+                Grid::new("ui_pitch_volume_edit_smoothing_grid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Volume attack");
+                        ui.add(drag_seconds(&mut smoothing.attack));
+                        ui.end_row();
+
+                        ui.label("Volume release");
+                        ui.add(drag_seconds(&mut smoothing.release));
+                        ui.end_row();
+
+                        ui.label("Pitch glide");
+                        ui.add(drag_seconds(&mut smoothing.glide));
+                        ui.end_row();
+                    });
+            });
+    }
+
+    fn ui_load_modulation(
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        modulation: &mut Option<LoadModulation>,
+        context: &ExprContext,
+    ) {
+        CollapsingHeader::new("Load modulation")
+            .id_salt("ui_pitch_volume_edit_load_modulation")
+            .show(ui, |ui| {
+                let mut enabled = modulation.is_some();
+                if ui.checkbox(&mut enabled, "Scale volume by load").changed() {
+                    *modulation = enabled.then(LoadModulation::default);
+                    action.add_undo();
+                }
+                let Some(modulation) = modulation else {
+                    return;
+                };
+                ComboBox::from_label("Input")
+                    .selected_text(modulation.input.label_text())
+                    .show_ui(ui, |ui| {
+                        for input in [LoadInput::Effort, LoadInput::Acceleration] {
+                            ui.selectable_value(&mut modulation.input, input, input.label_text());
+                        }
+                    });
+                UiFunctionEdit::new("Gain", (modulation.input.axis_label(), "Gain"))
+                    .context(&context.without_proportional())
+                    .y_percentage(true)
+                    .ui(ui, "ui_pitch_volume_edit_load_gain", &mut modulation.gain);
+            });
+    }
+
+    fn ui_synth(ui: &mut egui::Ui, synth: &mut SynthSource, context: &ExprContext) {
+        CollapsingHeader::new(synth.label_text().to_owned())
+            .id_salt("ui_pitch_volume_edit_synth")
+            .default_open(true)
+            .show(ui, |ui| match synth {
+                SynthSource::Pwm(settings) => Self::ui_pwm(ui, settings, context),
+                SynthSource::Harmonic(settings) => Self::ui_harmonic(ui, settings, context),
+                SynthSource::Noise(settings) => Self::ui_noise(ui, settings, context),
+            });
+    }
+
+    fn ui_pwm(ui: &mut egui::Ui, settings: &mut PwmSettings, context: &ExprContext) {
+        // ピッチの関数は両方の周波数にかける倍率になる
+        UiFunctionEdit::new("Carrier", ("Speed", "Hz"))
+            .context(context)
+            .ui(
+                ui,
+                "ui_pitch_volume_edit_pwm_carrier",
+                &mut settings.carrier_frequency,
+            );
+        ui.add_space(10.0);
+        UiFunctionEdit::new("Signal", ("Speed", "Hz"))
+            .context(context)
+            .ui(
+                ui,
+                "ui_pitch_volume_edit_pwm_signal",
+                &mut settings.signal_frequency,
+            );
+        ui.add_space(10.0);
+        UiFunctionEdit::new("Modulation", ("Speed", "Index"))
+            .context(context)
+            .ui(
+                ui,
+                "ui_pitch_volume_edit_pwm_modulation",
+                &mut settings.modulation_index,
+            );
+    }
+
+    fn ui_harmonic(ui: &mut egui::Ui, settings: &mut HarmonicSettings, context: &ExprContext) {
+        ui.weak(format!(
+            "Fundamental (motor rotational frequency of the drive train) at 100 km/h: {:.1} Hz",
+            HarmonicSettings::fundamental_at(100.0, context)
+        ));
+
+        let mut remove = None;
+        for (i, harmonic) in settings.harmonics.iter_mut().enumerate() {
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
+                ui.label("Order");
+                ui.add(
+                    DragValue::new(&mut harmonic.order)
+                        .range(0.01..=200.0)
+                        .speed(0.01)
+                        .prefix("\u{d7}"),
+                );
+                if ui.small_button("- Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+            let title = format!("Harmonic {i}");
+            UiFunctionEdit::new(&title, ("Speed", "Amplitude"))
+                .context(context)
+                .y_percentage(true)
+                .ui(ui, ui.id().with(&title), &mut harmonic.amplitude);
+        }
+        if let Some(i) = remove {
+            settings.harmonics.remove(i);
+        }
+
+        ui.add_space(10.0);
+        if ui
+            .add_enabled(settings.can_add_harmonic(), Button::new("+ Add harmonic"))
+            .clicked()
+        {
+            let order = settings.harmonics.last().map_or(1.0, |h| h.order + 1.0);
+            settings.harmonics.push(Harmonic {
+                order,
+                amplitude: EditableFunc::with_points(vec![(0.0, 0.1)]).into(),
+            });
+        }
+    }
+
+    fn ui_noise(ui: &mut egui::Ui, settings: &mut NoiseSettings, context: &ExprContext) {
+        UiFunctionEdit::new("Rolling cutoff", ("Speed", "Hz"))
+            .context(context)
+            .ui(
+                ui,
+                "ui_pitch_volume_edit_noise_rolling_cutoff",
+                &mut settings.rolling_cutoff,
+            );
+        ui.add_space(10.0);
+        UiFunctionEdit::new("Rolling level", ("Speed", "Level"))
+            .context(context)
+            .y_percentage(true)
+            .ui(
+                ui,
+                "ui_pitch_volume_edit_noise_rolling_level",
+                &mut settings.rolling_level,
+            );
+        ui.add_space(10.0);
+        // 継ぎ目を踏む間隔は列車性能のウィンドウで決める
+        UiFunctionEdit::new("Joint cutoff", ("Speed", "Hz"))
+            .context(context)
+            .ui(
+                ui,
+                "ui_pitch_volume_edit_noise_joint_cutoff",
+                &mut settings.joint_cutoff,
+            );
+        ui.add_space(10.0);
+        UiFunctionEdit::new("Joint level", ("Speed", "Level"))
+            .context(context)
+            .y_percentage(true)
+            .ui(
+                ui,
+                "ui_pitch_volume_edit_noise_joint_level",
+                &mut settings.joint_level,
+            );
+        ui.horizontal(|ui| {
+            ui.label("Joint decay");
+            ui.add(
+                DragValue::new(&mut settings.joint_decay)
+                    .range(0.001..=1.0)
+                    .speed(0.001)
+                    .max_decimals(3)
+                    .suffix(" s"),
+            );
+        })
+        .response
+        .on_hover_text("Rail geometry is set in the train performance window");
+    }
+
+    /// ループ再生かワンショット再生かを選ぶ
+    fn ui_playback(
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        one_shot: &mut Option<OneShotSettings>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Playback");
+            if ui.selectable_label(one_shot.is_none(), "Loop").clicked() && one_shot.is_some() {
+                *one_shot = None;
+                action.add_undo();
+            }
+            if ui
+                .selectable_label(one_shot.is_some(), "One-shot")
+                .on_hover_text("Play once from the start on each trigger")
+                .clicked()
+                && one_shot.is_none()
+            {
+                *one_shot = Some(OneShotSettings {
+                    triggers: vec![EventTrigger::default()],
+                });
+                action.add_undo();
+            }
+        });
+    }
+
+    fn ui_one_shot(
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        settings: &mut OneShotSettings,
+        schedules: &[(usize, String)],
+    ) {
+        let mut remove = None;
+        for (i, trigger) in settings.triggers.iter_mut().enumerate() {
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
+                ComboBox::from_id_salt(("ui_pitch_volume_edit_trigger_event", i))
+                    .selected_text(trigger.event.label_text())
+                    .show_ui(ui, |ui| {
+                        for event in TriggerEvent::ALL {
+                            let selected = trigger.event.same_kind(event);
+                            let response = ui.selectable_label(selected, event.label_text());
+                            let response = if event == TriggerEvent::ModeChange {
+                                response
+                                    .on_hover_text("Uses the carrier schedule of the chosen entry")
+                            } else {
+                                response
+                            };
+                            if response.clicked() && !selected {
+                                trigger.event = event;
+                            }
+                        }
+                    });
+                if let Some(speed) = trigger.event.threshold_mut() {
+                    ui.add(
+                        DragValue::new(speed)
+                            .range(0.0..=500.0)
+                            .speed(0.1)
+                            .suffix(" km/h"),
+                    );
+                }
+                if trigger.event == TriggerEvent::ModeChange {
+                    Self::ui_schedule_entry(ui, i, trigger, schedules);
+                }
+                if ui.small_button("- Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+            Grid::new(("ui_pitch_volume_edit_trigger_grid", i))
+                .num_columns(2)
+                .show(ui, |ui| Self::ui_trigger(ui, trigger));
+        }
+        if let Some(i) = remove {
+            settings.triggers.remove(i);
+            action.add_undo();
+        }
+
+        ui.add_space(10.0);
+        if ui.button("+ Add trigger").clicked() {
+            settings.triggers.push(EventTrigger::default());
+            action.add_undo();
+        }
+    }
+
+    /// 搬送波モードの変化を調べる音声を選ぶ
+    fn ui_schedule_entry(
+        ui: &mut egui::Ui,
+        i: usize,
+        trigger: &mut EventTrigger,
+        schedules: &[(usize, String)],
+    ) {
+        let selected_text = trigger
+            .schedule_entry
+            .and_then(|index| schedules.iter().find(|(j, _)| *j == index))
+            .map_or("This entry", |(_, name)| name.as_str());
+        ComboBox::from_id_salt(("ui_pitch_volume_edit_trigger_schedule", i))
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut trigger.schedule_entry, None, "This entry");
+                for (index, name) in schedules {
+                    ui.selectable_value(&mut trigger.schedule_entry, Some(*index), name);
+                }
+            });
+    }
+
+    fn ui_trigger(ui: &mut egui::Ui, trigger: &mut EventTrigger) {
+        ui.label("Volume");
+        ui.add(
+            DragValue::new(&mut trigger.volume)
+                .range(0.0..=1.0)
+                .speed(0.005)
+                .custom_formatter(|x, _| format!("{:.1}", 100.0 * x))
+                .custom_parser(|s| s.parse().ok().map(|v: f64| v / 100.0))
+                .suffix("%"),
+        );
+        ui.end_row();
+
+        ui.label("Pitch");
+        ui.add(
+            DragValue::new(&mut trigger.pitch)
+                .range(MIN_PITCH..=MAX_PITCH)
+                .speed(0.005)
+                .prefix("\u{d7}"),
+        );
+        ui.end_row();
+
+        // 条件
+        ui.label("Speed");
+        ui.horizontal(|ui| {
+            ui.add(
+                DragValue::new(&mut trigger.min_speed)
+                    .range(0.0..=500.0)
+                    .speed(0.1)
+                    .suffix(" km/h"),
+            );
+            ui.label("-");
+            let mut no_limit = trigger.max_speed.is_none();
+            if ui.checkbox(&mut no_limit, "No limit").changed() {
+                trigger.max_speed = (!no_limit).then_some(trigger.min_speed.max(100.0));
+            }
+            if let Some(max_speed) = &mut trigger.max_speed {
+                ui.add(
+                    DragValue::new(max_speed)
+                        .range(trigger.min_speed..=500.0)
+                        .speed(0.1)
+                        .suffix(" km/h"),
+                );
+            }
+        });
+        ui.end_row();
+
+        ui.label("State");
+        ComboBox::from_id_salt(ui.id().with("state"))
+            .selected_text(trigger.sound_type.map_or("Any", SoundType::label_text))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut trigger.sound_type, None, "Any");
+                for sound_type in SoundType::ALL {
+                    ui.selectable_value(
+                        &mut trigger.sound_type,
+                        Some(sound_type),
+                        sound_type.label_text(),
+                    );
+                }
+            })
+            .response
+            .on_hover_text("Running state right after the event");
+        ui.end_row();
+    }
+
+    /// 鳴らす走行状態を選ぶ
+    fn ui_state_toggles(ui: &mut egui::Ui, action: &mut AppAction, funcs: &mut StateFunctions) {
+        ui.horizontal(|ui| {
+            for sound_type in SoundType::ALL {
+                let mut enabled = funcs.get(sound_type).is_some();
+                if ui.checkbox(&mut enabled, sound_type.label_text()).changed() {
+                    funcs.set_enabled(sound_type, enabled);
+                    action.add_undo();
+                }
+            }
+        });
+    }
+
+    fn ui_funcs(
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        funcs: &mut AudioFunctions,
+        title_prefix: &str,
+        context: &ExprContext,
+    ) {
         let title_pitch = format!("{title_prefix}Pitch");
         let title_volume = format!("{title_prefix}Volume");
-        UiFunctionEdit::new(&title_pitch, ("Speed", "Pitch")).ui(
-            ui,
-            ui.id().with(&title_pitch),
-            &mut funcs.pitch,
-        );
+        UiFunctionEdit::new(&title_pitch, ("Speed", "Pitch"))
+            .context(context)
+            .ui(ui, ui.id().with(&title_pitch), &mut funcs.pitch);
         ui.add_space(10.0);
         UiFunctionEdit::new(&title_volume, ("Speed", "Volume"))
+            .context(context)
             .y_percentage(true)
             .ui(ui, ui.id().with(&title_volume), &mut funcs.volume);
+
+        // ノッチによって変わる曲線
+        let mut notch_dependent = funcs.low_notch.is_some();
+        if ui
+            .checkbox(&mut notch_dependent, "Notch dependent")
+            .on_hover_text(
+                "The curves above are for the highest notch; notches in between are interpolated.",
+            )
+            .changed()
+        {
+            funcs.set_notch_dependent(notch_dependent);
+            action.add_undo();
+        }
+        let Some(layer) = funcs.low_notch.as_mut() else {
+            return;
+        };
+        let title_volume = format!("{title_prefix}Lowest Notch Volume");
+        UiFunctionEdit::new(&title_volume, ("Speed", "Volume"))
+            .context(context)
+            .y_percentage(true)
+            .ui(ui, ui.id().with(&title_volume), &mut layer.volume);
+        let mut pitch_dependent = layer.pitch.is_some();
+        if ui
+            .checkbox(&mut pitch_dependent, "Notch dependent pitch")
+            .changed()
+        {
+            layer.pitch = pitch_dependent.then(|| funcs.pitch.clone());
+            action.add_undo();
+        }
+        if let Some(pitch) = layer.pitch.as_mut() {
+            let title_pitch = format!("{title_prefix}Lowest Notch Pitch");
+            UiFunctionEdit::new(&title_pitch, ("Speed", "Pitch"))
+                .context(context)
+                .ui(ui, ui.id().with(&title_pitch), pitch);
+        }
+    }
+}
+
+// サンプルレートが分かれば秒で表示
+fn drag_samples(value: &mut usize, len: usize, sample_rate: Option<f64>) -> DragValue<'_> {
+    let mut drag = DragValue::new(value).range(0..=len).speed(10.0);
+    if let Some(sample_rate) = sample_rate {
+        drag = drag
+            .custom_formatter(move |v, _| format!("{:.3}", v / sample_rate))
+            .custom_parser(move |s| s.parse().ok().map(|v: f64| v * sample_rate))
+            .suffix(" s");
     }
+    drag
+}
+
+fn drag_seconds(value: &mut f32) -> DragValue<'_> {
+    DragValue::new(value)
+        .range(0.0..=10.0)
+        .speed(0.01)
+        .max_decimals(3)
+        .suffix(" s")
+}
+
+fn seam_report_text(report: &SeamReport) -> String {
+    format!(
+        "Seam: jump \u{d7}{:.1}, spectrum {:.1} dB",
+        report.amplitude_jump, report.spectral_mismatch
+    )
 }
@@ -1,12 +1,13 @@
-use super::{UiPlotEdit, aixs_hint_formatter_percentage};
+use super::{PlotAutoColor, UiPlotEdit, aixs_hint_formatter_percentage, plot_carrier_bands};
 use crate::{
     app_action::AppAction,
-    player_state::PlayerState,
-    state::{AudioEntryId, SoundType, State},
+    func_edit::ExprContext,
+    player_state::{PlayerState, notch_position},
+    state::{AudioEntryId, AudioFunctions, CarrierSchedule, SoundType, State},
     ui::ui_plot_edit::PlotEditEntry,
 };
 use egui::{Color32, Sides};
-use egui_plot::{AxisHints, Plot, VLine};
+use egui_plot::{AxisHints, Line, LineStyle, Plot, PlotPoints, VLine};
 use std::{collections::HashMap, hash::Hash, ops::RangeInclusive};
 
 #[derive(Debug)]
@@ -83,6 +84,71 @@ enum PlotVariant {
     Volume,
 }
 
+/// 選択中の音声について曲線の下に重ねて描くもの
+struct SelectedOverlay {
+    schedule: CarrierSchedule,
+    /// ノッチごとの曲線 (最高ノッチは編集できる曲線として描かれる)
+    notch_curves: Option<(AudioFunctions, u8)>,
+    color: Color32,
+    context: ExprContext,
+}
+
+impl SelectedOverlay {
+    fn new(state: &State, sound_type: SoundType, context: &ExprContext) -> Option<Self> {
+        let (index, entry) = state
+            .audio_entries
+            .iter()
+            .enumerate()
+            .find(|(_, e)| Some(e.id()) == state.selection.as_ref())?;
+        let performance = &state.train_performance;
+        let steps = match sound_type {
+            SoundType::Accel => Some(performance.power_steps),
+            SoundType::Brake => Some(performance.brake_steps),
+            SoundType::Coast | SoundType::Idle => None,
+        };
+        let notch_curves = entry
+            .funcs_by_type(sound_type)
+            .filter(|f| f.low_notch.is_some())
+            .zip(steps)
+            .map(|(f, steps)| (f.clone(), steps));
+        Some(Self {
+            schedule: entry.carrier_schedule().clone(),
+            notch_curves,
+            color: PlotAutoColor::get_color(index),
+            context: context.with_owner(index),
+        })
+    }
+
+    fn plot(&self, plot_ui: &mut egui_plot::PlotUi<'_>, variant: PlotVariant) {
+        plot_carrier_bands(plot_ui, &self.schedule);
+
+        let Some((funcs, steps)) = &self.notch_curves else {
+            return;
+        };
+        if variant == PlotVariant::Pitch
+            && funcs.low_notch.as_ref().is_none_or(|l| l.pitch.is_none())
+        {
+            return;
+        }
+        let width = plot_ui.response().rect.width().round() as usize;
+        for notch in 1..*steps {
+            let position = notch_position(notch, *steps);
+            let funcs = funcs.clone();
+            let context = self.context.clone();
+            let value_at = move |x| match variant {
+                PlotVariant::Pitch => funcs.pitch_at(x, position, &context),
+                PlotVariant::Volume => funcs.volume_at(x, position, &context),
+            };
+            plot_ui.line(
+                Line::new("", PlotPoints::from_explicit_callback(value_at, .., width))
+                    .color(self.color.linear_multiply(0.5))
+                    .style(LineStyle::dashed_dense())
+                    .allow_hover(false),
+            );
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UiPitchVolumePlots {
     ui_pitch_plot: UiPlotEdit<AudioEntryId>,
@@ -101,6 +167,22 @@ impl Default for UiPitchVolumePlots {
 }
 
 impl UiPitchVolumePlots {
+    fn ui_sound_type(ui: &mut egui::Ui, player_state: &mut PlayerState) {
+        let mut clicked = false;
+        for sound_type in SoundType::ALL {
+            clicked |= ui
+                .selectable_value(
+                    &mut player_state.sound_type,
+                    sound_type,
+                    sound_type.label_text(),
+                )
+                .clicked();
+        }
+        if clicked {
+            player_state.master_controller = 0;
+        }
+    }
+
     pub fn ui(
         &mut self,
         ui: &mut egui::Ui,
@@ -118,21 +200,14 @@ impl UiPitchVolumePlots {
 
         Sides::new().show(
             ui,
-            |ui| {
-                let mut clicked = ui
-                    .selectable_value(&mut player_state.sound_type, SoundType::Accel, "Accel")
-                    .clicked();
-                clicked |= ui
-                    .selectable_value(&mut player_state.sound_type, SoundType::Brake, "Brake")
-                    .clicked();
-                if clicked {
-                    player_state.master_controller = 0;
-                }
-            },
+            |ui| Self::ui_sound_type(ui, player_state),
             |ui| {
                 reset_viewport = ui.button("Reset viewport").clicked();
             },
         );
+        let context = state.expr_context(player_state.sound_type);
+        // 選択中の音声の搬送波モードの帯とノッチごとの曲線
+        let overlay = SelectedOverlay::new(state, player_state.sound_type, &context);
 
         self.ui_pitch_plot.ui(
             ui,
@@ -141,7 +216,9 @@ impl UiPitchVolumePlots {
                 &mut state.audio_entries,
                 &state.selection,
                 player_state.sound_type,
+                &context,
             ),
+            &context,
             &mut state.selection,
             &mut None,
             || {
@@ -155,6 +232,9 @@ impl UiPitchVolumePlots {
                     .height(height / 2.0 - 18.0)
             },
             |plot_ui: &mut egui_plot::PlotUi<'_>| {
+                if let Some(overlay) = &overlay {
+                    overlay.plot(plot_ui, PlotVariant::Pitch);
+                }
                 plot_ui.vline(
                     VLine::new("", player_state.speed)
                         .allow_hover(false)
@@ -172,7 +252,8 @@ impl UiPitchVolumePlots {
         self.ui_volume_plot.ui(
             ui,
             action,
-            &mut PlotEditEntry::volume(&mut state.audio_entries, player_state.sound_type),
+            &mut PlotEditEntry::volume(&mut state.audio_entries, player_state.sound_type, &context),
+            &context,
             &mut state.selection,
             &mut None,
             || {
@@ -190,6 +271,9 @@ impl UiPitchVolumePlots {
                     ])
             },
             |plot_ui: &mut egui_plot::PlotUi<'_>| {
+                if let Some(overlay) = &overlay {
+                    overlay.plot(plot_ui, PlotVariant::Volume);
+                }
                 plot_ui.vline(
                     VLine::new("", player_state.speed)
                         .allow_hover(false)
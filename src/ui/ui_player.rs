@@ -1,7 +1,7 @@
-use crate::{player_state::PlayerState, preference::Preference};
+use crate::{player_state::PlayerState, preference::Preference, state::DriveTrain};
 use egui::{
-    Color32, FontId, Frame, Label, Layout, RichText, Sense, Slider, TextFormat, UiBuilder,
-    text::LayoutJob,
+    Color32, FontId, Frame, Label, Layout, ProgressBar, RichText, Sense, Slider, TextFormat,
+    UiBuilder, text::LayoutJob,
 };
 use egui_extras::{Size, StripBuilder};
 
@@ -24,10 +24,12 @@ impl UiPlayer {
         ui: &mut egui::Ui,
         preference: &mut Preference,
         player_state: &mut PlayerState,
+        drive_train: &DriveTrain,
     ) {
         StripBuilder::new(ui)
             .size(Size::exact(100.0))
             .size(Size::exact(160.0))
+            .size(Size::exact(120.0))
             .size(Size::remainder())
             .size(Size::exact(200.0))
             .horizontal(|mut strip| {
@@ -60,7 +62,21 @@ impl UiPlayer {
                     });
                 });
 
-                strip.cell(|_| {});
+                strip.cell(|ui| {
+                    ui.with_layout(Layout::left_to_right(egui::Align::Center), |ui| {
+                        let frequency = drive_train.inverter_frequency(
+                            player_state.speed,
+                            player_state.active_sound_type(),
+                        );
+                        Self::ui_inverter_frequency(ui, frequency);
+                    });
+                });
+
+                strip.cell(|ui| {
+                    ui.with_layout(Layout::left_to_right(egui::Align::Center), |ui| {
+                        Self::ui_load(ui, player_state);
+                    });
+                });
 
                 strip.cell(|ui| {
                     // 音量スライダー
@@ -120,6 +136,45 @@ impl UiPlayer {
         ui.label(job);
     }
 
+    fn ui_inverter_frequency(ui: &mut egui::Ui, frequency: f64) {
+        let color = ui.ctx().style().visuals.weak_text_color();
+
+        let mut job = LayoutJob::default();
+
+        job.append(
+            &format!("{frequency:.1}"),
+            0.0,
+            TextFormat {
+                font_id: FontId::proportional(20.0),
+                color,
+                ..Default::default()
+            },
+        );
+
+        job.append(
+            "Hz",
+            6.0,
+            TextFormat {
+                font_id: FontId::proportional(14.0),
+                color,
+                ..Default::default()
+            },
+        );
+
+        ui.label(job).on_hover_text("Inverter output frequency");
+    }
+
+    /// 負荷 (音量の変調に使う値)
+    fn ui_load(ui: &mut egui::Ui, player_state: &PlayerState) {
+        let effort = player_state.effort_ratio() as f32;
+        ui.add(
+            ProgressBar::new(effort)
+                .desired_width(120.0)
+                .text(format!("Effort {:.0}%", effort * 100.0)),
+        );
+        ui.weak(format!("{:+.2} km/h/s", player_state.acceleration()));
+    }
+
     fn ui_volume(ui: &mut egui::Ui, volume: &mut f32) {
         ui.horizontal(|ui| {
             ui.label(if *volume <= 0.0 {
@@ -1,6 +1,6 @@
 use crate::{
     app_action::AppAction,
-    func_edit::FuncEdit,
+    func_edit::{ExprContext, FuncEdit},
     state::{AudioEntry, AudioEntryId, SelectionCursor, SoundType},
     ui::PlotAutoColor,
 };
@@ -9,7 +9,7 @@ use egui_plot::{
     GridInput, GridMark, Line, Plot, PlotPoint, PlotPoints, PlotTransform, Points, Polygon, VLine,
     log_grid_spacer,
 };
-use std::sync::Arc;
+use std::{ops::Bound, sync::Arc};
 
 const MARKER_RADIUS: f32 = 8.0;
 
@@ -19,6 +19,8 @@ pub struct PlotEditEntry<'a, T> {
     pub name: String,
     pub id: T,
     pub gradient_color: Option<Arc<dyn Fn(PlotPoint) -> Color32 + Send + Sync>>,
+    /// この曲線だけの式の文脈 (無ければ共通の文脈を使う)
+    pub context: Option<ExprContext>,
 }
 
 impl<'a, T> PlotEditEntry<'a, T> {
@@ -29,6 +31,7 @@ impl<'a, T> PlotEditEntry<'a, T> {
             name,
             id,
             gradient_color: None,
+            context: None,
         }
     }
 }
@@ -38,6 +41,7 @@ impl<'a> PlotEditEntry<'a, AudioEntryId> {
         audio_entries: &'a mut [AudioEntry],
         selection: &Option<AudioEntryId>,
         sound_type: SoundType,
+        context: &ExprContext,
     ) -> Vec<Self> {
         audio_entries
             .iter_mut()
@@ -48,9 +52,11 @@ impl<'a> PlotEditEntry<'a, AudioEntryId> {
                 let color = PlotAutoColor::get_color(i);
                 if let Some(funcs) = e.funcs_by_type_mut(sound_type) {
                     let volume_fn = funcs.volume.clone();
+                    let context = context.with_owner(i);
+                    let volume_context = context.clone();
                     let gradient_color = move |p: PlotPoint| {
                         // 音量依存で濃さを変更
-                        let v = volume_fn.value_at(p.x) as f32;
+                        let v = volume_fn.value_at(p.x, &volume_context) as f32;
                         let f = 0.1 + 0.9 * v;
                         color.linear_multiply(f)
                     };
@@ -60,6 +66,7 @@ impl<'a> PlotEditEntry<'a, AudioEntryId> {
                         name: format!("Pitch {i}"),
                         id,
                         gradient_color: (!is_selected).then_some(Arc::new(gradient_color)),
+                        context: Some(context),
                     })
                 } else {
                     None
@@ -68,7 +75,11 @@ impl<'a> PlotEditEntry<'a, AudioEntryId> {
             .collect()
     }
 
-    pub fn volume(audio_entries: &'a mut [AudioEntry], sound_type: SoundType) -> Vec<Self> {
+    pub fn volume(
+        audio_entries: &'a mut [AudioEntry],
+        sound_type: SoundType,
+        context: &ExprContext,
+    ) -> Vec<Self> {
         audio_entries
             .iter_mut()
             .enumerate()
@@ -81,6 +92,7 @@ impl<'a> PlotEditEntry<'a, AudioEntryId> {
                         name: format!("Volume {i}"),
                         id,
                         gradient_color: None,
+                        context: Some(context.with_owner(i)),
                     })
                 } else {
                     None
@@ -90,10 +102,21 @@ impl<'a> PlotEditEntry<'a, AudioEntryId> {
     }
 }
 
+/// ドラッグして動かすもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DragTarget {
+    Point,
+    /// ベジェのハンドル
+    Handle,
+    /// 区間の境目
+    PieceStart,
+}
+
 #[derive(Debug)]
 struct DraggingPoint<T> {
     id: T,
     index: usize,
+    target: DragTarget,
     start_pointer_plot_pos: PlotPoint,
     grab_offset_screen: egui::Vec2,
 }
@@ -102,6 +125,7 @@ impl<T> DraggingPoint<T> {
     fn new(
         id: T,
         index: usize,
+        target: DragTarget,
         pointer_plot_pos: PlotPoint,
         point_screen_pos: Pos2,
         pointer_screen_pos: Pos2,
@@ -109,6 +133,7 @@ impl<T> DraggingPoint<T> {
         Self {
             id,
             index,
+            target,
             start_pointer_plot_pos: pointer_plot_pos,
             grab_offset_screen: pointer_screen_pos - point_screen_pos,
         }
@@ -122,15 +147,16 @@ impl<T> DraggingPoint<T> {
         pointer_plot_pos: PlotPoint,
         grid_data: &PlotGridData,
     ) -> bool {
-        if func.is_mode_points() {
-            func.move_point_to(
-                self.index,
-                self.get_drag_point_pos(modifiers, transform, pointer_plot_pos, grid_data),
-            );
-            true
-        } else {
-            false
+        let pos = self.get_drag_point_pos(modifiers, transform, pointer_plot_pos, grid_data);
+        match self.target {
+            DragTarget::PieceStart => func.move_piece_start(self.index, pos.0),
+            _ if !func.is_mode_points() => return false,
+            DragTarget::Handle => func.move_tangent_handle(self.index, pos),
+            DragTarget::Point => {
+                func.move_point_to(self.index, pos);
+            }
         }
+        true
     }
 
     fn get_drag_point_pos(
@@ -185,6 +211,7 @@ impl<T> UiPlotEdit<T> {
         ui: &mut egui::Ui,
         action: &mut AppAction,
         entries: &'a mut [PlotEditEntry<'a, T>],
+        context: &ExprContext,
         selection: &mut Option<T>,
         cursor: &mut Option<&mut SelectionCursor>,
         init_plot: impl FnOnce() -> Plot<'static>,
@@ -209,7 +236,8 @@ impl<T> UiPlotEdit<T> {
         let plot_response = plot.show(ui, |plot_ui| {
             let mouse_down = self.check_mouse_down(plot_ui.response());
 
-            remove_point = self.plot_content(plot_ui, action, entries, selection, mouse_down);
+            remove_point =
+                self.plot_content(plot_ui, action, entries, context, selection, mouse_down);
 
             if let Some(cursor) = cursor.as_deref() {
                 Self::show_cursor(plot_ui, cursor);
@@ -239,8 +267,10 @@ impl<T> UiPlotEdit<T> {
             name: _,
             id,
             gradient_color: _,
+            context: entry_context,
         } in entries.iter_mut()
         {
+            let context = entry_context.as_ref().unwrap_or(context);
             // 点のドラッグ移動を反映
             if let (Some(dragging_point), Some(pointer)) =
                 (self.dragging_point.as_ref(), pointer_coordinate)
@@ -268,11 +298,13 @@ impl<T> UiPlotEdit<T> {
             if clicked && selection.as_ref() == Some(id) {
                 if let Some(pointer) = pointer_coordinate {
                     let dpos_dvalue = transform.dpos_dvalue_y();
-                    if (pointer.y - func.value_at(pointer.x)).abs()
+                    if (pointer.y - func.value_at(pointer.x, context)).abs()
                         < -(MARKER_RADIUS as f64) / dpos_dvalue
                     {
-                        func.split_segment(pointer.x);
-                        action.add_undo();
+                        // 式の区間には点を追加しない
+                        if func.split_segment(pointer.x).is_some() {
+                            action.add_undo();
+                        }
                         clicked = false;
                     }
                 }
@@ -287,6 +319,7 @@ impl<T> UiPlotEdit<T> {
                 name,
                 id,
                 gradient_color: _,
+                context: _,
             } in entries.iter_mut()
             {
                 if Some(Id::new(name)) == hovered_plot_item {
@@ -310,6 +343,7 @@ impl<T> UiPlotEdit<T> {
         plot_ui: &mut egui_plot::PlotUi<'a>,
         action: &mut AppAction,
         entries: &'a [PlotEditEntry<'b, T>],
+        context: &'a ExprContext,
         selection: &Option<T>,
         mouse_down: bool,
     ) -> Option<(T, usize)>
@@ -338,10 +372,21 @@ impl<T> UiPlotEdit<T> {
             name,
             id,
             gradient_color,
+            context: entry_context,
         } in entries
         {
+            let context = entry_context.as_ref().unwrap_or(context);
             let is_selected = selection.as_ref() == Some(id);
 
+            let (handles, piece_starts) = if is_selected {
+                (
+                    self.plot_handles(plot_ui, &**func, *color, id, mouse_down),
+                    self.plot_piece_starts(plot_ui, &**func, context, *color, id, mouse_down),
+                )
+            } else {
+                (None, None)
+            };
+
             let marker = if is_selected && func.is_mode_points() {
                 if let Some(points) = func.points() {
                     // マーカークリック・ドラッグ
@@ -355,6 +400,7 @@ impl<T> UiPlotEdit<T> {
                                     self.dragging_point = Some(DraggingPoint::new(
                                         id.clone(),
                                         j,
+                                        DragTarget::Point,
                                         pointer_plot_pos,
                                         screen_pos,
                                         pointer_screen_pos,
@@ -384,10 +430,14 @@ impl<T> UiPlotEdit<T> {
                 None
             };
 
-            // 線描画
+            // 線描画 (値を持たない範囲は描かない)
             let mut line = Line::new(
                 name.clone(),
-                PlotPoints::from_explicit_callback(|x| func.value_at(x), .., width_usize),
+                PlotPoints::from_explicit_callback(
+                    |x| func.value_at(x, context),
+                    defined_bounds(&**func),
+                    width_usize,
+                ),
             )
             .width(2.0)
             .highlight(is_selected);
@@ -399,6 +449,12 @@ impl<T> UiPlotEdit<T> {
             plot_ui.line(line);
 
             // マーカーは線より上に表示
+            if let Some(handles) = handles {
+                plot_ui.points(handles);
+            }
+            if let Some(piece_starts) = piece_starts {
+                plot_ui.points(piece_starts);
+            }
             if let Some(marker) = marker {
                 plot_ui.points(marker);
             }
@@ -407,6 +463,118 @@ impl<T> UiPlotEdit<T> {
         remove_point
     }
 
+    /// ベジェのハンドルの操作と、点とハンドルを結ぶ線の描画
+    fn plot_handles(
+        &mut self,
+        plot_ui: &mut egui_plot::PlotUi<'_>,
+        func: &dyn FuncEdit,
+        color: Color32,
+        id: &T,
+        mouse_down: bool,
+    ) -> Option<Points<'static>>
+    where
+        T: Clone,
+    {
+        let handles = func.tangent_handles();
+        let points = func.points()?;
+        if handles.is_empty() {
+            return None;
+        }
+        let pointer_screen_pos = plot_ui.response().interact_pointer_pos();
+        for (index, pos) in &handles {
+            let screen_pos = plot_ui.screen_from_plot(PlotPoint::new(pos.0, pos.1));
+            if let (Some(pointer_screen_pos), Some(pointer_plot_pos), true) =
+                (pointer_screen_pos, plot_ui.pointer_coordinate(), mouse_down)
+            {
+                if pointer_screen_pos.distance_sq(screen_pos) < MARKER_RADIUS.powi(2) {
+                    self.dragging_point = Some(DraggingPoint::new(
+                        id.clone(),
+                        *index,
+                        DragTarget::Handle,
+                        pointer_plot_pos,
+                        screen_pos,
+                        pointer_screen_pos,
+                    ));
+                }
+            }
+            let segment: Vec<[f64; 2]> = vec![points[*index].into(), (*pos).into()];
+            plot_ui.line(
+                Line::new("", segment)
+                    .color(color.linear_multiply(0.5))
+                    .width(1.0)
+                    .allow_hover(false),
+            );
+        }
+        let positions: Vec<[f64; 2]> = handles.iter().map(|(_, p)| [p.0, p.1]).collect();
+        Some(
+            Points::new("", positions)
+                .radius(MARKER_RADIUS / 2.0)
+                .shape(egui_plot::MarkerShape::Circle)
+                .color(color)
+                .filled(true)
+                .allow_hover(false),
+        )
+    }
+
+    /// 区間の境目の操作と描画
+    fn plot_piece_starts(
+        &mut self,
+        plot_ui: &mut egui_plot::PlotUi<'_>,
+        func: &dyn FuncEdit,
+        context: &ExprContext,
+        color: Color32,
+        id: &T,
+        mouse_down: bool,
+    ) -> Option<Points<'static>>
+    where
+        T: Clone,
+    {
+        let starts = func.piece_starts();
+        if starts.is_empty() {
+            return None;
+        }
+        let pointer_screen_pos = plot_ui.response().interact_pointer_pos();
+        let mut positions = vec![];
+        for (index, x) in starts {
+            plot_ui.vline(
+                VLine::new("", x)
+                    .color(color.linear_multiply(0.5))
+                    .width(1.0)
+                    .style(egui_plot::LineStyle::dashed_dense())
+                    .allow_hover(false),
+            );
+            // 境目の線の上の値にマーカーを置いてつかめるようにする
+            let y = func.value_at(x, context);
+            if !y.is_finite() {
+                continue;
+            }
+            let screen_pos = plot_ui.screen_from_plot(PlotPoint::new(x, y));
+            if let (Some(pointer_screen_pos), Some(pointer_plot_pos), true) =
+                (pointer_screen_pos, plot_ui.pointer_coordinate(), mouse_down)
+            {
+                if pointer_screen_pos.distance_sq(screen_pos) < MARKER_RADIUS.powi(2) {
+                    self.dragging_point = Some(DraggingPoint::new(
+                        id.clone(),
+                        index,
+                        DragTarget::PieceStart,
+                        pointer_plot_pos,
+                        screen_pos,
+                        pointer_screen_pos,
+                    ));
+                }
+            }
+            positions.push([x, y]);
+        }
+        Some(
+            Points::new("", positions)
+                .radius(MARKER_RADIUS / 2.0)
+                .shape(egui_plot::MarkerShape::Square)
+                .color(color)
+                .filled(true)
+                .allow_hover(false),
+        )
+    }
+
     fn show_cursor(plot_ui: &mut egui_plot::PlotUi<'_>, cursor: &SelectionCursor) {
         let selection_visuals = plot_ui.ctx().style().visuals.selection;
 
@@ -556,3 +724,11 @@ impl PlotGridData {
         cx.zip(cy)
     }
 }
+
+/// 関数が値を持つ x の範囲
+fn defined_bounds(func: &dyn FuncEdit) -> (Bound<f64>, Bound<f64>) {
+    func.defined_range()
+        .map_or((Bound::Unbounded, Bound::Unbounded), |r| {
+            (Bound::Included(r.0), Bound::Included(r.1))
+        })
+}
@@ -1,31 +1,309 @@
-use crate::{state::State, ui::ui_about_rev};
-use egui::{Layout, Window};
+use crate::{
+    app_action::AppAction,
+    audio_player::{AudioOutput, OutputDeviceInfo, ResamplerQuality},
+    preference::{DeviceSettings, Preference, RenderSettings},
+    state::{State, TrainPerformance},
+    ui::ui_about_rev,
+};
+use egui::{ComboBox, DragValue, Grid, Layout, Slider, Window};
+
+/// 設定画面で選べるバッファサイズ (フレーム数)
+const BUFFER_SIZES: [u32; 7] = [64, 128, 256, 512, 1024, 2048, 4096];
 
 #[derive(Debug, Default)]
-pub struct UiSettingWindow;
+pub struct UiSettingWindow {
+    /// 出力デバイスの一覧 (開くたびに列挙すると重いのでキャッシュ)
+    devices: Option<Vec<OutputDeviceInfo>>,
+}
 
 impl UiSettingWindow {
     pub const TITLE: &str = "Settings";
 
-    pub fn show(&self, ctx: &egui::Context, open: &mut bool, state: &mut State) {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        open: &mut bool,
+        action: &mut AppAction,
+        state: &mut State,
+        preference: &mut Preference,
+        audio_output: &AudioOutput,
+    ) {
         Window::new(Self::TITLE)
             .open(open)
             .default_size([300.0, 200.0])
             .min_size([200.0, 100.0])
             .show(ctx, |ui| {
-                self.ui(ui, state);
+                self.ui(ui, action, state, preference, audio_output);
             });
     }
 
-    #[expect(clippy::unused_self)]
-    #[expect(unused_variables)]
     #[expect(clippy::needless_pass_by_ref_mut)]
-    fn ui(&self, ui: &mut egui::Ui, state: &mut State) {
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        state: &mut State,
+        preference: &mut Preference,
+        audio_output: &AudioOutput,
+    ) {
         egui::widgets::global_theme_preference_buttons(ui);
 
+        ui.separator();
+        ui.strong("Audio Output");
+        self.ui_audio_output(ui, action, preference, audio_output);
+        ui.horizontal(|ui| {
+            ui.label("Resampler");
+            ui_resampler_quality(
+                ui,
+                "playback_resampler_quality",
+                &mut preference.resampler_quality,
+            );
+        });
+
+        ui.separator();
+        ui.strong("Render");
+        Self::ui_render(ui, &mut preference.render, &state.train_performance);
+
         ui.with_layout(Layout::bottom_up(egui::Align::Min), |ui| {
             ui_about_rev(ui);
             ui.separator();
         });
     }
+
+    fn ui_audio_output(
+        &mut self,
+        ui: &mut egui::Ui,
+        action: &mut AppAction,
+        preference: &mut Preference,
+        audio_output: &AudioOutput,
+    ) {
+        let config = audio_output.config();
+        let buffer_size = audio_output
+            .buffer_size()
+            .map(|size| format!(", {size} frames"))
+            .unwrap_or_default();
+        ui.label(format!(
+            "{} ({} Hz, {} ch{buffer_size})",
+            audio_output.name(),
+            config.sample_rate,
+            config.channels
+        ));
+
+        let devices = self.devices.get_or_insert_with(|| {
+            AudioOutput::devices().unwrap_or_else(|err| {
+                log::warn!("Failed to enumerate output devices: {err}");
+                vec![]
+            })
+        });
+        let settings = &mut preference.audio_device;
+        let default_name = AudioOutput::default_device_name();
+        let info = devices
+            .iter()
+            .find(|d| Some(&d.name) == settings.name.as_ref().or(default_name.as_ref()));
+
+        let mut changed = false;
+        let mut use_null = false;
+        Grid::new(ui.id().with("audio_output_settings"))
+            .num_columns(2)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Device");
+                let selected_text = if settings.use_null {
+                    "Null output"
+                } else {
+                    settings.name.as_deref().unwrap_or("Default")
+                };
+                ComboBox::from_id_salt("audio_output_device")
+                    .selected_text(selected_text)
+                    .truncate()
+                    .show_ui(ui, |ui| {
+                        let is_default = !settings.use_null && settings.name.is_none();
+                        if ui.selectable_label(is_default, "Default").clicked() {
+                            settings.name = None;
+                            changed = true;
+                        }
+                        for device in devices.iter() {
+                            let selected =
+                                !settings.use_null && settings.name.as_ref() == Some(&device.name);
+                            if ui.selectable_label(selected, &device.name).clicked() {
+                                settings.name = Some(device.name.clone());
+                                changed = true;
+                            }
+                        }
+                        if ui
+                            .selectable_label(settings.use_null, "Null output")
+                            .clicked()
+                        {
+                            use_null = true;
+                        }
+                    });
+                ui.end_row();
+
+                changed |= Self::ui_stream_settings(ui, settings, info);
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button("Reconnect").clicked() {
+                changed = true;
+            }
+            if ui.button("Refresh devices").clicked() {
+                self.devices = None;
+            }
+        });
+
+        if use_null {
+            action.use_null_output();
+        } else if changed {
+            action.connect_audio_device();
+        }
+
+        if preference.audio_device.use_null {
+            let null_output = &mut preference.null_output;
+            let before = *null_output;
+            Grid::new(ui.id().with("null_output_settings"))
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("Null output rate (Hz)");
+                    ui.add(DragValue::new(&mut null_output.sample_rate).range(8000..=192_000));
+                    ui.end_row();
+
+                    ui.label("Null output channels");
+                    ui.add(Slider::new(&mut null_output.channels, 1..=2));
+                    ui.end_row();
+                });
+            if *null_output != before {
+                action.use_null_output();
+            }
+        }
+    }
+
+    fn ui_stream_settings(
+        ui: &mut egui::Ui,
+        settings: &mut DeviceSettings,
+        info: Option<&OutputDeviceInfo>,
+    ) -> bool {
+        let mut changed = false;
+        ui.label("Sample rate");
+        ComboBox::from_id_salt("audio_output_sample_rate")
+            .selected_text(
+                settings
+                    .sample_rate
+                    .map_or("Device default".to_owned(), |r| format!("{r} Hz")),
+            )
+            .show_ui(ui, |ui| {
+                changed |= ui
+                    .selectable_value(&mut settings.sample_rate, None, "Device default")
+                    .clicked();
+                for &rate in info.map(|i| i.sample_rates.as_slice()).unwrap_or_default() {
+                    changed |= ui
+                        .selectable_value(
+                            &mut settings.sample_rate,
+                            Some(rate),
+                            format!("{rate} Hz"),
+                        )
+                        .clicked();
+                }
+            });
+        ui.end_row();
+
+        ui.label("Buffer size");
+        ComboBox::from_id_salt("audio_output_buffer_size")
+            .selected_text(
+                settings
+                    .buffer_size
+                    .map_or("Device default".to_owned(), |s| format!("{s} frames")),
+            )
+            .show_ui(ui, |ui| {
+                changed |= ui
+                    .selectable_value(&mut settings.buffer_size, None, "Device default")
+                    .clicked();
+                let (min, max) = info
+                    .and_then(|i| i.buffer_size_range)
+                    .unwrap_or((0, u32::MAX));
+                for size in BUFFER_SIZES.into_iter().filter(|s| (min..=max).contains(s)) {
+                    changed |= ui
+                        .selectable_value(
+                            &mut settings.buffer_size,
+                            Some(size),
+                            format!("{size} frames"),
+                        )
+                        .clicked();
+                }
+            });
+        ui.end_row();
+
+        changed
+    }
+
+    fn ui_render(ui: &mut egui::Ui, settings: &mut RenderSettings, performance: &TrainPerformance) {
+        Grid::new(ui.id().with("render_settings"))
+            .num_columns(2)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Sample rate (Hz)");
+                ui.add(DragValue::new(&mut settings.sample_rate).range(8000..=192_000));
+                ui.end_row();
+
+                ui.label("Channels");
+                ui.add(Slider::new(&mut settings.channels, 1..=2));
+                ui.end_row();
+
+                ui.label("Power notch");
+                ui.add(Slider::new(
+                    &mut settings.power_notch,
+                    1..=performance.power_steps.max(1),
+                ));
+                ui.end_row();
+
+                ui.label("Target speed (km/h)");
+                ui.add(
+                    DragValue::new(&mut settings.target_speed)
+                        .range(1.0..=400.0)
+                        .speed(0.1),
+                );
+                ui.end_row();
+
+                ui.label("Coast time (s)");
+                ui.add(
+                    DragValue::new(&mut settings.coast_seconds)
+                        .range(0.0..=60.0)
+                        .speed(0.1),
+                );
+                ui.end_row();
+
+                ui.label("Brake notch");
+                ui.add(Slider::new(
+                    &mut settings.brake_notch,
+                    1..=performance.brake_steps.max(1),
+                ));
+                ui.end_row();
+
+                ui.label("Resampler");
+                ui_resampler_quality(
+                    ui,
+                    "render_resampler_quality",
+                    &mut settings.resampler_quality,
+                );
+                ui.end_row();
+
+                ui.label("Tail time (s)");
+                ui.add(
+                    DragValue::new(&mut settings.tail_seconds)
+                        .range(0.0..=10.0)
+                        .speed(0.1),
+                );
+                ui.end_row();
+            });
+    }
+}
+
+fn ui_resampler_quality(ui: &mut egui::Ui, id_salt: &str, quality: &mut ResamplerQuality) {
+    ComboBox::from_id_salt(id_salt)
+        .selected_text(quality.label_text())
+        .show_ui(ui, |ui| {
+            for q in ResamplerQuality::ALL {
+                ui.selectable_value(quality, q, q.label_text());
+            }
+        });
 }